@@ -0,0 +1,54 @@
+# -*- coding: utf-8 -*-
+
+import os
+import sys
+import timeit
+import tempfile
+
+import scandir_rs as scandir
+
+THREADS = [1, 2, 4, 8, 0]
+
+
+def CreateTestTree(dirName: str, dircnt: int, depth: int, filecnt: int):
+    totalDirs = sum(dircnt ** d for d in range(1, depth + 1))
+    print(f"Creating test tree with {totalDirs} directories and {totalDirs * filecnt} files...")
+    dirNames = [dirName]
+    for _ in range(depth):
+        subDirNames = []
+        for parentName in dirNames:
+            for dn in range(dircnt):
+                subDirName = f"{parentName}/dir{dn}"
+                os.makedirs(subDirName)
+                for fn in range(filecnt):
+                    open(f"{subDirName}/file{fn}.txt", "wb").close()
+                subDirNames.append(subDirName)
+        dirNames = subDirNames
+
+
+def ThreadsName(threads: int) -> str:
+    if threads == 0:
+        return "default"
+    if threads == 1:
+        return "serial"
+    return str(threads)
+
+
+def RunBenchmarks(dirName: str, number: int = 3):
+    print(f"Benchmarking directory: {dirName}")
+    print(scandir.Count(dirName).collect())
+    for cls, args in (("Count", ""), ("Count", ", return_type=scandir.ReturnType.Ext"),
+                      ("Count", ", histograms=True, resolve_owners=True"), ("Walk", ""), ("Scandir", ""), ("Scandir", ", return_type=scandir.ReturnType.Ext")):
+        for threads in THREADS:
+            print(f"scandir.{cls}(threads={ThreadsName(threads)}{args}).collect(): %.3f" % timeit.timeit(f"""
+scandir.{cls}('{dirName}', threads={threads}{args}).collect()
+    """, setup="import scandir_rs as scandir", number=number))
+
+
+if __name__ == "__main__":
+    if len(sys.argv) > 1:
+        RunBenchmarks(sys.argv[1])
+    else:
+        with tempfile.TemporaryDirectory(prefix="scandir_rs_bench_") as tempDir:
+            CreateTestTree(tempDir, 10, 3, 20)
+            RunBenchmarks(tempDir)
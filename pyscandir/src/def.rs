@@ -1,13 +1,23 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::BufWriter;
+
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
+#[cfg(feature = "arrow")]
+use pyo3::types::PyBytes;
 use pyo3::types::PyDict;
 
 use scandir;
+#[cfg(feature = "arrow")]
+use scandir::arrow::{ArrowWriter, RecordBatchWriter, StreamWriter};
 
 #[pyclass]
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub enum ReturnType {
     Base,
     Ext,
+    Fast,
 }
 
 impl ReturnType {
@@ -15,10 +25,258 @@ impl ReturnType {
         match &self {
             ReturnType::Base => scandir::ReturnType::Base,
             ReturnType::Ext => scandir::ReturnType::Ext,
+            ReturnType::Fast => scandir::ReturnType::Fast,
+        }
+    }
+}
+
+#[pyclass]
+#[derive(Debug, Clone, PartialEq)]
+pub enum SortBy {
+    Name,
+    NameCaseInsensitive,
+    NameNatural,
+    Size,
+    Mtime,
+}
+
+impl SortBy {
+    pub fn from_object(&self) -> scandir::SortBy {
+        match &self {
+            SortBy::Name => scandir::SortBy::Name,
+            SortBy::NameCaseInsensitive => scandir::SortBy::NameCaseInsensitive,
+            SortBy::NameNatural => scandir::SortBy::NameNatural,
+            SortBy::Size => scandir::SortBy::Size,
+            SortBy::Mtime => scandir::SortBy::Mtime,
+        }
+    }
+}
+
+#[pyclass]
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraversalOrder {
+    Default,
+    DepthFirst,
+    BreadthFirst,
+}
+
+impl TraversalOrder {
+    pub fn from_object(&self) -> scandir::TraversalOrder {
+        match &self {
+            TraversalOrder::Default => scandir::TraversalOrder::Default,
+            TraversalOrder::DepthFirst => scandir::TraversalOrder::DepthFirst,
+            TraversalOrder::BreadthFirst => scandir::TraversalOrder::BreadthFirst,
+        }
+    }
+}
+
+#[pyclass]
+#[derive(Debug, Clone, PartialEq)]
+pub enum OversizedAction {
+    Truncate,
+    Skip,
+}
+
+impl OversizedAction {
+    pub fn from_object(&self) -> scandir::OversizedAction {
+        match &self {
+            OversizedAction::Truncate => scandir::OversizedAction::Truncate,
+            OversizedAction::Skip => scandir::OversizedAction::Skip,
+        }
+    }
+}
+
+#[pyclass]
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    MaxFileCnt,
+    MaxEntries,
+    MaxDirs,
+    MaxTotalBytes,
+    Timeout,
+}
+
+impl StopReason {
+    pub fn new(stop_reason: &scandir::StopReason) -> Self {
+        match stop_reason {
+            scandir::StopReason::MaxFileCnt => StopReason::MaxFileCnt,
+            scandir::StopReason::MaxEntries => StopReason::MaxEntries,
+            scandir::StopReason::MaxDirs => StopReason::MaxDirs,
+            scandir::StopReason::MaxTotalBytes => StopReason::MaxTotalBytes,
+            scandir::StopReason::Timeout => StopReason::Timeout,
+        }
+    }
+}
+
+#[pyclass]
+#[derive(Debug, Clone, PartialEq)]
+pub enum TopNKey {
+    Size,
+    Mtime,
+    Atime,
+    Blocks,
+}
+
+impl TopNKey {
+    pub fn from_object(&self) -> scandir::TopNKey {
+        match &self {
+            TopNKey::Size => scandir::TopNKey::Size,
+            TopNKey::Mtime => scandir::TopNKey::Mtime,
+            TopNKey::Atime => scandir::TopNKey::Atime,
+            TopNKey::Blocks => scandir::TopNKey::Blocks,
+        }
+    }
+}
+
+#[pyclass]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportFormat {
+    Ndjson,
+    Csv,
+    MessagePack,
+}
+
+impl ExportFormat {
+    pub fn from_object(&self) -> scandir::ExportFormat {
+        match &self {
+            ExportFormat::Ndjson => scandir::ExportFormat::Ndjson,
+            ExportFormat::Csv => scandir::ExportFormat::Csv,
+            ExportFormat::MessagePack => scandir::ExportFormat::MessagePack,
+        }
+    }
+}
+
+/// Columns with names `columns`. The default columns depend on the return type.
+pub fn export_columns(
+    columns: Option<Vec<String>>,
+    return_type: &scandir::ReturnType,
+) -> PyResult<Vec<scandir::ExportColumn>> {
+    match columns {
+        Some(columns) => columns
+            .iter()
+            .map(|name| {
+                scandir::ExportColumn::from_name(name)
+                    .ok_or_else(|| PyValueError::new_err(format!("Unknown column {}", name)))
+            })
+            .collect::<PyResult<Vec<_>>>(),
+        None => Ok(match return_type {
+            scandir::ReturnType::Ext => scandir::ExportColumn::ALL.to_vec(),
+            _ => scandir::ExportColumn::BASE.to_vec(),
+        }),
+    }
+}
+
+/// Create writer for exporting results to file `file_path`. The default columns depend
+/// on the return type.
+pub fn create_export_writer(
+    file_path: &str,
+    format: Option<ExportFormat>,
+    columns: Option<Vec<String>>,
+    return_type: &scandir::ReturnType,
+) -> PyResult<scandir::ExportWriter<BufWriter<File>>> {
+    let columns = export_columns(columns, return_type)?;
+    let file = File::create(file_path)?;
+    Ok(scandir::ExportWriter::new(
+        BufWriter::new(file),
+        format.unwrap_or(ExportFormat::Ndjson).from_object(),
+    )
+    .columns(columns))
+}
+
+/// Create writer for record batches in the Arrow IPC streaming format
+#[cfg(feature = "arrow")]
+pub fn create_ipc_writer(
+    columns: Option<Vec<String>>,
+    batch_size: Option<usize>,
+    return_type: &scandir::ReturnType,
+) -> PyResult<RecordBatchWriter<StreamWriter<Vec<u8>>>> {
+    let columns = export_columns(columns, return_type)?;
+    Ok(RecordBatchWriter::ipc(Vec::new(), columns)?
+        .batch_size(batch_size.unwrap_or(scandir::arrow::DEFAULT_BATCH_SIZE)))
+}
+
+/// Create writer for Parquet file `file_path`
+#[cfg(feature = "arrow")]
+pub fn create_parquet_writer(
+    file_path: &str,
+    columns: Option<Vec<String>>,
+    batch_size: Option<usize>,
+    return_type: &scandir::ReturnType,
+) -> PyResult<RecordBatchWriter<ArrowWriter<BufWriter<File>>>> {
+    let columns = export_columns(columns, return_type)?;
+    let file = File::create(file_path)?;
+    Ok(RecordBatchWriter::parquet(BufWriter::new(file), columns)?
+        .batch_size(batch_size.unwrap_or(scandir::arrow::DEFAULT_BATCH_SIZE)))
+}
+
+/// Read Arrow IPC stream `data` into a `pyarrow.Table`. The columns are copied once as
+/// whole buffers, so no Python objects are created for the entries.
+#[cfg(feature = "arrow")]
+pub fn ipc_to_table(py: Python, data: &[u8]) -> PyResult<PyObject> {
+    let ipc = py.import("pyarrow.ipc")?;
+    let reader = ipc.call_method1("open_stream", (PyBytes::new(py, data),))?;
+    Ok(reader.call_method0("read_all")?.to_object(py))
+}
+
+#[pyclass]
+#[derive(Debug, Clone)]
+pub struct LinkTarget {
+    #[pyo3(get)]
+    pub path: String,
+    #[pyo3(get)]
+    pub is_dangling: bool,
+    #[pyo3(get)]
+    pub is_dir: Option<bool>,
+    #[pyo3(get)]
+    pub is_file: Option<bool>,
+    #[pyo3(get)]
+    pub st_ctime: Option<f64>,
+    #[pyo3(get)]
+    pub st_mtime: Option<f64>,
+    #[pyo3(get)]
+    pub st_atime: Option<f64>,
+    #[pyo3(get)]
+    pub st_size: Option<u64>,
+}
+
+impl LinkTarget {
+    pub fn new(link_target: &scandir::LinkTarget) -> Self {
+        let metadata = link_target.metadata.as_ref();
+        LinkTarget {
+            path: link_target.path.clone(),
+            is_dangling: link_target.is_dangling,
+            is_dir: metadata.map(|m| m.is_dir),
+            is_file: metadata.map(|m| m.is_file),
+            st_ctime: metadata.map(|m| m.st_ctime),
+            st_mtime: metadata.map(|m| m.st_mtime),
+            st_atime: metadata.map(|m| m.st_atime),
+            st_size: metadata.map(|m| m.st_size),
         }
     }
 }
 
+#[pymethods]
+impl LinkTarget {
+    pub fn as_dict(&self, py: Python) -> PyResult<PyObject> {
+        let pydict = PyDict::new(py);
+        pydict.set_item("path".to_object(py), self.path.clone())?;
+        pydict.set_item("is_dangling".to_object(py), self.is_dangling)?;
+        if self.st_size.is_some() {
+            pydict.set_item("is_dir".to_object(py), self.is_dir)?;
+            pydict.set_item("is_file".to_object(py), self.is_file)?;
+            pydict.set_item("st_ctime".to_object(py), self.st_ctime)?;
+            pydict.set_item("st_mtime".to_object(py), self.st_mtime)?;
+            pydict.set_item("st_atime".to_object(py), self.st_atime)?;
+            pydict.set_item("st_size".to_object(py), self.st_size)?;
+        }
+        Ok(pydict.to_object(py))
+    }
+
+    fn __str__(&self) -> PyResult<String> {
+        Ok(format!("{:?}", self))
+    }
+}
+
 #[pyclass]
 #[derive(Debug, Clone)]
 pub struct DirEntry {
@@ -36,6 +294,8 @@ pub struct DirEntry {
     pub st_mtime: f64,
     #[pyo3(get)]
     pub st_atime: f64,
+    #[pyo3(get)]
+    pub link_target: Option<LinkTarget>,
 }
 
 impl DirEntry {
@@ -48,6 +308,7 @@ impl DirEntry {
             st_ctime: entry.st_ctime,
             st_mtime: entry.st_mtime,
             st_atime: entry.st_atime,
+            link_target: entry.link_target.as_ref().map(LinkTarget::new),
         }
     }
 }
@@ -63,6 +324,9 @@ impl DirEntry {
         pydict.set_item("st_ctime".to_object(py), self.st_ctime)?;
         pydict.set_item("st_mtime".to_object(py), self.st_mtime)?;
         pydict.set_item("st_atime".to_object(py), self.st_atime)?;
+        if let Some(ref link_target) = self.link_target {
+            pydict.set_item("link_target".to_object(py), link_target.as_dict(py)?)?;
+        }
         Ok(pydict.to_object(py))
     }
 
@@ -108,6 +372,12 @@ pub struct DirEntryExt {
     pub st_gid: u32,
     #[pyo3(get)]
     pub st_rdev: u64,
+    #[pyo3(get)]
+    pub owner: Option<String>,
+    #[pyo3(get)]
+    pub group: Option<String>,
+    #[pyo3(get)]
+    pub link_target: Option<LinkTarget>,
 }
 
 impl DirEntryExt {
@@ -130,6 +400,9 @@ impl DirEntryExt {
             st_uid: entry.st_uid,
             st_gid: entry.st_gid,
             st_rdev: entry.st_rdev,
+            owner: entry.owner.clone(),
+            group: entry.group.clone(),
+            link_target: entry.link_target.as_ref().map(LinkTarget::new),
         }
     }
 }
@@ -155,6 +428,15 @@ impl DirEntryExt {
         pydict.set_item("st_uid".to_object(py), self.st_uid)?;
         pydict.set_item("st_gid".to_object(py), self.st_gid)?;
         pydict.set_item("st_rdev".to_object(py), self.st_rdev)?;
+        if let Some(ref owner) = self.owner {
+            pydict.set_item("owner".to_object(py), owner)?;
+        }
+        if let Some(ref group) = self.group {
+            pydict.set_item("group".to_object(py), group)?;
+        }
+        if let Some(ref link_target) = self.link_target {
+            pydict.set_item("link_target".to_object(py), link_target.as_dict(py)?)?;
+        }
         Ok(pydict.to_object(py))
     }
 
@@ -173,6 +455,8 @@ pub struct Toc {
     #[pyo3(get)]
     pub symlinks: Vec<String>,
     #[pyo3(get)]
+    pub broken_symlinks: Vec<String>,
+    #[pyo3(get)]
     pub other: Vec<String>,
     #[pyo3(get)]
     pub errors: Vec<String>,
@@ -185,6 +469,7 @@ impl Toc {
                 dirs: toc.dirs,
                 files: toc.files,
                 symlinks: toc.symlinks,
+                broken_symlinks: toc.broken_symlinks,
                 other: toc.other,
                 errors: toc.errors,
             }
@@ -193,6 +478,7 @@ impl Toc {
                 dirs: Vec::new(),
                 files: Vec::new(),
                 symlinks: Vec::new(),
+                broken_symlinks: Vec::new(),
                 other: Vec::new(),
                 errors: Vec::new(),
             }
@@ -205,6 +491,7 @@ impl Toc {
         self.dirs.extend_from_slice(&toc.dirs);
         self.files.extend_from_slice(&toc.files);
         self.symlinks.extend_from_slice(&toc.symlinks);
+        self.broken_symlinks.extend_from_slice(&toc.broken_symlinks);
         self.other.extend_from_slice(&toc.other);
         self.errors.extend_from_slice(&toc.errors);
     }
@@ -217,6 +504,10 @@ impl Toc {
         pydict.set_item("dirs".to_object(py), self.dirs.clone())?;
         pydict.set_item("files".to_object(py), self.files.clone())?;
         pydict.set_item("symlinks".to_object(py), self.symlinks.clone())?;
+        pydict.set_item(
+            "broken_symlinks".to_object(py),
+            self.broken_symlinks.clone(),
+        )?;
         pydict.set_item("other".to_object(py), self.other.clone())?;
         pydict.set_item("errors".to_object(py), self.errors.clone())?;
         Ok(pydict.to_object(py))
@@ -227,29 +518,83 @@ impl Toc {
     }
 }
 
+#[pyclass]
+#[derive(Debug, Clone)]
+pub struct Progress {
+    #[pyo3(get)]
+    pub dirs: u64,
+    #[pyo3(get)]
+    pub entries: u64,
+    #[pyo3(get)]
+    pub bytes: u64,
+    #[pyo3(get)]
+    pub errors: u64,
+    #[pyo3(get)]
+    pub pending_dirs: u64,
+    #[pyo3(get)]
+    pub elapsed: f64,
+}
+
+impl Progress {
+    pub fn new(progress: &scandir::Progress) -> Self {
+        Progress {
+            dirs: progress.dirs,
+            entries: progress.entries,
+            bytes: progress.bytes,
+            errors: progress.errors,
+            pending_dirs: progress.pending_dirs,
+            elapsed: progress.elapsed,
+        }
+    }
+}
+
+#[pymethods]
+impl Progress {
+    fn __str__(&self) -> PyResult<String> {
+        Ok(format!("{:?}", self))
+    }
+}
+
 #[pyclass]
 #[derive(Debug, Clone)]
 pub struct Statistics {
     #[pyo3(get)]
-    pub dirs: i32,
+    pub dirs: u64,
+    #[pyo3(get)]
+    pub files: u64,
     #[pyo3(get)]
-    pub files: i32,
+    pub slinks: u64,
     #[pyo3(get)]
-    pub slinks: i32,
+    pub hlinks: u64,
     #[pyo3(get)]
-    pub hlinks: i32,
+    pub devices: u64,
     #[pyo3(get)]
-    pub devices: i32,
+    pub pipes: u64,
     #[pyo3(get)]
-    pub pipes: i32,
+    pub entries: u64,
     #[pyo3(get)]
     pub size: u64,
     #[pyo3(get)]
     pub usage: u64,
     #[pyo3(get)]
+    pub unique_size: u64,
+    #[pyo3(get)]
+    pub unique_usage: u64,
+    pub owners: HashMap<String, scandir::Totals>,
+    pub extensions: HashMap<String, scandir::Totals>,
+    pub uids: HashMap<u32, scandir::Totals>,
+    pub ages: BTreeMap<u64, scandir::Totals>,
+    pub sizes: BTreeMap<u32, scandir::Totals>,
+    #[pyo3(get)]
     pub errors: Vec<String>,
     #[pyo3(get)]
+    pub errors_cnt: u64,
+    #[pyo3(get)]
     pub duration: f64,
+    #[pyo3(get)]
+    pub stop_reason: Option<StopReason>,
+    #[pyo3(get)]
+    pub oversized_dirs: Vec<(String, usize)>,
 }
 
 impl Statistics {
@@ -262,10 +607,21 @@ impl Statistics {
                 hlinks: statistics.hlinks,
                 devices: statistics.devices,
                 pipes: statistics.pipes,
+                entries: statistics.entries,
                 size: statistics.size,
                 usage: statistics.usage,
+                unique_size: statistics.unique_size,
+                unique_usage: statistics.unique_usage,
+                owners: statistics.owners.clone(),
+                extensions: statistics.extensions.clone(),
+                uids: statistics.uids.clone(),
+                ages: statistics.ages.clone(),
+                sizes: statistics.sizes.clone(),
                 errors: statistics.errors.clone(),
+                errors_cnt: statistics.errors_cnt,
                 duration: statistics.duration,
+                stop_reason: statistics.stop_reason.as_ref().map(StopReason::new),
+                oversized_dirs: statistics.oversized_dirs.clone(),
             }
         } else {
             Statistics {
@@ -275,17 +631,69 @@ impl Statistics {
                 hlinks: 0,
                 devices: 0,
                 pipes: 0,
+                entries: 0,
                 size: 0,
                 usage: 0,
+                unique_size: 0,
+                unique_usage: 0,
+                owners: HashMap::new(),
+                extensions: HashMap::new(),
+                uids: HashMap::new(),
+                ages: BTreeMap::new(),
+                sizes: BTreeMap::new(),
                 errors: Vec::new(),
+                errors_cnt: 0,
                 duration: 0.0,
+                stop_reason: None,
+                oversized_dirs: Vec::new(),
             }
         }
     }
 }
 
+fn totals_to_dict<'a, K, I>(totals: I, py: Python) -> PyResult<PyObject>
+where
+    K: ToPyObject + 'a,
+    I: IntoIterator<Item = (&'a K, &'a scandir::Totals)>,
+{
+    let pydict = PyDict::new(py);
+    for (key, value) in totals {
+        let pytotals = PyDict::new(py);
+        pytotals.set_item("count", value.count)?;
+        pytotals.set_item("size", value.size)?;
+        pytotals.set_item("usage", value.usage)?;
+        pydict.set_item(key.to_object(py), pytotals)?;
+    }
+    Ok(pydict.to_object(py))
+}
+
 #[pymethods]
 impl Statistics {
+    #[getter]
+    fn owners(&self, py: Python) -> PyResult<PyObject> {
+        totals_to_dict(&self.owners, py)
+    }
+
+    #[getter]
+    fn extensions(&self, py: Python) -> PyResult<PyObject> {
+        totals_to_dict(&self.extensions, py)
+    }
+
+    #[getter]
+    fn uids(&self, py: Python) -> PyResult<PyObject> {
+        totals_to_dict(&self.uids, py)
+    }
+
+    #[getter]
+    fn ages(&self, py: Python) -> PyResult<PyObject> {
+        totals_to_dict(&self.ages, py)
+    }
+
+    #[getter]
+    fn sizes(&self, py: Python) -> PyResult<PyObject> {
+        totals_to_dict(&self.sizes, py)
+    }
+
     pub fn as_dict(&self, duration: Option<bool>, py: Python) -> PyResult<PyObject> {
         let pyresult = PyDict::new(py);
         if self.dirs > 0 {
@@ -306,15 +714,44 @@ impl Statistics {
         if self.pipes > 0 {
             pyresult.set_item("pipes", self.pipes).unwrap();
         }
+        if self.entries > 0 {
+            pyresult.set_item("entries", self.entries).unwrap();
+        }
         if self.size > 0 {
             pyresult.set_item("size", self.size).unwrap();
         }
         if self.usage > 0 {
             pyresult.set_item("usage", self.usage).unwrap();
         }
+        if self.unique_size > 0 {
+            pyresult.set_item("unique_size", self.unique_size).unwrap();
+        }
+        if self.unique_usage > 0 {
+            pyresult
+                .set_item("unique_usage", self.unique_usage)
+                .unwrap();
+        }
+        if !self.owners.is_empty() {
+            pyresult.set_item("owners", totals_to_dict(&self.owners, py)?)?;
+        }
+        if !self.extensions.is_empty() {
+            pyresult.set_item("extensions", totals_to_dict(&self.extensions, py)?)?;
+        }
+        if !self.uids.is_empty() {
+            pyresult.set_item("uids", totals_to_dict(&self.uids, py)?)?;
+        }
+        if !self.ages.is_empty() {
+            pyresult.set_item("ages", totals_to_dict(&self.ages, py)?)?;
+        }
+        if !self.sizes.is_empty() {
+            pyresult.set_item("sizes", totals_to_dict(&self.sizes, py)?)?;
+        }
         if !self.errors.is_empty() {
             pyresult.set_item("errors", self.errors.to_vec()).unwrap();
         }
+        if self.errors_cnt > 0 {
+            pyresult.set_item("errors_cnt", self.errors_cnt).unwrap();
+        }
         if duration.unwrap_or(false) == true {
             pyresult.set_item("duration", self.duration).unwrap();
         }
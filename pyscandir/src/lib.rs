@@ -3,6 +3,7 @@ use pyo3::prelude::*;
 mod def;
 mod pycount;
 mod pyscandir;
+mod pytopn;
 mod pywalk;
 
 /// scandir_rs is a directory iteration module like os.walk(), but with more features and higher speed. Depending on the function call
@@ -14,8 +15,17 @@ mod pywalk;
 fn init(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add("__version__", env!("CARGO_PKG_VERSION"))?;
     m.add_class::<def::ReturnType>()?;
+    m.add_class::<def::SortBy>()?;
+    m.add_class::<def::TraversalOrder>()?;
+    m.add_class::<def::OversizedAction>()?;
+    m.add_class::<def::StopReason>()?;
+    m.add_class::<def::Progress>()?;
+    m.add_class::<def::TopNKey>()?;
+    m.add_class::<def::ExportFormat>()?;
+    m.add_class::<def::LinkTarget>()?;
     m.add_class::<pycount::Count>()?;
     m.add_class::<pywalk::Walk>()?;
     m.add_class::<pyscandir::Scandir>()?;
+    m.add_class::<pytopn::TopN>()?;
     Ok(())
 }
@@ -1,11 +1,13 @@
 use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::Duration;
 
 use pyo3::exceptions::{PyException, PyFileNotFoundError, PyRuntimeError, PyValueError};
 use pyo3::prelude::*;
 use pyo3::types::{PyAny, PyType};
 use pyo3::Python;
 
-use crate::def::{ReturnType, Statistics};
+use crate::def::{OversizedAction, ReturnType, Statistics};
 
 #[pyclass]
 #[derive(Debug)]
@@ -28,19 +30,72 @@ impl Count {
         file_exclude: Option<Vec<String>>,
         case_sensitive: Option<bool>,
         return_type: Option<ReturnType>,
+        resolve_owners: Option<bool>,
+        histograms: Option<bool>,
+        min_depth: Option<usize>,
+        include_root: Option<bool>,
+        max_entries: Option<usize>,
+        max_dirs: Option<usize>,
+        max_total_bytes: Option<u64>,
+        timeout: Option<f64>,
+        max_dir_entries: Option<usize>,
+        oversized_action: Option<OversizedAction>,
+        threads: Option<usize>,
+        hardlink_memory: Option<usize>,
+        max_dirs_per_sec: Option<usize>,
+        max_stats_per_sec: Option<usize>,
+        idle_io_priority: Option<bool>,
+        nice: Option<i32>,
+        checkpoint: Option<String>,
+        checkpoint_interval: Option<f64>,
+        resume_from: Option<String>,
     ) -> PyResult<Self> {
+        let instance = scandir::Count::new(root_path).and_then(|c| {
+            let c = c
+                .skip_hidden(skip_hidden.unwrap_or(false))
+                .max_depth(max_depth.unwrap_or(0))
+                .max_file_cnt(max_file_cnt.unwrap_or(0))
+                .dir_include(dir_include)
+                .dir_exclude(dir_exclude)
+                .file_include(file_include)
+                .file_exclude(file_exclude)
+                .case_sensitive(case_sensitive.unwrap_or(false))
+                .return_type(return_type.unwrap_or(ReturnType::Base).from_object())
+                .resolve_owners(resolve_owners.unwrap_or(false))
+                .histograms(histograms.unwrap_or(false))
+                .min_depth(min_depth.unwrap_or(0))
+                .include_root(include_root.unwrap_or(false))
+                .max_entries(max_entries.unwrap_or(0))
+                .max_dirs(max_dirs.unwrap_or(0))
+                .max_total_bytes(max_total_bytes.unwrap_or(0))
+                .timeout(timeout.map(|t| Duration::from_secs_f64(t.max(0.0))))
+                .max_dir_entries(max_dir_entries.unwrap_or(0))
+                .oversized_action(
+                    oversized_action
+                        .unwrap_or(OversizedAction::Truncate)
+                        .from_object(),
+                )
+                .threads(threads.unwrap_or(0))
+                .hardlink_memory(hardlink_memory.unwrap_or(0))
+                .max_dirs_per_sec(max_dirs_per_sec.unwrap_or(0))
+                .max_stats_per_sec(max_stats_per_sec.unwrap_or(0))
+                .idle_io_priority(idle_io_priority.unwrap_or(false))
+                .nice(nice.unwrap_or(0))
+                .checkpoint(checkpoint.map(PathBuf::from))
+                .checkpoint_interval(Duration::from_secs_f64(
+                    checkpoint_interval.unwrap_or(60.0).max(0.0),
+                ));
+            match resume_from {
+                Some(resume_from) => scandir::Count::resume_from(
+                    scandir::Checkpoint::load(resume_from)?,
+                    c.options(),
+                ),
+                None => Ok(c),
+            }
+        });
         Ok(Count {
-            instance: match scandir::Count::new(root_path) {
-                Ok(c) => c
-                    .skip_hidden(skip_hidden.unwrap_or(false))
-                    .max_depth(max_depth.unwrap_or(0))
-                    .max_file_cnt(max_file_cnt.unwrap_or(0))
-                    .dir_include(dir_include)
-                    .dir_exclude(dir_exclude)
-                    .file_include(file_include)
-                    .file_exclude(file_exclude)
-                    .case_sensitive(case_sensitive.unwrap_or(false))
-                    .extended(return_type.unwrap_or(ReturnType::Base) == ReturnType::Ext),
+            instance: match instance {
+                Ok(c) => c,
                 Err(e) => match e.kind() {
                     ErrorKind::InvalidInput => return Err(PyValueError::new_err(e.to_string())),
                     ErrorKind::NotFound => return Err(PyFileNotFoundError::new_err(e.to_string())),
@@ -76,6 +131,18 @@ impl Count {
         Ok(true)
     }
 
+    pub fn pause(&self) {
+        self.instance.pause();
+    }
+
+    pub fn resume(&self) {
+        self.instance.resume();
+    }
+
+    pub fn paused(&self) -> bool {
+        self.instance.paused()
+    }
+
     pub fn collect(&mut self, py: Python) -> PyResult<PyObject> {
         let results = py.allow_threads(|| self.instance.collect())?;
         Ok(PyCell::new(py, Statistics::new(Some(results)))
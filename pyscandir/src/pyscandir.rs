@@ -1,4 +1,6 @@
+use std::collections::VecDeque;
 use std::io::ErrorKind;
+use std::path::PathBuf;
 use std::thread;
 use std::time::Duration;
 
@@ -7,7 +9,12 @@ use pyo3::prelude::*;
 use pyo3::types::{PyAny, PyDict, PyType};
 use pyo3::Python;
 
-use crate::def::{DirEntry, DirEntryExt, ReturnType};
+use crate::def::{
+    create_export_writer, DirEntry, DirEntryExt, ExportFormat, OversizedAction, Progress,
+    ReturnType, SortBy, StopReason, TraversalOrder,
+};
+#[cfg(feature = "arrow")]
+use crate::def::{create_ipc_writer, create_parquet_writer, ipc_to_table};
 use scandir::{self, ScandirResult};
 
 fn result2py(result: &ScandirResult, py: Python) -> PyObject {
@@ -24,8 +31,9 @@ fn result2py(result: &ScandirResult, py: Python) -> PyObject {
 #[derive(Debug)]
 pub struct Scandir {
     instance: scandir::Scandir,
-    entries: Vec<ScandirResult>,
-    errors: Vec<(String, String)>,
+    // Results received by the iterator, returned in the order of the scan
+    entries: VecDeque<ScandirResult>,
+    errors: VecDeque<(String, String)>,
 }
 
 #[pymethods]
@@ -43,28 +51,111 @@ impl Scandir {
         file_exclude: Option<Vec<String>>,
         case_sensitive: Option<bool>,
         return_type: Option<ReturnType>,
+        read_links: Option<bool>,
+        link_metadata: Option<bool>,
+        resolve_owners: Option<bool>,
+        sort_by: Option<SortBy>,
+        dirs_first: Option<bool>,
+        order: Option<TraversalOrder>,
+        min_depth: Option<usize>,
+        include_root: Option<bool>,
+        max_entries: Option<usize>,
+        max_dirs: Option<usize>,
+        max_total_bytes: Option<u64>,
+        timeout: Option<f64>,
+        max_dir_entries: Option<usize>,
+        oversized_action: Option<OversizedAction>,
+        threads: Option<usize>,
+        progress_callback: Option<PyObject>,
+        progress_interval: Option<f64>,
+        max_dirs_per_sec: Option<usize>,
+        max_stats_per_sec: Option<usize>,
+        idle_io_priority: Option<bool>,
+        nice: Option<i32>,
+        checkpoint: Option<String>,
+        checkpoint_interval: Option<f64>,
+        resume_from: Option<String>,
+        store: Option<bool>,
     ) -> PyResult<Self> {
         let return_type = return_type.unwrap_or(ReturnType::Base).from_object();
+        let instance = scandir::Scandir::new(root_path).and_then(|s| {
+            let s = s
+                .skip_hidden(skip_hidden.unwrap_or(false))
+                .max_depth(max_depth.unwrap_or(0))
+                .max_file_cnt(max_file_cnt.unwrap_or(0))
+                .dir_include(dir_include)
+                .dir_exclude(dir_exclude)
+                .file_include(file_include)
+                .file_exclude(file_exclude)
+                .case_sensitive(case_sensitive.unwrap_or(false))
+                .return_type(return_type)
+                .read_links(read_links.unwrap_or(false))
+                .link_metadata(link_metadata.unwrap_or(false))
+                .resolve_owners(resolve_owners.unwrap_or(false))
+                .sort_by(match sort_by {
+                    Some(sort_by) => Some(sort_by.from_object()),
+                    None => sorted.unwrap_or(false).then_some(scandir::SortBy::Name),
+                })
+                .dirs_first(dirs_first.unwrap_or(false))
+                .order(order.unwrap_or(TraversalOrder::Default).from_object())
+                .min_depth(min_depth.unwrap_or(0))
+                .include_root(include_root.unwrap_or(false))
+                .max_entries(max_entries.unwrap_or(0))
+                .max_dirs(max_dirs.unwrap_or(0))
+                .max_total_bytes(max_total_bytes.unwrap_or(0))
+                .timeout(timeout.map(|t| Duration::from_secs_f64(t.max(0.0))))
+                .max_dir_entries(max_dir_entries.unwrap_or(0))
+                .oversized_action(
+                    oversized_action
+                        .unwrap_or(OversizedAction::Truncate)
+                        .from_object(),
+                )
+                .threads(threads.unwrap_or(0))
+                .max_dirs_per_sec(max_dirs_per_sec.unwrap_or(0))
+                .max_stats_per_sec(max_stats_per_sec.unwrap_or(0))
+                .idle_io_priority(idle_io_priority.unwrap_or(false))
+                .nice(nice.unwrap_or(0))
+                .checkpoint(checkpoint.map(PathBuf::from))
+                .checkpoint_interval(Duration::from_secs_f64(
+                    checkpoint_interval.unwrap_or(60.0).max(0.0),
+                ));
+            match resume_from {
+                Some(resume_from) => scandir::Scandir::resume_from(
+                    scandir::Checkpoint::load(resume_from)?,
+                    s.options(),
+                ),
+                None => Ok(s),
+            }
+        });
         Ok(Scandir {
-            instance: match scandir::Scandir::new(root_path) {
-                Ok(s) => s
-                    .sorted(sorted.unwrap_or(false))
-                    .skip_hidden(skip_hidden.unwrap_or(false))
-                    .max_depth(max_depth.unwrap_or(0))
-                    .max_file_cnt(max_file_cnt.unwrap_or(0))
-                    .dir_include(dir_include)
-                    .dir_exclude(dir_exclude)
-                    .file_include(file_include)
-                    .file_exclude(file_exclude)
-                    .case_sensitive(case_sensitive.unwrap_or(false))
-                    .return_type(return_type),
+            instance: match instance {
+                Ok(s) => {
+                    let s = s
+                        .progress_interval(Duration::from_secs_f64(
+                            progress_interval.unwrap_or(0.1).max(0.0),
+                        ))
+                        .store(store.unwrap_or(true));
+                    match progress_callback {
+                        Some(progress_callback) => s.progress_callback(move |progress| {
+                            Python::with_gil(|py| {
+                                if let Err(e) =
+                                    progress_callback.call1(py, (Progress::new(progress),))
+                                {
+                                    e.print(py);
+                                }
+                            })
+                        }),
+                        None => s,
+                    }
+                }
                 Err(e) => match e.kind() {
+                    ErrorKind::InvalidInput => return Err(PyValueError::new_err(e.to_string())),
                     ErrorKind::NotFound => return Err(PyFileNotFoundError::new_err(e.to_string())),
                     _ => return Err(PyException::new_err(e.to_string())),
                 },
             },
-            entries: Vec::new(),
-            errors: Vec::new(),
+            entries: VecDeque::new(),
+            errors: VecDeque::new(),
         })
     }
 
@@ -88,19 +179,119 @@ impl Scandir {
         Ok(true)
     }
 
-    pub fn stop(&mut self) -> PyResult<bool> {
-        if !self.instance.stop() {
+    pub fn stop(&mut self, py: Python) -> PyResult<bool> {
+        if !py.allow_threads(|| self.instance.stop()) {
             return Err(PyRuntimeError::new_err("Thread not running"));
         }
         Ok(true)
     }
 
+    pub fn pause(&self) {
+        self.instance.pause();
+    }
+
+    pub fn resume(&self) {
+        self.instance.resume();
+    }
+
+    pub fn paused(&self) -> bool {
+        self.instance.paused()
+    }
+
     pub fn collect(&mut self, py: Python) -> PyResult<(Vec<PyObject>, Vec<(String, String)>)> {
         let (entries, errors) = py.allow_threads(|| self.instance.collect())?;
         let results = entries.iter().map(|e| result2py(e, py)).collect();
         Ok((results, errors))
     }
 
+    /// Write the results to file `file_path` while the scan is running. Returns the number
+    /// of written entries.
+    pub fn export(
+        &mut self,
+        py: Python,
+        file_path: &str,
+        format: Option<ExportFormat>,
+        columns: Option<Vec<String>>,
+    ) -> PyResult<u64> {
+        let mut writer = create_export_writer(
+            file_path,
+            format,
+            columns,
+            &self.instance.options().return_type,
+        )?;
+        let rows = py.allow_threads(|| -> std::io::Result<u64> {
+            scandir::export_scandir(&mut self.instance, &mut writer)?;
+            let rows = writer.rows();
+            writer.finish()?;
+            Ok(rows)
+        })?;
+        Ok(rows)
+    }
+
+    /// Return the results as `pyarrow.Table`. The record batches are built from the results
+    /// while the scan is running and passed to `pyarrow` as Arrow IPC stream.
+    #[cfg(feature = "arrow")]
+    pub fn collect_arrow(
+        &mut self,
+        py: Python,
+        columns: Option<Vec<String>>,
+        batch_size: Option<usize>,
+    ) -> PyResult<PyObject> {
+        let mut writer =
+            create_ipc_writer(columns, batch_size, &self.instance.options().return_type)?;
+        let data = py.allow_threads(|| -> std::io::Result<Vec<u8>> {
+            scandir::export_scandir(&mut self.instance, &mut writer)?;
+            writer.finish()?.into_inner().map_err(std::io::Error::other)
+        })?;
+        ipc_to_table(py, &data)
+    }
+
+    /// Write the results to Parquet file `file_path` while the scan is running. Returns
+    /// the number of written entries.
+    #[cfg(feature = "arrow")]
+    pub fn write_parquet(
+        &mut self,
+        py: Python,
+        file_path: &str,
+        columns: Option<Vec<String>>,
+        batch_size: Option<usize>,
+    ) -> PyResult<u64> {
+        let mut writer = create_parquet_writer(
+            file_path,
+            columns,
+            batch_size,
+            &self.instance.options().return_type,
+        )?;
+        let rows = py.allow_threads(|| -> std::io::Result<u64> {
+            scandir::export_scandir(&mut self.instance, &mut writer)?;
+            let rows = writer.rows();
+            writer.finish()?;
+            Ok(rows)
+        })?;
+        Ok(rows)
+    }
+
+    /// Create or update the SQLite index in database `file_path` while the scan is
+    /// running. Returns the number of written entries.
+    #[cfg(feature = "sqlite")]
+    pub fn write_sqlite(
+        &mut self,
+        py: Python,
+        file_path: &str,
+        batch_size: Option<usize>,
+    ) -> PyResult<u64> {
+        let batch_size = batch_size.unwrap_or(scandir::sqlite::DEFAULT_BATCH_SIZE);
+        py.allow_threads(|| {
+            scandir::sqlite::index_scandir(&mut self.instance, file_path, batch_size)
+        })
+        .map_err(|e| match e.kind() {
+            ErrorKind::InvalidInput | ErrorKind::InvalidData => {
+                PyValueError::new_err(e.to_string())
+            }
+            _ => e.into(),
+        })
+    }
+
     pub fn has_results(&mut self, only_new: Option<bool>) -> bool {
         self.instance.has_results(only_new.unwrap_or(false))
     }
@@ -147,6 +338,18 @@ impl Scandir {
         self.instance.errors(return_all.unwrap_or(false))
     }
 
+    pub fn progress(&self) -> Progress {
+        Progress::new(&self.instance.progress())
+    }
+
+    pub fn oversized_dirs(&self) -> Vec<(String, usize)> {
+        self.instance.oversized_dirs()
+    }
+
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.instance.stop_reason().as_ref().map(StopReason::new)
+    }
+
     pub fn duration(&mut self) -> f64 {
         self.instance.duration()
     }
@@ -191,8 +394,9 @@ impl Scandir {
         ty: Option<&PyType>,
         _value: Option<&PyAny>,
         _traceback: Option<&PyAny>,
+        py: Python,
     ) -> PyResult<bool> {
-        if !self.instance.stop() {
+        if !py.allow_threads(|| self.instance.stop()) {
             return Ok(false);
         }
         self.instance.join();
@@ -223,7 +427,7 @@ impl Scandir {
 
     fn __next__(&mut self, py: Python) -> PyResult<Option<PyObject>> {
         loop {
-            if let Some(entry) = self.entries.pop() {
+            if let Some(entry) = self.entries.pop_front() {
                 match entry {
                     ScandirResult::DirEntry(e) => {
                         return Ok(Some(
@@ -238,7 +442,7 @@ impl Scandir {
                     ScandirResult::Error(error) => return Ok(Some(error.to_object(py))),
                 }
             }
-            if let Some(error) = self.errors.pop() {
+            if let Some(error) = self.errors.pop_front() {
                 return Ok(Some(error.to_object(py)));
             }
             let (entries, errors) = self.instance.results(false);
@@ -246,10 +450,11 @@ impl Scandir {
                 if !self.instance.busy() {
                     break;
                 }
-                thread::sleep(Duration::from_millis(10));
+                // Progress callbacks of the workers need the GIL
+                py.allow_threads(|| thread::sleep(Duration::from_millis(10)));
             } else {
-                self.entries.extend_from_slice(&entries);
-                self.errors.extend_from_slice(&errors);
+                self.entries.extend(entries);
+                self.errors.extend(errors);
             }
         }
         Ok(None)
@@ -0,0 +1,176 @@
+use std::io::ErrorKind;
+
+use pyo3::exceptions::{PyException, PyFileNotFoundError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyAny, PyType};
+use pyo3::Python;
+
+use crate::def::{DirEntryExt, TopNKey};
+
+fn entries2py(entries: Vec<scandir::DirEntryExt>, py: Python) -> Vec<PyObject> {
+    entries
+        .iter()
+        .map(|e| PyCell::new(py, DirEntryExt::new(e)).unwrap().to_object(py))
+        .collect()
+}
+
+#[pyclass]
+#[derive(Debug)]
+pub struct TopN {
+    instance: scandir::TopN,
+    busy: bool,
+}
+
+#[pymethods]
+impl TopN {
+    #[new]
+    fn new(
+        root_path: &str,
+        n: Option<usize>,
+        key: Option<TopNKey>,
+        reverse: Option<bool>,
+        skip_hidden: Option<bool>,
+        max_depth: Option<usize>,
+        dir_include: Option<Vec<String>>,
+        dir_exclude: Option<Vec<String>>,
+        file_include: Option<Vec<String>>,
+        file_exclude: Option<Vec<String>>,
+        case_sensitive: Option<bool>,
+        threads: Option<usize>,
+    ) -> PyResult<Self> {
+        Ok(TopN {
+            instance: match scandir::TopN::new(root_path, n.unwrap_or(10)) {
+                Ok(t) => t
+                    .key(key.unwrap_or(TopNKey::Size).from_object())
+                    .reverse(reverse.unwrap_or(false))
+                    .skip_hidden(skip_hidden.unwrap_or(false))
+                    .max_depth(max_depth.unwrap_or(0))
+                    .dir_include(dir_include)
+                    .dir_exclude(dir_exclude)
+                    .file_include(file_include)
+                    .file_exclude(file_exclude)
+                    .case_sensitive(case_sensitive.unwrap_or(false))
+                    .threads(threads.unwrap_or(0)),
+                Err(e) => match e.kind() {
+                    ErrorKind::NotFound => return Err(PyFileNotFoundError::new_err(e.to_string())),
+                    _ => return Err(PyException::new_err(e.to_string())),
+                },
+            },
+            busy: false,
+        })
+    }
+
+    pub fn clear(&mut self) {
+        self.instance.clear();
+    }
+
+    pub fn start(&mut self) -> PyResult<()> {
+        self.instance.start().map_err(|e| match e.kind() {
+            ErrorKind::InvalidInput => PyValueError::new_err(e.to_string()),
+            _ => PyException::new_err(e.to_string()),
+        })
+    }
+
+    pub fn join(&mut self, py: Python) -> PyResult<bool> {
+        let result = py.allow_threads(|| self.instance.join());
+        if !result {
+            return Err(PyRuntimeError::new_err("Thread not running"));
+        }
+        Ok(true)
+    }
+
+    pub fn stop(&mut self) -> PyResult<bool> {
+        if !self.instance.stop() {
+            return Err(PyRuntimeError::new_err("Thread not running"));
+        }
+        Ok(true)
+    }
+
+    pub fn collect(&mut self, py: Python) -> PyResult<Vec<PyObject>> {
+        let entries = py.allow_threads(|| self.instance.collect())?;
+        Ok(entries2py(entries, py))
+    }
+
+    pub fn has_results(&mut self) -> bool {
+        self.instance.has_results()
+    }
+
+    pub fn results(&mut self, py: Python) -> Vec<PyObject> {
+        entries2py(self.instance.results(), py)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.instance.has_errors()
+    }
+
+    pub fn errors(&self) -> Vec<(String, String)> {
+        self.instance.errors()
+    }
+
+    pub fn duration(&mut self) -> f64 {
+        self.instance.duration()
+    }
+
+    pub fn finished(&mut self) -> bool {
+        self.instance.finished()
+    }
+
+    pub fn busy(&self) -> bool {
+        self.instance.busy()
+    }
+
+    fn __enter__(mut slf: PyRefMut<Self>) -> PyResult<PyRefMut<Self>> {
+        slf.instance
+            .start()
+            .map_err(|e| PyException::new_err(e.to_string()))?;
+        Ok(slf)
+    }
+
+    fn __exit__(
+        &mut self,
+        ty: Option<&PyType>,
+        _value: Option<&PyAny>,
+        _traceback: Option<&PyAny>,
+    ) -> PyResult<bool> {
+        if !self.instance.stop() {
+            return Ok(false);
+        }
+        self.instance.join();
+        match ty {
+            Some(ty) => {
+                if ty
+                    .eq(Python::acquire_gil().python().get_type::<PyValueError>())
+                    .unwrap()
+                {
+                    Ok(true)
+                } else {
+                    Ok(false)
+                }
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn __iter__(mut slf: PyRefMut<Self>) -> PyResult<PyRefMut<Self>> {
+        if slf.busy {
+            return Err(PyRuntimeError::new_err("Busy"));
+        }
+        slf.instance.start()?;
+        slf.busy = true;
+        Ok(slf)
+    }
+
+    fn __next__(&mut self, py: Python) -> PyResult<Option<Vec<PyObject>>> {
+        if !self.busy {
+            return Ok(None);
+        }
+        if !self.instance.busy() {
+            self.busy = false;
+        }
+        Ok(Some(entries2py(self.instance.results(), py)))
+    }
+
+    fn __str__(&self) -> PyResult<String> {
+        Ok(format!("{:?}", self))
+    }
+}
@@ -1,25 +1,43 @@
 use std::fmt::Debug;
 use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
 use pyo3::exceptions::{PyException, PyFileNotFoundError, PyRuntimeError, PyValueError};
 use pyo3::prelude::*;
-use pyo3::types::{PyAny, PyType};
+use pyo3::types::{PyAny, PyDict, PyList, PyType};
 use pyo3::Python;
 
-use crate::def::{ReturnType, Toc};
+use crate::def::{
+    create_export_writer, DirEntryExt, ExportFormat, OversizedAction, Progress, ReturnType, SortBy,
+    StopReason, Toc, TraversalOrder,
+};
+#[cfg(feature = "arrow")]
+use crate::def::{create_ipc_writer, create_parquet_writer, ipc_to_table};
 
 #[pyclass]
 #[derive(Debug)]
 pub struct Walk {
     instance: scandir::Walk,
     return_type: ReturnType,
+    // First exception raised by the prune callback
+    prune_error: Arc<Mutex<Option<PyErr>>>,
     // For iterator
     entries: Vec<(String, scandir::Toc)>,
     idx: usize,
 }
 
+impl Walk {
+    /// Raise the exception of the prune callback, if any.
+    fn raise_prune_error(&self) -> PyResult<()> {
+        match self.prune_error.lock().unwrap().take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
 #[pymethods]
 impl Walk {
     #[new]
@@ -35,27 +53,119 @@ impl Walk {
         file_exclude: Option<Vec<String>>,
         case_sensitive: Option<bool>,
         return_type: Option<ReturnType>,
+        read_links: Option<bool>,
+        topdown: Option<bool>,
+        prune: Option<PyObject>,
+        sort_by: Option<SortBy>,
+        dirs_first: Option<bool>,
+        order: Option<TraversalOrder>,
+        min_depth: Option<usize>,
+        max_entries: Option<usize>,
+        max_dirs: Option<usize>,
+        max_total_bytes: Option<u64>,
+        timeout: Option<f64>,
+        max_dir_entries: Option<usize>,
+        oversized_action: Option<OversizedAction>,
+        threads: Option<usize>,
+        progress_callback: Option<PyObject>,
+        progress_interval: Option<f64>,
+        max_dirs_per_sec: Option<usize>,
+        max_stats_per_sec: Option<usize>,
+        idle_io_priority: Option<bool>,
+        nice: Option<i32>,
+        store: Option<bool>,
     ) -> PyResult<Self> {
+        if prune.is_some() && topdown == Some(false) {
+            return Err(PyValueError::new_err(
+                "Parameter prune requires topdown to be True",
+            ));
+        }
         let return_type = return_type.unwrap_or(ReturnType::Base);
+        let prune_error = Arc::new(Mutex::new(None));
+        let prune_error_cloned = prune_error.clone();
         Ok(Walk {
             instance: match scandir::Walk::new(root_path) {
-                Ok(s) => s
-                    .sorted(sorted.unwrap_or(false))
-                    .skip_hidden(skip_hidden.unwrap_or(false))
-                    .max_depth(max_depth.unwrap_or(0))
-                    .max_file_cnt(max_file_cnt.unwrap_or(0))
-                    .dir_include(dir_include)
-                    .dir_exclude(dir_exclude)
-                    .file_include(file_include)
-                    .file_exclude(file_exclude)
-                    .case_sensitive(case_sensitive.unwrap_or(false))
-                    .return_type(return_type.from_object()),
+                Ok(s) => {
+                    let s = s
+                        .skip_hidden(skip_hidden.unwrap_or(false))
+                        .max_depth(max_depth.unwrap_or(0))
+                        .max_file_cnt(max_file_cnt.unwrap_or(0))
+                        .dir_include(dir_include)
+                        .dir_exclude(dir_exclude)
+                        .file_include(file_include)
+                        .file_exclude(file_exclude)
+                        .case_sensitive(case_sensitive.unwrap_or(false))
+                        .return_type(return_type.from_object())
+                        .read_links(read_links.unwrap_or(false))
+                        .topdown(topdown.unwrap_or(true))
+                        .sort_by(match sort_by {
+                            Some(sort_by) => Some(sort_by.from_object()),
+                            None => sorted.unwrap_or(false).then_some(scandir::SortBy::Name),
+                        })
+                        .dirs_first(dirs_first.unwrap_or(false))
+                        .order(order.unwrap_or(TraversalOrder::Default).from_object())
+                        .min_depth(min_depth.unwrap_or(0))
+                        .max_entries(max_entries.unwrap_or(0))
+                        .max_dirs(max_dirs.unwrap_or(0))
+                        .max_total_bytes(max_total_bytes.unwrap_or(0))
+                        .timeout(timeout.map(|t| Duration::from_secs_f64(t.max(0.0))))
+                        .max_dir_entries(max_dir_entries.unwrap_or(0))
+                        .oversized_action(
+                            oversized_action
+                                .unwrap_or(OversizedAction::Truncate)
+                                .from_object(),
+                        )
+                        .threads(threads.unwrap_or(0))
+                        .progress_interval(Duration::from_secs_f64(
+                            progress_interval.unwrap_or(0.1).max(0.0),
+                        ))
+                        .max_dirs_per_sec(max_dirs_per_sec.unwrap_or(0))
+                        .max_stats_per_sec(max_stats_per_sec.unwrap_or(0))
+                        .idle_io_priority(idle_io_priority.unwrap_or(false))
+                        .nice(nice.unwrap_or(0))
+                        .store(store.unwrap_or(true));
+                    let s = match progress_callback {
+                        Some(progress_callback) => s.progress_callback(move |progress| {
+                            Python::with_gil(|py| {
+                                if let Err(e) =
+                                    progress_callback.call1(py, (Progress::new(progress),))
+                                {
+                                    e.print(py);
+                                }
+                            })
+                        }),
+                        None => s,
+                    };
+                    match prune {
+                        Some(prune) => s.prune(move |root_dir, dirs| {
+                            Python::with_gil(|py| {
+                                let pydirs = PyList::new(py, dirs.iter());
+                                let result = prune
+                                    .call1(py, (root_dir, pydirs))
+                                    .and_then(|_| pydirs.extract::<Vec<String>>());
+                                match result {
+                                    Ok(pruned) => {
+                                        *dirs = pruned;
+                                        true
+                                    }
+                                    Err(e) => {
+                                        // Keep the first exception and stop the walk
+                                        prune_error_cloned.lock().unwrap().get_or_insert(e);
+                                        false
+                                    }
+                                }
+                            })
+                        }),
+                        None => s,
+                    }
+                }
                 Err(e) => match e.kind() {
                     ErrorKind::NotFound => return Err(PyFileNotFoundError::new_err(e.to_string())),
                     _ => return Err(PyException::new_err(e.to_string())),
                 },
             },
             return_type,
+            prune_error,
             entries: Vec::new(),
             idx: std::usize::MAX,
         })
@@ -68,6 +178,7 @@ impl Walk {
     }
 
     pub fn start(&mut self) -> PyResult<()> {
+        self.prune_error.lock().unwrap().take();
         self.instance
             .start()
             .map_err(|e| PyException::new_err(e.to_string()))
@@ -78,20 +189,100 @@ impl Walk {
         if !result {
             return Err(PyRuntimeError::new_err("Thread not running"));
         }
+        self.raise_prune_error()?;
         Ok(true)
     }
 
-    pub fn stop(&mut self) -> PyResult<bool> {
-        if !self.instance.stop() {
+    pub fn stop(&mut self, py: Python) -> PyResult<bool> {
+        if !py.allow_threads(|| self.instance.stop()) {
             return Err(PyRuntimeError::new_err("Thread not running"));
         }
         Ok(true)
     }
 
+    pub fn pause(&self) {
+        self.instance.pause();
+    }
+
+    pub fn resume(&self) {
+        self.instance.resume();
+    }
+
+    pub fn paused(&self) -> bool {
+        self.instance.paused()
+    }
+
     pub fn collect(&mut self, py: Python) -> PyResult<Toc> {
-        Ok(Toc::new(Some(
-            py.allow_threads(|| self.instance.collect())?,
-        )))
+        let toc = py.allow_threads(|| self.instance.collect())?;
+        self.raise_prune_error()?;
+        Ok(Toc::new(Some(toc)))
+    }
+
+    /// Write the results to file `file_path` while the walk is running. Returns the number
+    /// of written entries.
+    pub fn export(
+        &mut self,
+        py: Python,
+        file_path: &str,
+        format: Option<ExportFormat>,
+        columns: Option<Vec<String>>,
+    ) -> PyResult<u64> {
+        let mut writer = create_export_writer(
+            file_path,
+            format,
+            columns,
+            &self.instance.options().return_type,
+        )?;
+        let rows = py.allow_threads(|| -> std::io::Result<u64> {
+            scandir::export_walk(&mut self.instance, &mut writer)?;
+            let rows = writer.rows();
+            writer.finish()?;
+            Ok(rows)
+        })?;
+        Ok(rows)
+    }
+
+    /// Return the results as `pyarrow.Table`. The record batches are built from the results
+    /// while the walk is running and passed to `pyarrow` as Arrow IPC stream.
+    #[cfg(feature = "arrow")]
+    pub fn collect_arrow(
+        &mut self,
+        py: Python,
+        columns: Option<Vec<String>>,
+        batch_size: Option<usize>,
+    ) -> PyResult<PyObject> {
+        let mut writer =
+            create_ipc_writer(columns, batch_size, &self.instance.options().return_type)?;
+        let data = py.allow_threads(|| -> std::io::Result<Vec<u8>> {
+            scandir::export_walk(&mut self.instance, &mut writer)?;
+            writer.finish()?.into_inner().map_err(std::io::Error::other)
+        })?;
+        ipc_to_table(py, &data)
+    }
+
+    /// Write the results to Parquet file `file_path` while the walk is running. Returns
+    /// the number of written entries.
+    #[cfg(feature = "arrow")]
+    pub fn write_parquet(
+        &mut self,
+        py: Python,
+        file_path: &str,
+        columns: Option<Vec<String>>,
+        batch_size: Option<usize>,
+    ) -> PyResult<u64> {
+        let mut writer = create_parquet_writer(
+            file_path,
+            columns,
+            batch_size,
+            &self.instance.options().return_type,
+        )?;
+        let rows = py.allow_threads(|| -> std::io::Result<u64> {
+            scandir::export_walk(&mut self.instance, &mut writer)?;
+            let rows = writer.rows();
+            writer.finish()?;
+            Ok(rows)
+        })?;
+        Ok(rows)
     }
 
     pub fn has_results(&mut self, only_new: Option<bool>) -> bool {
@@ -115,10 +306,42 @@ impl Walk {
         results
     }
 
+    pub fn results_ext(
+        &mut self,
+        return_all: Option<bool>,
+        py: Python,
+    ) -> PyResult<Vec<(String, PyObject, PyObject)>> {
+        let mut results = Vec::new();
+        for result in self.instance.results_ext(return_all.unwrap_or(false)) {
+            let entries = PyDict::new(py);
+            for (name, entry) in result.entries.iter() {
+                entries.set_item(name, PyCell::new(py, DirEntryExt::new(entry))?)?;
+            }
+            results.push((
+                result.path,
+                PyCell::new(py, Toc::new(Some(result.toc)))?.to_object(py),
+                entries.to_object(py),
+            ));
+        }
+        Ok(results)
+    }
+
     pub fn has_errors(&mut self) -> bool {
         self.instance.has_errors()
     }
 
+    pub fn progress(&self) -> Progress {
+        Progress::new(&self.instance.progress())
+    }
+
+    pub fn oversized_dirs(&self) -> Vec<(String, usize)> {
+        self.instance.oversized_dirs()
+    }
+
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.instance.stop_reason().as_ref().map(StopReason::new)
+    }
+
     pub fn duration(&mut self) -> f64 {
         self.instance.duration()
     }
@@ -132,6 +355,7 @@ impl Walk {
     }
 
     fn __enter__(mut slf: PyRefMut<Self>) -> PyResult<PyRefMut<Self>> {
+        slf.prune_error.lock().unwrap().take();
         slf.instance
             .start()
             .map_err(|e| PyException::new_err(e.to_string()))?;
@@ -143,8 +367,9 @@ impl Walk {
         ty: Option<&PyType>,
         _value: Option<&PyAny>,
         _traceback: Option<&PyAny>,
+        py: Python,
     ) -> PyResult<bool> {
-        if !self.instance.stop() {
+        if !py.allow_threads(|| self.instance.stop()) {
             return Ok(false);
         }
         self.instance.join();
@@ -167,6 +392,7 @@ impl Walk {
         if slf.idx < std::usize::MAX {
             return Err(PyRuntimeError::new_err("Busy"));
         }
+        slf.prune_error.lock().unwrap().take();
         slf.instance.start()?;
         slf.entries.clear();
         slf.idx = 0;
@@ -177,7 +403,7 @@ impl Walk {
         loop {
             if let Some((root_dir, toc)) = self.entries.get(self.idx) {
                 self.idx += 1;
-                if self.return_type == ReturnType::Base {
+                if self.return_type != ReturnType::Ext {
                     return Ok(Some(
                         (root_dir, toc.dirs.clone(), toc.files.clone()).to_object(py),
                     ));
@@ -202,13 +428,14 @@ impl Walk {
                     if !self.instance.busy() {
                         break;
                     }
-                    thread::sleep(Duration::from_millis(10));
+                    py.allow_threads(|| thread::sleep(Duration::from_millis(10)));
                     continue;
                 }
                 self.idx = 0;
             }
         }
         self.idx = std::usize::MAX;
+        self.raise_prune_error()?;
         Ok(None)
     }
 
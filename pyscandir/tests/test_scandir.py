@@ -1,11 +1,15 @@
 # -*- coding: utf-8 -*-
 
+import csv
+import json
 import os
+import sqlite3
 import time
 import tempfile
 
 import pytest
-from scandir_rs import Count, Walk, Scandir, ReturnType
+from scandir_rs import Count, Walk, Scandir, TopN, ExportFormat, OversizedAction, Progress, ReturnType, SortBy, \
+    StopReason, TopNKey, TraversalOrder
 
 
 def CreateTempFileTree(dircnt: int, depth: int, filecnt: int):
@@ -35,42 +39,48 @@ def test_count(tempDir):
     count = Count(tempDir.name)
     count.start()
     count.join()
-    assert count.as_dict() == {'dirs': 6, 'files': 180,
-                               'size': 24576, 'usage': 24576}
+    assert count.as_dict() == {'dirs': 6, 'files': 180, 'entries': 186,
+                               'size': 24576, 'usage': 24576,
+                               'unique_size': 24576, 'unique_usage': 24576}
 
 
 def test_count_extended(tempDir):
     count = Count(tempDir.name, return_type=ReturnType.Ext).collect()
-    assert count.as_dict() == {'dirs': 6, 'files': 180,
-                               'size': 24576, 'usage': 24576}
+    assert count.as_dict() == {'dirs': 6, 'files': 180, 'entries': 186,
+                               'size': 24576, 'usage': 24576,
+                               'unique_size': 24576, 'unique_usage': 24576}
 
 
 def test_count_extended_file_exclude(tempDir):
     count = Count(tempDir.name, return_type=ReturnType.Ext,
                   file_exclude=["*.bin"]).collect()
-    assert count.as_dict() == {'dirs': 6, 'files': 120,
-                               'size': 24576, 'usage': 24576}
+    assert count.as_dict() == {'dirs': 6, 'files': 120, 'entries': 126,
+                               'size': 24576, 'usage': 24576,
+                               'unique_size': 24576, 'unique_usage': 24576}
 
 
 def test_count_extended_file_include(tempDir):
     count = Count(tempDir.name, return_type=ReturnType.Ext,
                   file_include=["*.bin"]).collect()
-    assert count.as_dict() == {'dirs': 6, 'files': 60,
-                               'size': 24576, 'usage': 24576}
+    assert count.as_dict() == {'dirs': 6, 'files': 60, 'entries': 66,
+                               'size': 24576, 'usage': 24576,
+                               'unique_size': 24576, 'unique_usage': 24576}
 
 
 def test_count_extended_dir_include(tempDir):
     count = Count(tempDir.name, return_type=ReturnType.Ext,
                   dir_include=["dir0/**"]).collect()
-    assert count.as_dict() == {'dirs': 3, 'files': 90,
-                               'size': 12288, 'usage': 12288}
+    assert count.as_dict() == {'dirs': 3, 'files': 90, 'entries': 93,
+                               'size': 12288, 'usage': 12288,
+                               'unique_size': 12288, 'unique_usage': 12288}
 
 
 def test_count_extended_dir_exclude(tempDir):
     count = Count(tempDir.name, return_type=ReturnType.Ext,
                   dir_exclude=["dir0", "dir1"]).collect()
-    assert count.as_dict() == {'dirs': 1, 'files': 30,
-                               'size': 4096, 'usage': 4096}
+    assert count.as_dict() == {'dirs': 1, 'files': 30, 'entries': 31,
+                               'size': 4096, 'usage': 4096,
+                               'unique_size': 4096, 'unique_usage': 4096}
 
 
 def test_walk_toc(tempDir):
@@ -127,6 +137,66 @@ def test_walk_walk_ext(tempDir):
     assert len(allFiles) == 180
 
 
+def test_walk_bottom_up(tempDir):
+    roots = [root for root, dirs, files in Walk(tempDir.name, topdown=False)]
+    assert len(roots) == 7
+    assert roots[-1] == ""
+    for i, root in enumerate(roots):
+        prefix = root + os.sep if root else ""
+        assert not [r for r in roots[i + 1:] if r.startswith(prefix) and r != root]
+
+
+def test_walk_prune(tempDir):
+    def prune(root, dirs):
+        pruned.append(root)
+        dirs.clear()
+
+    pruned = []
+    assert list(Walk(tempDir.name, prune=prune)) == []
+    assert pruned == [""]
+
+    def prune_dirs(root, dirs):
+        if root == "":
+            dirs[:] = [d for d in dirs if d == "dir1"]
+
+    toc = Walk(tempDir.name, prune=prune_dirs).collect()
+    assert sorted(toc.dirs) == ["dir1", os.path.join("dir1", "dir0")]
+    assert len(toc.files) == 60
+
+    def prune_error(root, dirs):
+        raise KeyError(root)
+
+    with pytest.raises(KeyError):
+        Walk(tempDir.name, prune=prune_error).collect()
+    with pytest.raises(KeyError):
+        list(Walk(tempDir.name, prune=prune_error))
+    with pytest.raises(ValueError):
+        Walk(tempDir.name, topdown=False, prune=prune_error)
+
+
+def test_walk_results_ext(tempDir):
+    sd = Walk(tempDir.name, return_type=ReturnType.Ext)
+    sd.start()
+    sd.join()
+    allDirs = []
+    allFiles = []
+    for root, toc, entries in sd.results_ext(True):
+        assert sorted(entries.keys()) == sorted(toc.dirs + toc.files)
+        for name in toc.files:
+            entry = entries[name]
+            assert entry.is_file
+            assert entry.st_size == os.stat(os.path.join(tempDir.name, root, name)).st_size
+            assert entry.path == os.path.join(root, name) if root else name
+        allDirs.extend(toc.dirs)
+        allFiles.extend(toc.files)
+    assert len(allDirs) == 6
+    assert len(allFiles) == 180
+    sd = Walk(tempDir.name, return_type=ReturnType.Base)
+    sd.start()
+    sd.join()
+    assert sd.results_ext(True) == []
+
+
 def test_scandir_fast(tempDir):
     sd = Scandir(tempDir.name, return_type=ReturnType.Base)
     contents = {}
@@ -139,6 +209,493 @@ def test_scandir_fast(tempDir):
     assert len(contents) == 186
 
 
+def test_scandir_sort_by():
+    tmpDir = tempfile.TemporaryDirectory(prefix="scandir_rs_sort_")
+    root = tmpDir.name
+    os.makedirs(f"{root}/sub")
+    for fn, size in (("file10", 3), ("File2", 1), ("file1", 2), ("sub/b", 0), ("sub/a", 1)):
+        with open(f"{root}/{fn}", "wb") as F:
+            F.write(b"x" * size)
+    try:
+        def paths(**kwargs):
+            entries, errors = Scandir(root, **kwargs).collect()
+            assert errors == []
+            return [e.path for e in entries]
+
+        sub_a, sub_b = os.path.join("sub", "a"), os.path.join("sub", "b")
+        assert paths(sort_by=SortBy.Name) == ["File2", "file1", "file10", "sub", sub_a, sub_b]
+        assert paths(sort_by=SortBy.NameCaseInsensitive) == ["file1", "file10", "File2", "sub", sub_a, sub_b]
+        assert paths(sort_by=SortBy.NameNatural, dirs_first=True) == ["sub", sub_a, sub_b, "File2", "file1", "file10"]
+        assert paths(sort_by=SortBy.Size, dirs_first=True) == ["sub", sub_b, sub_a, "File2", "file1", "file10"]
+        for _ in range(10):
+            assert paths(sorted=True) == paths(sort_by=SortBy.Name)
+        # Iterating returns the same order as collect
+        for kwargs in ({"sort_by": SortBy.Name}, {"sort_by": SortBy.Size, "dirs_first": True}):
+            assert [e.path for e in Scandir(root, **kwargs)] == paths(**kwargs)
+        toc = Walk(root, sort_by=SortBy.Size, dirs_first=True).collect()
+        assert toc.dirs == ["sub"]
+        assert toc.files == ["File2", "file1", "file10", sub_b, sub_a]
+    finally:
+        tmpDir.cleanup()
+
+
+@pytest.fixture(scope="session")
+def orderDir():
+    tmpDir = tempfile.TemporaryDirectory(prefix="scandir_rs_order_")
+    for path in ("a/x/y", "b", "c"):
+        os.makedirs(os.path.join(tmpDir.name, path))
+    for path in ("a/a1", "a/x/x1", "a/x/y/y1", "b/b1", "f0"):
+        open(os.path.join(tmpDir.name, path), "w").close()
+    yield tmpDir
+    tmpDir.cleanup()
+
+
+def test_scandir_order(orderDir):
+    def paths(**kwargs):
+        entries, errors = Scandir(orderDir.name, sorted=True, **kwargs).collect()
+        assert errors == []
+        return [e.path.replace(os.sep, "/") for e in entries]
+
+    assert paths(order=TraversalOrder.DepthFirst) == [
+        "a", "a/a1", "a/x", "a/x/x1", "a/x/y", "a/x/y/y1", "b", "b/b1", "c", "f0"]
+    assert paths(order=TraversalOrder.BreadthFirst) == [
+        "a", "b", "c", "f0", "a/a1", "a/x", "b/b1", "a/x/x1", "a/x/y", "a/x/y/y1"]
+    assert paths(order=TraversalOrder.BreadthFirst, max_depth=2) == [
+        "a", "b", "c", "f0", "a/a1", "a/x", "b/b1"]
+    # Iterating returns the same order as collect
+    for order in (TraversalOrder.DepthFirst, TraversalOrder.BreadthFirst):
+        iterated = [e.path.replace(os.sep, "/") for e in Scandir(orderDir.name, sorted=True, order=order)]
+        assert iterated == paths(order=order)
+    entries, errors = Scandir(orderDir.name, order=TraversalOrder.BreadthFirst).collect()
+    depths = [e.path.count(os.sep) for e in entries]
+    assert depths == sorted(depths)
+
+
+def test_walk_order(orderDir):
+    def roots(**kwargs):
+        return [root.replace(os.sep, "/") for root, dirs, files in Walk(orderDir.name, sorted=True, **kwargs)]
+
+    assert roots(order=TraversalOrder.DepthFirst) == ["", "a", "a/x", "a/x/y", "b"]
+    assert roots(order=TraversalOrder.BreadthFirst) == ["", "a", "b", "a/x", "a/x/y"]
+
+
+def test_scandir_min_depth(orderDir):
+    def paths(**kwargs):
+        entries, errors = Scandir(orderDir.name, order=TraversalOrder.DepthFirst, sorted=True, **kwargs).collect()
+        assert errors == []
+        return [e.path.replace(os.sep, "/") for e in entries]
+
+    assert paths(min_depth=2) == ["a/a1", "a/x", "a/x/x1", "a/x/y", "a/x/y/y1", "b/b1"]
+    assert paths(min_depth=2, max_depth=2) == ["a/a1", "a/x", "b/b1"]
+    assert paths(include_root=True)[:2] == ["", "a"]
+    assert paths(include_root=True, min_depth=1) == paths()
+    entries, errors = Scandir(orderDir.name, include_root=True, return_type=ReturnType.Ext).collect()
+    root = [e for e in entries if e.path == ""]
+    assert len(root) == 1
+    assert root[0].is_dir
+    assert root[0].st_ino == os.stat(orderDir.name).st_ino
+    assert len(entries) == 11
+
+
+def test_count_min_depth(orderDir):
+    count = Count(orderDir.name).collect()
+    assert (count.dirs, count.files) == (5, 5)
+    count = Count(orderDir.name, include_root=True).collect()
+    assert (count.dirs, count.files) == (6, 5)
+    count = Count(orderDir.name, min_depth=3).collect()
+    assert (count.dirs, count.files) == (1, 2)
+    toc = Walk(orderDir.name, min_depth=3).collect()
+    assert sorted(toc.dirs) == [os.path.join("a", "x", "y")]
+    assert sorted(toc.files) == [os.path.join("a", "x", "x1"), os.path.join("a", "x", "y", "y1")]
+
+
+def test_scandir_budgets(tempDir):
+    sd = Scandir(tempDir.name, max_file_cnt=5)
+    entries, errors = sd.collect()
+    assert len([e for e in entries if e.is_file]) == 5
+    assert sd.stop_reason() == StopReason.MaxFileCnt
+    sd = Scandir(tempDir.name, max_entries=7)
+    entries, errors = sd.collect()
+    assert len(entries) == 7
+    assert sd.stop_reason() == StopReason.MaxEntries
+    sd = Scandir(tempDir.name, max_dirs=2, order=TraversalOrder.DepthFirst)
+    entries, errors = sd.collect()
+    assert len([e for e in entries if e.is_dir]) == 2
+    assert sd.stop_reason() == StopReason.MaxDirs
+    sd = Scandir(tempDir.name, timeout=0.0)
+    entries, errors = sd.collect()
+    assert not entries
+    assert sd.stop_reason() == StopReason.Timeout
+    sd = Scandir(tempDir.name)
+    sd.collect()
+    assert sd.stop_reason() is None
+
+
+def test_max_total_bytes():
+    tmpDir = tempfile.TemporaryDirectory(prefix="scandir_rs_budget_")
+    root = tmpDir.name
+    for i in range(5):
+        with open(f"{root}/file{i}.dat", "wb") as F:
+            F.write(b"x" * 10)
+    try:
+        sd = Scandir(root, max_total_bytes=25)
+        entries, errors = sd.collect()
+        assert len(entries) == 2
+        assert sd.stop_reason() == StopReason.MaxTotalBytes
+        count = Count(root, max_total_bytes=25).collect()
+        assert count.size == 20
+        assert count.stop_reason == StopReason.MaxTotalBytes
+    finally:
+        tmpDir.cleanup()
+
+
+def test_walk_count_budgets(tempDir):
+    sd = Walk(tempDir.name, max_file_cnt=5)
+    toc = sd.collect()
+    assert len(toc.files) == 5
+    assert sd.stop_reason() == StopReason.MaxFileCnt
+    count = Count(tempDir.name, max_file_cnt=10).collect()
+    assert count.files == 10
+    assert count.stop_reason == StopReason.MaxFileCnt
+    count = Count(tempDir.name).collect()
+    assert count.stop_reason is None
+
+
+def test_max_dir_entries():
+    tmpDir = tempfile.TemporaryDirectory(prefix="scandir_rs_oversized_")
+    root = tmpDir.name
+    os.makedirs(f"{root}/big")
+    os.makedirs(f"{root}/small")
+    for i in range(20):
+        open(f"{root}/big/file{i}.txt", "wb").close()
+    for i in range(3):
+        open(f"{root}/small/file{i}.txt", "wb").close()
+    sep = os.path.sep
+    try:
+        sd = Scandir(root, max_dir_entries=10)
+        entries, errors = sd.collect()
+        assert len([e for e in entries if e.path.startswith(f"big{sep}")]) == 10
+        assert len([e for e in entries if e.path.startswith(f"small{sep}")]) == 3
+        assert sd.oversized_dirs() == [("big", 20)]
+        # Truncation keeps the first entries in sort order
+        entries, errors = Scandir(root, max_dir_entries=10, sort_by=SortBy.NameNatural).collect()
+        assert [e.path for e in entries if e.path.startswith(f"big{sep}")] == \
+            [f"big{sep}file{i}.txt" for i in range(10)]
+        toc = Walk(root, max_dir_entries=10, sort_by=SortBy.NameNatural).collect()
+        assert [f for f in toc.files if f.startswith(f"big{sep}")] == \
+            [f"big{sep}file{i}.txt" for i in range(10)]
+        sd = Scandir(root, max_dir_entries=10, oversized_action=OversizedAction.Skip)
+        entries, errors = sd.collect()
+        assert sorted(e.path for e in entries if not e.path.startswith(f"small{sep}")) == ["big", "small"]
+        assert sd.oversized_dirs() == [("big", 20)]
+        sd = Walk(root, max_dir_entries=10)
+        toc = sd.collect()
+        assert len(toc.files) == 13
+        assert sd.oversized_dirs() == [("big", 20)]
+        count = Count(root, max_dir_entries=10, oversized_action=OversizedAction.Skip).collect()
+        assert count.files == 3
+        assert count.oversized_dirs == [("big", 20)]
+    finally:
+        tmpDir.cleanup()
+
+
+def test_threads(tempDir):
+    for threads in (0, 1, 4):
+        count = Count(tempDir.name, threads=threads).collect()
+        assert count.as_dict() == {'dirs': 6, 'files': 180, 'entries': 186,
+                                   'size': 24576, 'usage': 24576,
+                                   'unique_size': 24576, 'unique_usage': 24576}
+        entries, errors = Scandir(tempDir.name, threads=threads).collect()
+        assert len(entries) == 186
+        toc = Walk(tempDir.name, threads=threads).collect()
+        assert len(toc.files) == 180
+        assert len(TopN(tempDir.name, 5, threads=threads).collect()) == 5
+
+
+def test_progress(tempDir):
+    for cls in (Scandir, Walk):
+        reports = []
+        instance = cls(tempDir.name, progress_callback=reports.append, progress_interval=0.0)
+        assert instance.progress().dirs == 0
+        instance.collect()
+        progress = instance.progress()
+        assert isinstance(progress, Progress)
+        assert progress.dirs == 7
+        assert progress.entries == 186
+        assert progress.errors == 0
+        assert progress.pending_dirs == 0
+        assert progress.elapsed > 0.0
+        assert reports
+        assert reports[-1].dirs == progress.dirs
+        assert reports[-1].entries == progress.entries
+        assert all(a.entries <= b.entries for a, b in zip(reports, reports[1:]))
+
+
+def test_progress_iter(tempDir):
+    for cls in (Scandir, Walk):
+        reports = []
+        results = list(cls(tempDir.name, progress_callback=reports.append, progress_interval=0.0))
+        assert results
+        assert reports
+        assert reports[-1].entries == 186
+
+
+def test_pause_resume(tempDir):
+    sd = Scandir(tempDir.name)
+    sd.pause()
+    assert sd.paused()
+    sd.start()
+    time.sleep(0.1)
+    assert sd.busy()
+    assert sd.results_cnt(True) == 0
+    sd.resume()
+    assert not sd.paused()
+    sd.join()
+    assert sd.results_cnt(True) == 186
+    walk = Walk(tempDir.name)
+    walk.pause()
+    walk.start()
+    time.sleep(0.1)
+    assert walk.busy()
+    walk.resume()
+    toc = walk.collect()
+    assert len(toc.files) == 180
+    count = Count(tempDir.name)
+    count.pause()
+    count.start()
+    time.sleep(0.1)
+    assert count.busy()
+    # Stopping resumes the paused workers
+    count.stop()
+    assert not count.busy()
+    # The paused time does not count for the timeout, the duration and the progress
+    sd = Scandir(tempDir.name, timeout=0.2)
+    sd.pause()
+    sd.start()
+    time.sleep(0.3)
+    sd.resume()
+    sd.join()
+    assert sd.stop_reason() is None
+    assert sd.results_cnt(True) == 186
+    assert sd.duration() < 0.2
+    assert sd.progress().elapsed < 0.2
+
+
+def test_rate_limits(tempDir):
+    sd = Scandir(tempDir.name, max_dirs_per_sec=20)
+    entries, errors = sd.collect()
+    assert len(entries) == 186
+    assert sd.duration() >= 0.25
+    count = Count(tempDir.name, max_stats_per_sec=1000).collect()
+    assert count.files == 180
+    assert count.duration >= 0.15
+    toc = Walk(tempDir.name, max_dirs_per_sec=20).collect()
+    assert len(toc.files) == 180
+
+
+def test_low_priority(tempDir):
+    nice = os.nice(0) if os.name != "nt" else 0
+    for threads in (0, 1, 4):
+        entries, errors = Scandir(tempDir.name, threads=threads, idle_io_priority=True, nice=10).collect()
+        assert len(entries) == 186
+        count = Count(tempDir.name, threads=threads, idle_io_priority=True, nice=10).collect()
+        assert count.files == 180
+    # Only the scanning threads are changed
+    if os.name != "nt":
+        assert os.nice(0) == nice
+    # Raising the priority needs privileges, so it fails when the scan is started
+    if os.name != "nt" and os.uname().sysname == "Linux" and os.geteuid() != 0:
+        with pytest.raises(PermissionError):
+            Scandir(tempDir.name, nice=-5).collect()
+        with pytest.raises(PermissionError):
+            Count(tempDir.name, nice=-5).collect()
+
+
+def test_checkpoint(tempDir):
+    tmpDir = tempfile.TemporaryDirectory(prefix="scandir_rs_checkpoint_")
+    checkpoint = f"{tmpDir.name}/scan.checkpoint"
+    try:
+        all_paths = {e.path for e in Scandir(tempDir.name).collect()[0]}
+        # A finished scan has no pending directories
+        entries, errors = Scandir(tempDir.name, checkpoint=checkpoint).collect()
+        assert len(entries) == 186
+        with open(checkpoint) as F:
+            lines = F.read().splitlines()
+        assert "completed 7" in lines
+        assert not [line for line in lines if line.startswith("pending")]
+        entries, errors = Scandir(tempDir.name, resume_from=checkpoint).collect()
+        assert entries == []
+        # Resuming a scan stopped by a budget returns the remaining entries
+        entries1, errors = Scandir(tempDir.name, max_entries=50, threads=1, checkpoint=checkpoint).collect()
+        assert len(entries1) == 50
+        entries2, errors = Scandir(tempDir.name, resume_from=checkpoint, checkpoint=checkpoint).collect()
+        assert len(entries2) < 186
+        assert {e.path for e in entries1} | {e.path for e in entries2} == all_paths
+        # Handcrafted checkpoint with completed root and dir0
+        root = os.path.realpath(tempDir.name)
+        with open(checkpoint, "w") as F:
+            F.write(f"scandir-checkpoint 1\nroot {root}\ncompleted 4\npending dir1\npending dir2\n")
+        entries, errors = Scandir(tempDir.name, resume_from=checkpoint).collect()
+        assert len(entries) == 91
+        assert all(e.path.startswith(("dir1", "dir2")) for e in entries)
+        with open(checkpoint, "a") as F:
+            F.write("statistics\ndirs 5\nfiles 90\nentries 95\n")
+        count = Count(tempDir.name, resume_from=checkpoint, checkpoint=checkpoint).collect()
+        assert count.dirs == 6
+        assert count.files == 180
+        assert count.entries == 186
+        with open(checkpoint) as F:
+            lines = F.read().splitlines()
+        assert "completed 7" in lines
+        assert "files 180" in lines
+        with pytest.raises(ValueError):
+            Scandir(tmpDir.name, resume_from=checkpoint)
+        with pytest.raises(OSError):
+            Scandir(tempDir.name, order=TraversalOrder.BreadthFirst, checkpoint=checkpoint).collect()
+    finally:
+        tmpDir.cleanup()
+
+
+def test_export(tempDir):
+    tmpDir = tempfile.TemporaryDirectory(prefix="scandir_rs_export_")
+    try:
+        sd = Scandir(tempDir.name, store=False)
+        assert sd.export(f"{tmpDir.name}/scan.ndjson") == 186
+        assert sd.results_cnt(True) == 0
+        with open(f"{tmpDir.name}/scan.ndjson") as F:
+            rows = [json.loads(line) for line in F]
+        assert len(rows) == 186
+        assert sum(row["is_file"] for row in rows) == 180
+        assert set(rows[0]) == {"path", "is_symlink", "is_dir", "is_file", "st_ctime", "st_mtime", "st_atime",
+                                "st_size", "error"}
+        sd = Scandir(tempDir.name, return_type=ReturnType.Ext)
+        assert sd.export(f"{tmpDir.name}/scan.csv", ExportFormat.Csv, ["path", "st_size", "st_ino"]) == 186
+        with open(f"{tmpDir.name}/scan.csv", newline="") as F:
+            rows = list(csv.DictReader(F))
+        assert len(rows) == 186
+        assert all(int(row["st_ino"]) > 0 for row in rows)
+        walk = Walk(tempDir.name, return_type=ReturnType.Ext)
+        assert walk.export(f"{tmpDir.name}/walk.msgpack", ExportFormat.MessagePack) == 186
+        assert os.path.getsize(f"{tmpDir.name}/walk.msgpack") > 0
+        with pytest.raises(ValueError):
+            Walk(tempDir.name).export(f"{tmpDir.name}/walk.csv", ExportFormat.Csv, ["unknown"])
+    finally:
+        tmpDir.cleanup()
+
+
+@pytest.mark.skipif(not hasattr(Scandir, "write_parquet"), reason="Requires feature arrow")
+def test_parquet(tempDir):
+    tmpDir = tempfile.TemporaryDirectory(prefix="scandir_rs_parquet_")
+    try:
+        sd = Scandir(tempDir.name, store=False)
+        assert sd.write_parquet(f"{tmpDir.name}/scan.parquet", batch_size=50) == 186
+        assert sd.results_cnt(True) == 0
+        walk = Walk(tempDir.name, return_type=ReturnType.Ext)
+        assert walk.write_parquet(f"{tmpDir.name}/walk.parquet", ["path", "st_size", "st_mode"]) == 186
+        for name in ("scan.parquet", "walk.parquet"):
+            with open(f"{tmpDir.name}/{name}", "rb") as F:
+                data = F.read()
+            assert data[:4] == b"PAR1" and data[-4:] == b"PAR1"
+        with pytest.raises(ValueError):
+            Scandir(tempDir.name).write_parquet(f"{tmpDir.name}/scan.parquet", ["unknown"])
+        try:
+            import pyarrow.parquet
+        except ImportError:
+            return
+        table = pyarrow.parquet.read_table(f"{tmpDir.name}/walk.parquet")
+        assert table.num_rows == 186
+        assert table.column_names == ["path", "st_size", "st_mode"]
+    finally:
+        tmpDir.cleanup()
+
+
+@pytest.mark.skipif(not hasattr(Scandir, "write_parquet"), reason="Requires feature arrow")
+def test_collect_arrow(tempDir):
+    pyarrow = pytest.importorskip("pyarrow")
+    table = Scandir(tempDir.name, return_type=ReturnType.Ext).collect_arrow(batch_size=50)
+    assert isinstance(table, pyarrow.Table)
+    assert table.num_rows == 186
+    assert table.schema.field("st_size").type == pyarrow.uint64()
+    assert table.schema.field("st_mode").type == pyarrow.uint32()
+    assert sum(table.column("is_file").to_pylist()) == 180
+    table = Walk(tempDir.name).collect_arrow(["path", "is_dir"])
+    assert table.num_rows == 186
+    assert table.column_names == ["path", "is_dir"]
+    assert sum(table.column("is_dir").to_pylist()) == 6
+
+
+@pytest.mark.skipif(not hasattr(Scandir, "write_sqlite"), reason="Requires feature sqlite")
+def test_sqlite(tempDir):
+    tmpDir = tempfile.TemporaryDirectory(prefix="scandir_rs_sqlite_")
+    try:
+        dbPath = f"{tmpDir.name}/index.db"
+        sd = Scandir(tempDir.name, return_type=ReturnType.Ext, store=False)
+        assert sd.write_sqlite(dbPath, batch_size=50) == 186
+        with sqlite3.connect(dbPath) as conn:
+            assert conn.execute("PRAGMA user_version").fetchone()[0] == 1
+            assert conn.execute("SELECT COUNT(*) FROM files").fetchone()[0] == 180
+            assert conn.execute("SELECT COUNT(*) FROM directories").fetchone()[0] == 7
+            assert conn.execute("SELECT total_files, total_dirs FROM directories WHERE path = ''").fetchone() == \
+                (180, 6)
+            assert conn.execute("SELECT files, dirs FROM directories WHERE path = 'dir0'").fetchone() == (30, 1)
+        conn.close()
+        # Update the existing index
+        assert Scandir(tempDir.name).write_sqlite(dbPath) == 186
+        with sqlite3.connect(dbPath) as conn:
+            assert conn.execute("SELECT COUNT(*) FROM files").fetchone()[0] == 180
+            assert conn.execute("SELECT value FROM meta WHERE key = 'generation'").fetchone()[0] == "2"
+        conn.close()
+        with pytest.raises(ValueError):
+            Scandir(tmpDir.name).write_sqlite(dbPath)
+    finally:
+        tmpDir.cleanup()
+
+
+@pytest.mark.skipif(os.name == "nt", reason="Hardlinks are not supported")
+def test_count_hardlinks():
+    tmpDir = tempfile.TemporaryDirectory(prefix="scandir_rs_hardlinks_")
+    root = tmpDir.name
+    with open(f"{root}/file.dat", "wb") as F:
+        F.write(b"x" * 100)
+    os.link(f"{root}/file.dat", f"{root}/link1.dat")
+    os.link(f"{root}/file.dat", f"{root}/link2.dat")
+    with open(f"{root}/other.dat", "wb") as F:
+        F.write(b"x" * 50)
+    try:
+        for hardlink_memory in (0, 1024):
+            count = Count(root, return_type=ReturnType.Ext, hardlink_memory=hardlink_memory).collect()
+            assert count.files == 4
+            assert count.hlinks == 2
+            assert count.size == 350
+            assert count.unique_size == 150
+            assert count.usage == 4 * 4096
+            assert count.unique_usage == 2 * 4096
+            assert count.as_dict()["unique_size"] == 150
+        # Hardlinks are detected with histograms, but not with ReturnType.Base only
+        assert Count(root, histograms=True).collect().unique_size == 150
+        count = Count(root).collect()
+        assert count.size == 350
+        assert count.hlinks == 0
+        assert count.unique_size == 350
+    finally:
+        tmpDir.cleanup()
+
+
+def test_scandir_fast_no_metadata(tempDir):
+    entries, errors = Scandir(tempDir.name, return_type=ReturnType.Fast).collect()
+    assert errors == []
+    assert len(entries) == 186
+    assert len([e for e in entries if e.is_dir]) == 6
+    for dirEntry in entries:
+        assert dirEntry.st_mtime == 0.0
+        assert dirEntry.st_ctime == 0.0
+
+
+def test_count_fast(tempDir):
+    count = Count(tempDir.name, return_type=ReturnType.Fast).collect()
+    assert count.as_dict() == {'dirs': 6, 'files': 180, 'entries': 186}
+
+
 def test_scandir_ext(tempDir):
     sd = Scandir(tempDir.name, return_type=ReturnType.Ext)
     contents = {}
@@ -149,3 +706,124 @@ def test_scandir_ext(tempDir):
         assert hasattr(dirEntry, "st_mode")
         contents[dirEntry.path] = dirEntry
     assert len(contents) == 186
+
+
+@pytest.fixture(scope="session")
+def linkDir():
+    tmpDir = tempfile.TemporaryDirectory(prefix="scandir_rs_links_")
+    with open(f"{tmpDir.name}/target.txt", "wb") as F:
+        F.write(b"0123456789")
+    os.symlink("target.txt", f"{tmpDir.name}/good_link")
+    os.symlink("missing.txt", f"{tmpDir.name}/broken_link")
+    yield tmpDir
+    tmpDir.cleanup()
+
+
+def test_scandir_read_links(linkDir):
+    sd = Scandir(linkDir.name, return_type=ReturnType.Ext,
+                 read_links=True, link_metadata=True)
+    entries = {dirEntry.path: dirEntry for dirEntry in sd.collect()[0]}
+    assert entries["target.txt"].link_target is None
+    good = entries["good_link"].link_target
+    assert good.path == "target.txt"
+    assert not good.is_dangling
+    assert good.is_file
+    assert good.st_size == 10
+    broken = entries["broken_link"].link_target
+    assert broken.path == "missing.txt"
+    assert broken.is_dangling
+    assert broken.st_size is None
+
+
+def test_walk_broken_symlinks(linkDir):
+    toc = Walk(linkDir.name, read_links=True).collect()
+    assert toc.files == ["target.txt"]
+    assert toc.symlinks == ["good_link"]
+    assert toc.broken_symlinks == ["broken_link"]
+
+
+def test_scandir_resolve_owners(tempDir):
+    import pwd
+    import grp
+    owner = pwd.getpwuid(os.getuid()).pw_name
+    group = grp.getgrgid(os.getgid()).gr_name
+    entries, errors = Scandir(tempDir.name, return_type=ReturnType.Ext,
+                              resolve_owners=True).collect()
+    assert not errors
+    assert len(entries) == 186
+    for dirEntry in entries:
+        assert dirEntry.owner == owner
+        assert dirEntry.group == group
+
+
+def test_count_resolve_owners(tempDir):
+    import pwd
+    owner = pwd.getpwuid(os.getuid()).pw_name
+    count = Count(tempDir.name, resolve_owners=True).collect()
+    assert count.owners == {owner: {'count': 186,
+                                    'size': 24576, 'usage': 24576}}
+
+
+def test_count_histograms(tempDir):
+    count = Count(tempDir.name, histograms=True).collect()
+    assert count.extensions == {
+        'bin': {'count': 60, 'size': 0, 'usage': 0},
+        'txt': {'count': 60, 'size': 0, 'usage': 0},
+        'log': {'count': 60, 'size': 0, 'usage': 0}}
+    assert count.uids == {os.getuid(): {'count': 180, 'size': 0, 'usage': 0}}
+    assert count.ages == {0: {'count': 180, 'size': 0, 'usage': 0}}
+    assert count.sizes == {0: {'count': 180, 'size': 0, 'usage': 0}}
+    assert count.as_dict()["sizes"] == count.sizes
+
+
+def test_topn():
+    tmpDir = tempfile.TemporaryDirectory(prefix="scandir_rs_topn_")
+    root = tmpDir.name
+    for fn, size in enumerate((10, 50, 20, 40, 30)):
+        with open(f"{root}/file{fn}.dat", "wb") as F:
+            F.write(b"x" * size)
+        os.utime(f"{root}/file{fn}.dat", (1000000 + fn, 1000000 + fn))
+    try:
+        entries = TopN(root, 3).collect()
+        assert [e.st_size for e in entries] == [50, 40, 30]
+        entries = TopN(root, 2, reverse=True).collect()
+        assert [e.st_size for e in entries] == [10, 20]
+        entries = TopN(root, 2, key=TopNKey.Mtime).collect()
+        assert [e.path for e in entries] == ["file4.dat", "file3.dat"]
+        entries = TopN(root, 1, key=TopNKey.Mtime, reverse=True).collect()
+        assert [e.path for e in entries] == ["file0.dat"]
+    finally:
+        tmpDir.cleanup()
+
+
+@pytest.mark.skipif(os.name == "nt" or os.geteuid() == 0, reason="Requires unprivileged user")
+def test_scandir_errors():
+    tmpDir = tempfile.TemporaryDirectory(prefix="scandir_rs_errors_")
+    root = tmpDir.name
+    os.makedirs(f"{root}/denied/subdir")
+    with open(f"{root}/file.txt", "w") as F:
+        F.write("x")
+    os.chmod(f"{root}/denied", 0)
+    try:
+        entries, errors = Scandir(root).collect()
+        assert sorted(e.path for e in entries) == ["denied", "file.txt"]
+        assert len(errors) == 1
+        assert errors[0][0] == "denied"
+        assert "ermission denied" in errors[0][1]
+        count = Count(root).collect()
+        assert count.errors_cnt == 1
+        assert count.errors[0].startswith("denied: ")
+        topn = TopN(root, 5)
+        assert [e.path for e in topn.collect()] == ["file.txt"]
+        assert topn.has_errors()
+        assert [path for path, _ in topn.errors()] == ["denied"]
+    finally:
+        os.chmod(f"{root}/denied", 0o755)
+        tmpDir.cleanup()
+
+
+def test_count_entries(tempDir):
+    count = Count(tempDir.name).collect()
+    assert count.entries == 186
+    assert count.errors_cnt == 0
+    assert count.dirs + count.files == count.entries
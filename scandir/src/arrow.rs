@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::io::{Error, Write};
+use std::sync::Arc;
+
+use arrow_array::builder::{
+    BooleanBuilder, Float64Builder, StringBuilder, UInt32Builder, UInt64Builder,
+};
+use arrow_array::ArrayRef;
+use arrow_schema::{Field, Schema};
+
+pub use arrow_array::RecordBatch;
+pub use arrow_ipc::writer::StreamWriter;
+pub use arrow_schema::{DataType, SchemaRef};
+pub use parquet::arrow::ArrowWriter;
+
+use crate::def::{DirEntryExt, ExportColumn, ScandirResult, Toc};
+use crate::export::{for_each_toc_row, ExportSink, Row, Value};
+
+/// Default number of rows of a record batch
+pub const DEFAULT_BATCH_SIZE: usize = 8192;
+
+/// Arrow data type of `column`
+pub fn data_type(column: ExportColumn) -> DataType {
+    match column {
+        ExportColumn::Path
+        | ExportColumn::Owner
+        | ExportColumn::Group
+        | ExportColumn::LinkTarget
+        | ExportColumn::Error => DataType::Utf8,
+        ExportColumn::IsSymlink | ExportColumn::IsDir | ExportColumn::IsFile => DataType::Boolean,
+        ExportColumn::StCtime | ExportColumn::StMtime | ExportColumn::StAtime => DataType::Float64,
+        ExportColumn::StMode | ExportColumn::StUid | ExportColumn::StGid => DataType::UInt32,
+        ExportColumn::StIno
+        | ExportColumn::StDev
+        | ExportColumn::StNlink
+        | ExportColumn::StSize
+        | ExportColumn::StBlksize
+        | ExportColumn::StBlocks
+        | ExportColumn::StRdev => DataType::UInt64,
+    }
+}
+
+/// Arrow schema of record batches with `columns`. All fields are nullable, because not
+/// all columns have a value for every entry.
+pub fn schema(columns: &[ExportColumn]) -> SchemaRef {
+    Arc::new(Schema::new(
+        columns
+            .iter()
+            .map(|column| Field::new(column.name(), data_type(*column), true))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+#[derive(Debug)]
+enum ColumnBuilder {
+    Utf8(StringBuilder),
+    Boolean(BooleanBuilder),
+    Float64(Float64Builder),
+    UInt32(UInt32Builder),
+    UInt64(UInt64Builder),
+}
+
+impl ColumnBuilder {
+    fn new(column: ExportColumn, capacity: usize) -> Self {
+        match data_type(column) {
+            DataType::Utf8 => ColumnBuilder::Utf8(StringBuilder::with_capacity(capacity, 0)),
+            DataType::Boolean => ColumnBuilder::Boolean(BooleanBuilder::with_capacity(capacity)),
+            DataType::Float64 => ColumnBuilder::Float64(Float64Builder::with_capacity(capacity)),
+            DataType::UInt32 => ColumnBuilder::UInt32(UInt32Builder::with_capacity(capacity)),
+            _ => ColumnBuilder::UInt64(UInt64Builder::with_capacity(capacity)),
+        }
+    }
+
+    fn append(&mut self, value: Value) {
+        match (self, value) {
+            (ColumnBuilder::Utf8(builder), Value::Str(v)) => builder.append_value(v),
+            (ColumnBuilder::Utf8(builder), _) => builder.append_null(),
+            (ColumnBuilder::Boolean(builder), Value::Bool(v)) => builder.append_value(v),
+            (ColumnBuilder::Boolean(builder), _) => builder.append_null(),
+            (ColumnBuilder::Float64(builder), Value::F64(v)) => builder.append_value(v),
+            (ColumnBuilder::Float64(builder), _) => builder.append_null(),
+            (ColumnBuilder::UInt32(builder), Value::U64(v)) => builder.append_value(v as u32),
+            (ColumnBuilder::UInt32(builder), _) => builder.append_null(),
+            (ColumnBuilder::UInt64(builder), Value::U64(v)) => builder.append_value(v),
+            (ColumnBuilder::UInt64(builder), _) => builder.append_null(),
+        }
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            ColumnBuilder::Utf8(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Boolean(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Float64(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::UInt32(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::UInt64(builder) => Arc::new(builder.finish()),
+        }
+    }
+}
+
+/// Destination of record batches
+pub trait BatchTarget {
+    /// Write a complete record batch.
+    fn write_batch(&mut self, batch: RecordBatch) -> Result<(), Error>;
+
+    /// Called after the last record batch was written.
+    fn close(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Keeps all record batches in memory.
+impl BatchTarget for Vec<RecordBatch> {
+    fn write_batch(&mut self, batch: RecordBatch) -> Result<(), Error> {
+        self.push(batch);
+        Ok(())
+    }
+}
+
+/// Writes a Parquet file. Each record batch is written as a row group.
+impl<W: Write + Send> BatchTarget for ArrowWriter<W> {
+    fn write_batch(&mut self, batch: RecordBatch) -> Result<(), Error> {
+        self.write(&batch).map_err(Error::other)?;
+        self.flush().map_err(Error::other)
+    }
+
+    fn close(&mut self) -> Result<(), Error> {
+        self.finish().map_err(Error::other)?;
+        Ok(())
+    }
+}
+
+/// Writes the Arrow IPC streaming format.
+impl<W: Write> BatchTarget for StreamWriter<W> {
+    fn write_batch(&mut self, batch: RecordBatch) -> Result<(), Error> {
+        self.write(&batch).map_err(Error::other)
+    }
+
+    fn close(&mut self) -> Result<(), Error> {
+        self.finish().map_err(Error::other)
+    }
+}
+
+/// Builds Arrow record batches from scan results. The values are appended directly to
+/// the column builders and a record batch is passed to the target as soon as it has
+/// `batch_size` rows, so at most one batch is kept in memory.
+#[derive(Debug)]
+pub struct RecordBatchWriter<T: BatchTarget> {
+    target: T,
+    columns: Vec<ExportColumn>,
+    schema: SchemaRef,
+    builders: Vec<ColumnBuilder>,
+    batch_size: usize,
+    batch_rows: usize,
+    rows: u64,
+}
+
+impl RecordBatchWriter<Vec<RecordBatch>> {
+    /// Create a writer which keeps the record batches in memory.
+    pub fn memory(columns: Vec<ExportColumn>) -> Self {
+        RecordBatchWriter::new(Vec::new(), columns)
+    }
+}
+
+impl<W: Write + Send> RecordBatchWriter<ArrowWriter<W>> {
+    /// Create a writer for a Parquet file. For large exports `writer` should be buffered.
+    pub fn parquet(writer: W, columns: Vec<ExportColumn>) -> Result<Self, Error> {
+        let target = ArrowWriter::try_new(writer, schema(&columns), None).map_err(Error::other)?;
+        Ok(RecordBatchWriter::new(target, columns))
+    }
+}
+
+impl<W: Write> RecordBatchWriter<StreamWriter<W>> {
+    /// Create a writer for the Arrow IPC streaming format.
+    pub fn ipc(writer: W, columns: Vec<ExportColumn>) -> Result<Self, Error> {
+        let target = StreamWriter::try_new(writer, &schema(&columns)).map_err(Error::other)?;
+        Ok(RecordBatchWriter::new(target, columns))
+    }
+}
+
+impl<T: BatchTarget> RecordBatchWriter<T> {
+    pub fn new(target: T, columns: Vec<ExportColumn>) -> Self {
+        RecordBatchWriter {
+            target,
+            schema: schema(&columns),
+            builders: columns
+                .iter()
+                .map(|column| ColumnBuilder::new(*column, DEFAULT_BATCH_SIZE))
+                .collect(),
+            columns,
+            batch_size: DEFAULT_BATCH_SIZE,
+            batch_rows: 0,
+            rows: 0,
+        }
+    }
+
+    /// Set maximum number of rows of a record batch.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    /// Number of entries written
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    fn write_row(&mut self, row: &Row) -> Result<(), Error> {
+        for (builder, column) in self.builders.iter_mut().zip(self.columns.iter()) {
+            builder.append(row.value(*column));
+        }
+        self.rows += 1;
+        self.batch_rows += 1;
+        if self.batch_rows >= self.batch_size {
+            self.flush_batch()?;
+        }
+        Ok(())
+    }
+
+    fn flush_batch(&mut self) -> Result<(), Error> {
+        if self.batch_rows == 0 {
+            return Ok(());
+        }
+        self.batch_rows = 0;
+        let arrays = self.builders.iter_mut().map(|b| b.finish()).collect();
+        let batch = RecordBatch::try_new(self.schema.clone(), arrays).map_err(Error::other)?;
+        self.target.write_batch(batch)
+    }
+
+    /// Write the last record batch, close the target and return it.
+    pub fn finish(mut self) -> Result<T, Error> {
+        self.flush_batch()?;
+        self.target.close()?;
+        Ok(self.target)
+    }
+}
+
+impl<T: BatchTarget> ExportSink for RecordBatchWriter<T> {
+    fn write_result(&mut self, result: &ScandirResult) -> Result<(), Error> {
+        self.write_row(&Row::from(result))
+    }
+
+    fn write_error(&mut self, path: &str, error: &str) -> Result<(), Error> {
+        self.write_row(&Row::Error { path, error })
+    }
+
+    fn write_toc(
+        &mut self,
+        dir_path: &str,
+        toc: &Toc,
+        entries: Option<&HashMap<String, DirEntryExt>>,
+    ) -> Result<(), Error> {
+        for_each_toc_row(dir_path, toc, entries, |row| self.write_row(row))
+    }
+}
@@ -0,0 +1,129 @@
+use std::fs::Metadata;
+use std::io::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::def::{Options, StopReason};
+use crate::pause::RunTimer;
+
+#[derive(Debug, Default)]
+struct Used {
+    files: usize,
+    entries: usize,
+    dirs: usize,
+    bytes: u64,
+}
+
+/// Budgets of a scan, shared by all workers. The scan stops as soon as an
+/// entry would exceed one of the limits or the timeout has expired. The time
+/// the scan was paused does not count for the timeout.
+#[derive(Debug)]
+pub struct Budget {
+    max_file_cnt: usize,
+    max_entries: usize,
+    max_dirs: usize,
+    max_total_bytes: u64,
+    timeout: Option<Duration>,
+    timer: RunTimer,
+    limited: bool,
+    exhausted: AtomicBool,
+    used: Mutex<Used>,
+    stop_reason: Mutex<Option<StopReason>>,
+}
+
+impl Budget {
+    pub fn new(options: &Options, timer: RunTimer) -> Self {
+        Budget {
+            max_file_cnt: options.max_file_cnt,
+            max_entries: options.max_entries,
+            max_dirs: options.max_dirs,
+            max_total_bytes: options.max_total_bytes,
+            timeout: options.timeout,
+            timer,
+            limited: options.max_file_cnt < std::usize::MAX
+                || options.max_entries < std::usize::MAX
+                || options.max_dirs < std::usize::MAX
+                || options.max_total_bytes < std::u64::MAX,
+            exhausted: AtomicBool::new(false),
+            used: Mutex::new(Used::default()),
+            stop_reason: Mutex::new(None),
+        }
+    }
+
+    fn stop(&self, reason: StopReason) {
+        let mut stop_reason = self.stop_reason.lock().unwrap();
+        if stop_reason.is_none() {
+            *stop_reason = Some(reason);
+        }
+        self.exhausted.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if a budget is exhausted or the timeout has expired.
+    pub fn exhausted(&self) -> bool {
+        if self.exhausted.load(Ordering::Relaxed) {
+            return true;
+        }
+        if let Some(timeout) = self.timeout {
+            if self.timer.elapsed() >= timeout {
+                self.stop(StopReason::Timeout);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Take the budget for an entry. Returns `false` if the entry exceeds a budget.
+    /// In this case the entry must not be returned.
+    pub fn take(&self, is_file: bool, is_dir: bool, size: u64) -> bool {
+        if self.exhausted() {
+            return false;
+        }
+        if !self.limited {
+            return true;
+        }
+        let mut used = self.used.lock().unwrap();
+        let reason = if is_file && used.files >= self.max_file_cnt {
+            Some(StopReason::MaxFileCnt)
+        } else if used.entries >= self.max_entries {
+            Some(StopReason::MaxEntries)
+        } else if is_dir && used.dirs >= self.max_dirs {
+            Some(StopReason::MaxDirs)
+        } else if used.bytes.saturating_add(size) > self.max_total_bytes {
+            Some(StopReason::MaxTotalBytes)
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            drop(used);
+            self.stop(reason);
+            return false;
+        }
+        used.entries += 1;
+        if is_file {
+            used.files += 1;
+        }
+        if is_dir {
+            used.dirs += 1;
+        }
+        used.bytes += size;
+        true
+    }
+
+    /// Take the budget for a directory entry. See `take`.
+    pub fn take_entry(
+        &self,
+        dir_entry: &jwalk::DirEntry<((), Option<Result<Metadata, Error>>)>,
+    ) -> bool {
+        self.take(
+            dir_entry.file_type.is_file(),
+            dir_entry.file_type.is_dir(),
+            dir_entry.metadata.as_ref().map(|m| m.size).unwrap_or(0),
+        )
+    }
+
+    /// Budget which stopped the scan, if any.
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.stop_reason.lock().unwrap().clone()
+    }
+}
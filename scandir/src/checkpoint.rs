@@ -0,0 +1,462 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs::{self, Metadata};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::count::{Statistics, Totals};
+
+/// First line of a checkpoint file
+const HEADER: &str = "scandir-checkpoint 1";
+
+/// State of an interrupted scan, from which the scan can be resumed.
+#[derive(Debug, Clone, Default)]
+pub struct Checkpoint {
+    /// Absolute path of the scanned directory
+    pub root_path: String,
+    /// Number of directories whose entries have all been returned
+    pub completed_dirs: u64,
+    /// Relative paths of the directories which have been found, but not yet been read
+    pub pending_dirs: Vec<String>,
+    /// Statistics of the completed directories. Only used by `Count`.
+    pub statistics: Option<Statistics>,
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some(c) => result.push(c),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+fn invalid(line: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Invalid checkpoint line: {}", line),
+    )
+}
+
+fn parse<T: FromStr>(value: Option<&str>, line: &str) -> Result<T, Error> {
+    value
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| invalid(line))
+}
+
+fn write_totals<K: ToString>(out: &mut String, name: &str, key: K, totals: &Totals) {
+    let _ = writeln!(
+        out,
+        "{} {} {} {} {}",
+        name,
+        totals.count,
+        totals.size,
+        totals.usage,
+        escape(&key.to_string())
+    );
+}
+
+fn write_statistics(out: &mut String, statistics: &Statistics) {
+    for (name, value) in [
+        ("dirs", statistics.dirs),
+        ("files", statistics.files),
+        ("slinks", statistics.slinks),
+        ("hlinks", statistics.hlinks),
+        ("devices", statistics.devices),
+        ("pipes", statistics.pipes),
+        ("entries", statistics.entries),
+        ("size", statistics.size),
+        ("usage", statistics.usage),
+        ("unique_size", statistics.unique_size),
+        ("unique_usage", statistics.unique_usage),
+        ("errors_cnt", statistics.errors_cnt),
+    ] {
+        let _ = writeln!(out, "{} {}", name, value);
+    }
+    for (key, totals) in &statistics.owners {
+        write_totals(out, "owner", key, totals);
+    }
+    for (key, totals) in &statistics.extensions {
+        write_totals(out, "extension", key, totals);
+    }
+    for (key, totals) in &statistics.uids {
+        write_totals(out, "uid", key, totals);
+    }
+    for (key, totals) in &statistics.ages {
+        write_totals(out, "age", key, totals);
+    }
+    for (key, totals) in &statistics.sizes {
+        write_totals(out, "size_bucket", key, totals);
+    }
+    for error in &statistics.errors {
+        let _ = writeln!(out, "error {}", escape(error));
+    }
+    for (path, entries_cnt) in &statistics.oversized_dirs {
+        let _ = writeln!(out, "oversized {} {}", entries_cnt, escape(path));
+    }
+}
+
+/// Parse a statistics line. Returns `false` if the line does not belong to the statistics.
+fn read_statistics(statistics: &mut Statistics, line: &str) -> Result<bool, Error> {
+    let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+    let counter = match name {
+        "dirs" => &mut statistics.dirs,
+        "files" => &mut statistics.files,
+        "slinks" => &mut statistics.slinks,
+        "hlinks" => &mut statistics.hlinks,
+        "devices" => &mut statistics.devices,
+        "pipes" => &mut statistics.pipes,
+        "entries" => &mut statistics.entries,
+        "size" => &mut statistics.size,
+        "usage" => &mut statistics.usage,
+        "unique_size" => &mut statistics.unique_size,
+        "unique_usage" => &mut statistics.unique_usage,
+        "errors_cnt" => &mut statistics.errors_cnt,
+        "owner" | "extension" | "uid" | "age" | "size_bucket" => {
+            let mut fields = value.splitn(4, ' ');
+            let totals = Totals {
+                count: parse(fields.next(), line)?,
+                size: parse(fields.next(), line)?,
+                usage: parse(fields.next(), line)?,
+            };
+            let key = unescape(fields.next().ok_or_else(|| invalid(line))?);
+            match name {
+                "owner" => {
+                    statistics.owners.insert(key, totals);
+                }
+                "extension" => {
+                    statistics.extensions.insert(key, totals);
+                }
+                "uid" => {
+                    statistics.uids.insert(parse(Some(&key), line)?, totals);
+                }
+                "age" => {
+                    statistics.ages.insert(parse(Some(&key), line)?, totals);
+                }
+                _ => {
+                    statistics.sizes.insert(parse(Some(&key), line)?, totals);
+                }
+            }
+            return Ok(true);
+        }
+        "error" => {
+            statistics.errors.push(unescape(value));
+            return Ok(true);
+        }
+        "oversized" => {
+            let (entries_cnt, path) = value.split_once(' ').ok_or_else(|| invalid(line))?;
+            statistics
+                .oversized_dirs
+                .push((unescape(path), parse(Some(entries_cnt), line)?));
+            return Ok(true);
+        }
+        _ => return Ok(false),
+    };
+    *counter = parse(Some(value), line)?;
+    Ok(true)
+}
+
+impl Checkpoint {
+    /// Load a checkpoint written by a scan with a checkpoint file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let content = fs::read_to_string(path)?;
+        let mut lines = content.lines();
+        if lines.next() != Some(HEADER) {
+            return Err(Error::new(ErrorKind::InvalidData, "Not a checkpoint file"));
+        }
+        let mut checkpoint = Checkpoint::default();
+        for line in lines {
+            let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+            match name {
+                "root" => checkpoint.root_path = unescape(value),
+                "completed" => checkpoint.completed_dirs = parse(Some(value), line)?,
+                "pending" => checkpoint.pending_dirs.push(unescape(value)),
+                "statistics" => checkpoint.statistics = Some(Statistics::new()),
+                _ => {
+                    let statistics = checkpoint.statistics.as_mut();
+                    if !statistics
+                        .map(|s| read_statistics(s, line))
+                        .unwrap_or(Ok(false))?
+                    {
+                        return Err(invalid(line));
+                    }
+                }
+            }
+        }
+        Ok(checkpoint)
+    }
+
+    /// Check that the checkpoint was written by a scan of `root_path`.
+    pub(crate) fn check_root_path(&self, root_path: &Path) -> Result<(), Error> {
+        if Path::new(&self.root_path) != root_path {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Checkpoint is for directory {}", self.root_path),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Write the checkpoint. The file is replaced atomically, so a crash while writing
+    /// keeps the previous checkpoint.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut out = String::new();
+        let _ = writeln!(out, "{}", HEADER);
+        let _ = writeln!(out, "root {}", escape(&self.root_path));
+        let _ = writeln!(out, "completed {}", self.completed_dirs);
+        for dir_path in &self.pending_dirs {
+            let _ = writeln!(out, "pending {}", escape(dir_path));
+        }
+        if let Some(ref statistics) = self.statistics {
+            let _ = writeln!(out, "statistics");
+            write_statistics(&mut out, statistics);
+        }
+        let path = path.as_ref();
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, out)?;
+        fs::rename(&tmp_path, path)
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    completed_dirs: u64,
+    pending_dirs: HashSet<String>,
+    /// Directories whose entries are not all returned yet, with the number of remaining
+    /// entries and the subdirectories to read
+    returning: HashMap<String, (usize, Vec<String>)>,
+    statistics: Option<Statistics>,
+    last_save: Instant,
+}
+
+/// Tracks the completed and pending directories of a scan and periodically writes
+/// them to a checkpoint file. Shared by all workers.
+#[derive(Debug)]
+pub struct Checkpointer {
+    path: PathBuf,
+    interval: Duration,
+    root_path: String,
+    state: Mutex<State>,
+    saving: Mutex<()>,
+}
+
+impl Checkpointer {
+    pub fn new(
+        path: PathBuf,
+        interval: Duration,
+        root_path: String,
+        resume: Option<&Checkpoint>,
+        with_statistics: bool,
+    ) -> Self {
+        let statistics = match with_statistics {
+            true => Some(
+                resume
+                    .and_then(|c| c.statistics.clone())
+                    .unwrap_or_else(Statistics::new),
+            ),
+            false => None,
+        };
+        Checkpointer {
+            path,
+            interval,
+            root_path,
+            state: Mutex::new(State {
+                completed_dirs: resume.map(|c| c.completed_dirs).unwrap_or(0),
+                // The root is read first
+                pending_dirs: resume
+                    .map(|c| c.pending_dirs.iter().cloned().collect())
+                    .unwrap_or_else(|| HashSet::from([String::new()])),
+                returning: HashMap::new(),
+                statistics,
+                last_save: Instant::now(),
+            }),
+            saving: Mutex::new(()),
+        }
+    }
+
+    /// Mark directory `dir_path` as completed. `None` is the parent of the root, whose only
+    /// entry is the root itself. `subdirs` are the subdirectories which will be read and
+    /// `statistics` are the statistics of the entries of the directory.
+    pub fn complete(
+        &self,
+        dir_path: Option<&str>,
+        subdirs: Vec<String>,
+        statistics: Option<&Statistics>,
+    ) {
+        {
+            let mut state = self.state.lock().unwrap();
+            if let Some(dir_path) = dir_path {
+                state.pending_dirs.remove(dir_path);
+                state.completed_dirs += 1;
+            }
+            state.pending_dirs.extend(subdirs);
+            if let (Some(total), Some(statistics)) = (state.statistics.as_mut(), statistics) {
+                total.merge(statistics);
+            }
+        }
+        self.save_if_due();
+    }
+
+    /// Mark directory `dir_path` as completed after `entries_cnt` of its entries have
+    /// been returned. See `returned`.
+    pub fn complete_after(&self, dir_path: &str, entries_cnt: usize, subdirs: Vec<String>) {
+        if entries_cnt == 0 {
+            return self.complete(Some(dir_path), subdirs, None);
+        }
+        self.state
+            .lock()
+            .unwrap()
+            .returning
+            .insert(dir_path.to_string(), (entries_cnt, subdirs));
+    }
+
+    /// An entry of directory `dir_path` has been returned.
+    pub fn returned(&self, dir_path: &str) {
+        let subdirs = {
+            let mut state = self.state.lock().unwrap();
+            match state.returning.get_mut(dir_path) {
+                Some((entries_cnt, _)) if *entries_cnt > 1 => {
+                    *entries_cnt -= 1;
+                    return;
+                }
+                Some(_) => state.returning.remove(dir_path).map(|(_, subdirs)| subdirs),
+                None => return,
+            }
+        };
+        if let Some(subdirs) = subdirs {
+            self.complete(Some(dir_path), subdirs, None);
+        }
+    }
+
+    /// Add the error of directory `failed_dir`, which could not be read, to the statistics.
+    /// The directory is completed, so that a resumed scan does not read it again and report
+    /// the error twice.
+    pub fn error(&self, failed_dir: &str, error: String) {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.pending_dirs.remove(failed_dir) {
+                state.completed_dirs += 1;
+            }
+            if let Some(statistics) = state.statistics.as_mut() {
+                statistics.errors.push(error);
+                statistics.errors_cnt += 1;
+            }
+        }
+        self.save_if_due();
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        let state = self.state.lock().unwrap();
+        let mut pending_dirs: Vec<String> = state.pending_dirs.iter().cloned().collect();
+        pending_dirs.sort();
+        Checkpoint {
+            root_path: self.root_path.clone(),
+            completed_dirs: state.completed_dirs,
+            pending_dirs,
+            statistics: state.statistics.clone(),
+        }
+    }
+
+    fn save_if_due(&self) {
+        if let Ok(_saving) = self.saving.try_lock() {
+            let due = {
+                let mut state = self.state.lock().unwrap();
+                let due = state.last_save.elapsed() >= self.interval;
+                if due {
+                    state.last_save = Instant::now();
+                }
+                due
+            };
+            if due {
+                let _ = self.checkpoint().save(&self.path);
+            }
+        }
+    }
+
+    /// Write the checkpoint now.
+    pub fn save(&self) -> Result<(), Error> {
+        let _saving = self.saving.lock().unwrap();
+        self.checkpoint().save(&self.path)
+    }
+}
+
+/// Directories of a resumed scan which have been completed before.
+#[derive(Debug)]
+pub struct Resume {
+    pending_dirs: HashSet<String>,
+    /// Ancestors of the pending directories. They have all been completed.
+    completed_dirs: HashSet<String>,
+}
+
+impl Resume {
+    pub fn new(checkpoint: &Checkpoint) -> Self {
+        let mut completed_dirs = HashSet::new();
+        if checkpoint.pending_dirs.is_empty() {
+            // The scan was complete
+            completed_dirs.insert(String::new());
+        }
+        for dir_path in &checkpoint.pending_dirs {
+            let mut path = dir_path.as_str();
+            while !path.is_empty() {
+                path = path.rfind(MAIN_SEPARATOR).map(|i| &path[..i]).unwrap_or("");
+                if !completed_dirs.insert(path.to_string()) {
+                    break;
+                }
+            }
+        }
+        Resume {
+            pending_dirs: checkpoint.pending_dirs.iter().cloned().collect(),
+            completed_dirs,
+        }
+    }
+
+    /// Returns `true` if all entries of directory `dir_path` have been returned before.
+    pub fn is_completed(&self, dir_path: &str) -> bool {
+        self.completed_dirs.contains(dir_path)
+    }
+
+    /// Keep only the subdirectories of a completed directory which lead to pending
+    /// directories. All other subdirectories are completed with all of their descendants.
+    pub fn retain_pending(
+        &self,
+        dir_path: &str,
+        children: &mut Vec<
+            Result<jwalk::DirEntry<((), Option<Result<Metadata, Error>>)>, jwalk::Error>,
+        >,
+    ) {
+        children.retain(|dir_entry_result| match dir_entry_result {
+            Ok(dir_entry) if dir_entry.file_type.is_dir() => {
+                let path = match dir_path.is_empty() {
+                    true => dir_entry.file_name.to_string_lossy().to_string(),
+                    false => format!(
+                        "{}{}{}",
+                        dir_path,
+                        MAIN_SEPARATOR,
+                        dir_entry.file_name.to_string_lossy()
+                    ),
+                };
+                self.pending_dirs.contains(&path) || self.completed_dirs.contains(&path)
+            }
+            _ => false,
+        });
+    }
+}
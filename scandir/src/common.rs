@@ -1,14 +1,21 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fs;
 use std::fs::Metadata;
 use std::io::{Error, ErrorKind};
-use std::path::PathBuf;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::str::Chars;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[cfg(unix)]
 use expanduser::expanduser;
 
 use glob::{MatchOptions, Pattern};
 
-use crate::def::{Filter, Options};
+use crate::def::{Filter, LinkMetadata, LinkTarget, Options, OversizedAction, Parallelism, SortBy};
+use crate::throttle::set_thread_priority;
 
 pub fn check_and_expand_path(path_str: &str) -> Result<PathBuf, Error> {
     #[cfg(unix)]
@@ -43,6 +50,61 @@ pub fn get_root_path_len(root_path: &PathBuf) -> usize {
     root_path_len
 }
 
+/// Return path relative to root path and error message of a jwalk error.
+pub fn split_error(root_path_len: usize, error: &jwalk::Error) -> (String, String) {
+    let path = error
+        .path()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let message = match error.io_error() {
+        Some(e) => e.to_string(),
+        None => error.to_string(),
+    };
+    (
+        path.get(root_path_len..).unwrap_or(&path).to_string(),
+        message,
+    )
+}
+
+#[inline]
+pub fn system_time_to_f64(time: Option<SystemTime>) -> f64 {
+    let duration = time
+        .unwrap_or(UNIX_EPOCH)
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_else(|_err| Duration::new(0, 0));
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9
+}
+
+/// Read the target of the symbolic link `path`.
+///
+/// Returns `None` if `path` is not a symbolic link. If `link_metadata` is `true`
+/// then also the metadata of the link target is returned.
+pub fn read_link(path: &Path, link_metadata: bool) -> Option<LinkTarget> {
+    let target = fs::read_link(path).ok()?;
+    let (is_dangling, metadata) = match fs::metadata(path) {
+        Ok(metadata) => (
+            false,
+            match link_metadata {
+                true => Some(LinkMetadata {
+                    is_dir: metadata.is_dir(),
+                    is_file: metadata.is_file(),
+                    st_ctime: system_time_to_f64(metadata.created().ok()),
+                    st_mtime: system_time_to_f64(metadata.modified().ok()),
+                    st_atime: system_time_to_f64(metadata.accessed().ok()),
+                    st_size: metadata.len(),
+                }),
+                false => None,
+            },
+        ),
+        Err(_) => (true, None),
+    };
+    Some(LinkTarget {
+        path: target.to_string_lossy().to_string(),
+        is_dangling,
+        metadata,
+    })
+}
+
 pub fn create_filter(options: &Options) -> Result<Option<Filter>, Error> {
     let mut filter = Filter {
         dir_include: Vec::new(),
@@ -208,6 +270,79 @@ pub fn filter_dir(
     true
 }
 
+/// Thread pool created by a scan for `Parallelism::Threads` or for lowering the priority
+/// of the workers, together with the number of threads and the priority it was created
+/// with. The pool is reused by the following scans of the same instance.
+#[derive(Debug, Default)]
+pub struct ThreadPoolCache(Option<((usize, bool, i32), Arc<rayon::ThreadPool>)>);
+
+impl ThreadPoolCache {
+    /// Return the parallelism of `options` with `Parallelism::Threads` and low priority
+    /// replaced by `Parallelism::Pool`. A new thread pool is only created if the number of
+    /// threads or the priority have changed. Falls back to the global thread pool if this
+    /// fails.
+    pub fn parallelism(&mut self, options: &Options) -> Parallelism {
+        let idle_io_priority = options.idle_io_priority;
+        let nice = options.nice;
+        let low_priority = idle_io_priority || nice != 0;
+        let num_threads = match options.parallelism {
+            // The priority of the threads of the global thread pool must not be changed
+            Parallelism::Default if low_priority => 0,
+            Parallelism::Threads(num_threads) => num_threads,
+            ref parallelism => return parallelism.clone(),
+        };
+        let key = (num_threads, idle_io_priority, nice);
+        if let Some((ref pool_key, ref pool)) = self.0 {
+            if *pool_key == key {
+                return Parallelism::Pool(pool.clone());
+            }
+        }
+        let mut builder = rayon::ThreadPoolBuilder::new().num_threads(num_threads);
+        if low_priority {
+            // Errors are reported by `check_thread_priority` when the scan is started
+            builder = builder.start_handler(move |_| {
+                let _ = set_thread_priority(idle_io_priority, nice);
+            });
+        }
+        match builder.build() {
+            Ok(pool) => {
+                let pool = Arc::new(pool);
+                self.0 = Some((key, pool.clone()));
+                Parallelism::Pool(pool)
+            }
+            Err(_) => Parallelism::Default,
+        }
+    }
+}
+
+/// Convert the parallelism of `options` into the parallelism of jwalk. The thread pools
+/// for `Parallelism::Threads` and low priority are created by `ThreadPoolCache` before.
+pub fn jwalk_parallelism(options: &Options) -> jwalk::Parallelism {
+    match options.parallelism {
+        Parallelism::Default => jwalk::Parallelism::RayonDefaultPool,
+        Parallelism::Threads(num_threads) => jwalk::Parallelism::RayonNewPool(num_threads),
+        Parallelism::Pool(ref pool) => jwalk::Parallelism::RayonExistingPool(pool.clone()),
+        Parallelism::Serial => jwalk::Parallelism::Serial,
+    }
+}
+
+/// Number of worker threads of a scan with `parallelism`.
+pub fn worker_threads(parallelism: &Parallelism) -> usize {
+    match parallelism {
+        Parallelism::Default => rayon::current_num_threads(),
+        Parallelism::Threads(threads) => *threads,
+        Parallelism::Pool(pool) => pool.current_num_threads(),
+        Parallelism::Serial => 0,
+    }
+}
+
+/// Index of the slot of the current worker thread in a list of `slots_len` slots with
+/// one slot per worker thread. Slot 0 is used by threads outside of a thread pool.
+#[inline]
+pub fn worker_slot(slots_len: usize) -> usize {
+    rayon::current_thread_index().map(|i| i + 1).unwrap_or(0) % slots_len
+}
+
 pub fn filter_children(
     children: &mut Vec<
         Result<jwalk::DirEntry<((), Option<Result<Metadata, Error>>)>, jwalk::Error>,
@@ -233,7 +368,258 @@ pub fn filter_children(
                     }
                     true
                 })
-                .unwrap_or(false)
+                .unwrap_or(true) // Keep errors
+        });
+    }
+}
+
+fn take_digits(chars: &mut Peekable<Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.peek().copied() {
+        if !c.is_ascii_digit() {
+            break;
+        }
+        digits.push(c);
+        chars.next();
+    }
+    digits
+}
+
+/// Compare strings in natural order, i.e. embedded numbers are compared by their value.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let da = take_digits(&mut a);
+                let db = take_digits(&mut b);
+                let na = da.trim_start_matches('0');
+                let nb = db.trim_start_matches('0');
+                let ordering = na
+                    .len()
+                    .cmp(&nb.len())
+                    .then_with(|| na.cmp(nb))
+                    .then_with(|| da.len().cmp(&db.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(ca), Some(cb)) => {
+                if ca != cb {
+                    return ca.cmp(&cb);
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn compare_entries(
+    a: &jwalk::DirEntry<((), Option<Result<Metadata, Error>>)>,
+    b: &jwalk::DirEntry<((), Option<Result<Metadata, Error>>)>,
+    sort_by: &SortBy,
+) -> Ordering {
+    let by_name = || a.file_name.cmp(&b.file_name);
+    match sort_by {
+        SortBy::Name => by_name(),
+        SortBy::NameCaseInsensitive => a
+            .file_name
+            .to_string_lossy()
+            .to_lowercase()
+            .cmp(&b.file_name.to_string_lossy().to_lowercase())
+            .then_with(by_name),
+        SortBy::NameNatural => natural_cmp(
+            &a.file_name.to_string_lossy(),
+            &b.file_name.to_string_lossy(),
+        )
+        .then_with(by_name),
+        SortBy::Size => {
+            let size = |e: &jwalk::DirEntry<((), Option<Result<Metadata, Error>>)>| {
+                e.metadata.as_ref().map(|m| m.size).unwrap_or(0)
+            };
+            size(a).cmp(&size(b)).then_with(by_name)
+        }
+        SortBy::Mtime => {
+            let mtime = |e: &jwalk::DirEntry<((), Option<Result<Metadata, Error>>)>| {
+                e.metadata.as_ref().and_then(|m| m.modified)
+            };
+            mtime(a).cmp(&mtime(b)).then_with(by_name)
+        }
+    }
+}
+
+/// Sort children of a directory. Errors are moved to the end.
+pub fn sort_children(
+    children: &mut [Result<jwalk::DirEntry<((), Option<Result<Metadata, Error>>)>, jwalk::Error>],
+    sort_by: &Option<SortBy>,
+    dirs_first: bool,
+) {
+    if sort_by.is_none() && !dirs_first {
+        return;
+    }
+    children.sort_by(|a, b| match (a, b) {
+        (Ok(a), Ok(b)) => {
+            if dirs_first {
+                let ordering = b.file_type.is_dir().cmp(&a.file_type.is_dir());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            match sort_by {
+                Some(sort_by) => compare_entries(a, b, sort_by),
+                None => Ordering::Equal,
+            }
+        }
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => Ordering::Equal,
+    });
+}
+
+/// Truncate or clear the children of a directory with more than `max_dir_entries`
+/// entries. Returns the number of entries if the directory is oversized.
+///
+/// This is called after jwalk has read the directory and the metadata of all its
+/// entries, so it only limits what is returned and descended into.
+pub fn limit_dir_entries(
+    children: &mut Vec<
+        Result<jwalk::DirEntry<((), Option<Result<Metadata, Error>>)>, jwalk::Error>,
+    >,
+    max_dir_entries: usize,
+    oversized_action: &OversizedAction,
+) -> Option<usize> {
+    let entries_cnt = children.len();
+    if entries_cnt <= max_dir_entries {
+        return None;
+    }
+    match oversized_action {
+        OversizedAction::Truncate => children.truncate(max_dir_entries),
+        OversizedAction::Skip => children.clear(),
+    }
+    Some(entries_cnt)
+}
+
+/// Return relative path of entry `name` in directory `dir_path`.
+pub fn join_path(dir_path: &str, name: &str) -> String {
+    match dir_path.is_empty() {
+        true => name.to_string(),
+        false => format!("{}{}{}", dir_path, MAIN_SEPARATOR, name),
+    }
+}
+
+/// Relative paths of the subdirectories of a directory which will be read.
+pub fn subdirs_to_read(
+    dir_path: &str,
+    depth: Option<usize>,
+    max_depth: usize,
+    children: &[Result<jwalk::DirEntry<((), Option<Result<Metadata, Error>>)>, jwalk::Error>],
+) -> Vec<String> {
+    match depth {
+        Some(depth) if depth + 1 < max_depth => children
+            .iter()
+            .filter_map(|r| r.as_ref().ok())
+            .filter(|e| e.file_type.is_dir() && e.read_children_path.is_some())
+            .map(|e| join_path(dir_path, &e.file_name.to_string_lossy()))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[derive(Debug)]
+struct Level<T> {
+    /// Directory path, results and subdirectories of each read directory
+    dirs: Vec<(String, Vec<T>, Vec<String>)>,
+    expected: usize,
+}
+
+/// Buffers the results of a parallel walk and releases them breadth-first,
+/// i.e. all results of one depth before the results of the next depth.
+/// The results of one depth are released in the order of their parent directories.
+#[derive(Debug)]
+pub struct LevelBuffer<T> {
+    levels: Vec<Level<T>>,
+    next: usize,
+    ranks: HashMap<String, usize>,
+}
+
+impl<T> LevelBuffer<T> {
+    pub fn new() -> Self {
+        LevelBuffer {
+            levels: vec![Level {
+                dirs: Vec::new(),
+                expected: 1, // Root directory
+            }],
+            next: 0,
+            ranks: HashMap::new(),
+        }
+    }
+
+    /// Add results of directory `dir_path` with `depth`. `subdirs` are the relative paths of
+    /// the subdirectories which will be read. Returns all results which can be released now.
+    pub fn add(
+        &mut self,
+        depth: usize,
+        dir_path: String,
+        results: Vec<T>,
+        subdirs: Vec<String>,
+    ) -> Vec<T> {
+        while self.levels.len() < depth + 2 {
+            self.levels.push(Level {
+                dirs: Vec::new(),
+                expected: 0,
+            });
+        }
+        self.levels[depth + 1].expected += subdirs.len();
+        self.levels[depth].dirs.push((dir_path, results, subdirs));
+        let mut released = Vec::new();
+        while self.next < self.levels.len()
+            && self.levels[self.next].dirs.len() >= self.levels[self.next].expected
+        {
+            released.extend(self.release_next());
+        }
+        released
+    }
+
+    fn release_next(&mut self) -> Vec<T> {
+        let mut dirs = std::mem::take(&mut self.levels[self.next].dirs);
+        let ranks = &self.ranks;
+        dirs.sort_by(|a, b| {
+            let rank_a = ranks.get(&a.0).unwrap_or(&usize::MAX);
+            let rank_b = ranks.get(&b.0).unwrap_or(&usize::MAX);
+            rank_a.cmp(rank_b).then_with(|| a.0.cmp(&b.0))
         });
+        let mut released = Vec::new();
+        let mut ranks = HashMap::new();
+        for (_, results, subdirs) in dirs {
+            released.extend(results);
+            for subdir in subdirs {
+                let rank = ranks.len();
+                ranks.insert(subdir, rank);
+            }
+        }
+        self.ranks = ranks;
+        self.next += 1;
+        released
+    }
+
+    /// Release all remaining results. Remaining results exist if directories
+    /// could not be read or the walk has been stopped.
+    pub fn flush(&mut self) -> Vec<T> {
+        let mut released = Vec::new();
+        while self.next < self.levels.len() {
+            released.extend(self.release_next());
+        }
+        released
+    }
+}
+
+impl<T> Default for LevelBuffer<T> {
+    fn default() -> Self {
+        Self::new()
     }
 }
@@ -1,29 +1,104 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::Metadata;
 use std::io::{Error, ErrorKind};
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant, SystemTime};
 
 use flume::{unbounded, Receiver, Sender};
 use jwalk::WalkDirGeneric;
 
-use crate::common::{check_and_expand_path, create_filter, filter_children, get_root_path_len};
-use crate::def::{Filter, Options, ReturnType};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::budget::Budget;
+use crate::checkpoint::{Checkpoint, Checkpointer, Resume};
+use crate::common::{
+    check_and_expand_path, create_filter, filter_children, get_root_path_len, jwalk_parallelism,
+    limit_dir_entries, split_error, subdirs_to_read, worker_slot, worker_threads, ThreadPoolCache,
+};
+use crate::def::{Filter, Options, OversizedAction, Parallelism, ReturnType, StopReason};
+use crate::hardlinks::SharedHardlinkSet;
+use crate::pause::{Pause, RunTimer};
+use crate::throttle::{check_thread_priority, set_thread_priority, Throttle};
+use crate::users::NameCache;
+
+/// Number of entries, total size and disk usage of a group of entries
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Totals {
+    pub count: u64,
+    pub size: u64,
+    pub usage: u64,
+}
+
+impl Totals {
+    pub fn add(&mut self, size: u64, usage: u64) {
+        self.count += 1;
+        self.size += size;
+        self.usage += usage;
+    }
+
+    pub fn merge(&mut self, other: &Totals) {
+        self.count += other.count;
+        self.size += other.size;
+        self.usage += other.usage;
+    }
+}
+
+/// Lower bounds (in days) of the file age buckets of `Statistics::ages`
+pub const AGE_BUCKETS: [u64; 8] = [0, 1, 7, 30, 90, 365, 730, 1825];
+
+/// Return key of size bucket for `Statistics::sizes`.
+///
+/// Bucket `0` contains empty files. Bucket `n` contains files with a size
+/// of at least `2^(n-1)` and less than `2^n` bytes.
+#[inline]
+pub fn size_bucket(size: u64) -> u32 {
+    64 - size.leading_zeros()
+}
+
+/// Return key of age bucket for `Statistics::ages`.
+#[inline]
+pub fn age_bucket(age_days: u64) -> u64 {
+    *AGE_BUCKETS
+        .iter()
+        .rev()
+        .find(|&&days| age_days >= days)
+        .unwrap_or(&0)
+}
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Statistics {
-    pub dirs: i32,
-    pub files: i32,
-    pub slinks: i32,
-    pub hlinks: i32,
-    pub devices: i32,
-    pub pipes: i32,
+    pub dirs: u64,
+    pub files: u64,
+    pub slinks: u64,
+    pub hlinks: u64,
+    pub devices: u64,
+    pub pipes: u64,
+    pub entries: u64,
     pub size: u64,
     pub usage: u64,
+    /// Size without repeated hardlinks. Hardlinks are only detected if the extended
+    /// metadata is read, otherwise this equals `size`.
+    pub unique_size: u64,
+    /// Disk usage without repeated hardlinks
+    pub unique_usage: u64,
+    pub owners: HashMap<String, Totals>,
+    pub extensions: HashMap<String, Totals>,
+    pub uids: HashMap<u32, Totals>,
+    pub ages: BTreeMap<u64, Totals>,
+    pub sizes: BTreeMap<u32, Totals>,
     pub errors: Vec<String>,
+    pub errors_cnt: u64,
     pub duration: f64,
+    /// Budget which stopped the counting, if any
+    pub stop_reason: Option<StopReason>,
+    /// Relative paths and numbers of entries of directories with more than `max_dir_entries` entries
+    pub oversized_dirs: Vec<(String, usize)>,
 }
 
 impl Statistics {
@@ -35,10 +110,21 @@ impl Statistics {
             hlinks: 0,
             devices: 0,
             pipes: 0,
+            entries: 0,
             size: 0,
             usage: 0,
+            unique_size: 0,
+            unique_usage: 0,
+            owners: HashMap::new(),
+            extensions: HashMap::new(),
+            uids: HashMap::new(),
+            ages: BTreeMap::new(),
+            sizes: BTreeMap::new(),
             errors: Vec::new(),
+            errors_cnt: 0,
             duration: 0.0,
+            stop_reason: None,
+            oversized_dirs: Vec::new(),
         }
     }
 
@@ -49,160 +135,406 @@ impl Statistics {
         self.hlinks = 0;
         self.devices = 0;
         self.pipes = 0;
+        self.entries = 0;
         self.size = 0;
         self.usage = 0;
+        self.unique_size = 0;
+        self.unique_usage = 0;
+        self.owners.clear();
+        self.extensions.clear();
+        self.uids.clear();
+        self.ages.clear();
+        self.sizes.clear();
         self.errors.clear();
+        self.errors_cnt = 0;
         self.duration = 0.0;
+        self.stop_reason = None;
+        self.oversized_dirs.clear();
+    }
+
+    /// Add the counters of `other` to these statistics.
+    ///
+    /// Used for combining partial statistics, e.g. from several worker threads.
+    pub fn merge(&mut self, other: &Statistics) {
+        self.dirs += other.dirs;
+        self.files += other.files;
+        self.slinks += other.slinks;
+        self.hlinks += other.hlinks;
+        self.devices += other.devices;
+        self.pipes += other.pipes;
+        self.entries += other.entries;
+        self.size += other.size;
+        self.usage += other.usage;
+        self.unique_size += other.unique_size;
+        self.unique_usage += other.unique_usage;
+        for (key, totals) in &other.owners {
+            self.owners.entry(key.clone()).or_default().merge(totals);
+        }
+        for (key, totals) in &other.extensions {
+            self.extensions
+                .entry(key.clone())
+                .or_default()
+                .merge(totals);
+        }
+        for (key, totals) in &other.uids {
+            self.uids.entry(*key).or_default().merge(totals);
+        }
+        for (key, totals) in &other.ages {
+            self.ages.entry(*key).or_default().merge(totals);
+        }
+        for (key, totals) in &other.sizes {
+            self.sizes.entry(*key).or_default().merge(totals);
+        }
+        self.errors.extend_from_slice(&other.errors);
+        self.errors_cnt += other.errors_cnt;
+        if self.stop_reason.is_none() {
+            self.stop_reason = other.stop_reason.clone();
+        }
+        self.oversized_dirs.extend_from_slice(&other.oversized_dirs);
+    }
+}
+
+/// Partial statistics of the worker threads. Each worker updates its own slot,
+/// so the workers do not block each other.
+struct Partials {
+    slots: Vec<Mutex<Statistics>>,
+}
+
+impl Partials {
+    fn new(parallelism: &Parallelism) -> Self {
+        Partials {
+            slots: (0..=worker_threads(parallelism))
+                .map(|_| Mutex::new(Statistics::new()))
+                .collect(),
+        }
+    }
+
+    fn slot(&self) -> MutexGuard<'_, Statistics> {
+        self.slots[worker_slot(self.slots.len())].lock().unwrap()
+    }
+
+    fn merge_into(&self, statistics: &mut Statistics) {
+        for slot in &self.slots {
+            statistics.merge(&slot.lock().unwrap());
+        }
+    }
+}
+
+/// Adds entries to statistics. Shared by all worker threads.
+struct Counter {
+    resolve_owners: bool,
+    histograms: bool,
+    // Hardlinks need the device and inode from the extended metadata
+    detect_hardlinks: bool,
+    now: SystemTime,
+    name_cache: Arc<NameCache>,
+    hardlinks: SharedHardlinkSet,
+}
+
+impl Counter {
+    fn add(
+        &self,
+        statistics: &mut Statistics,
+        dir_entry: &jwalk::DirEntry<((), Option<Result<Metadata, Error>>)>,
+    ) {
+        statistics.entries += 1;
+        let file_type = dir_entry.file_type;
+        if file_type.is_dir() {
+            statistics.dirs += 1;
+        } else if file_type.is_file() {
+            statistics.files += 1;
+        } else if file_type.is_symlink() {
+            statistics.slinks += 1;
+        }
+        // Repeated hardlinks only count for the apparent size
+        let mut repeated_link = false;
+        if let Some(metadata) = dir_entry
+            .metadata_ext
+            .as_ref()
+            .filter(|_| self.detect_hardlinks)
+        {
+            #[cfg(unix)]
+            if metadata.st_nlink > 1 && !file_type.is_dir() {
+                repeated_link = !self.hardlinks.insert(metadata.st_dev, metadata.st_ino);
+            }
+            #[cfg(windows)]
+            if let (Some(nlink), Some(ino)) = (metadata.number_of_links, metadata.file_index) {
+                if nlink > 1 && !file_type.is_dir() {
+                    let dev = metadata.volume_serial_number.unwrap_or(0) as u64;
+                    repeated_link = !self.hardlinks.insert(dev, ino);
+                }
+            }
+        }
+        if repeated_link {
+            statistics.hlinks += 1;
+        }
+        let mut file_size: u64 = 0;
+        let mut file_usage: u64 = 0;
+        if let Some(ref metadata) = dir_entry.metadata {
+            file_size = metadata.size;
+            let mut blocks = file_size >> 12;
+            if blocks << 12 < file_size {
+                blocks += 1;
+            }
+            file_usage = blocks << 12;
+            statistics.usage += file_usage;
+            statistics.size += file_size;
+            if !repeated_link {
+                statistics.unique_usage += file_usage;
+                statistics.unique_size += file_size;
+            }
+        }
+        if self.histograms && file_type.is_file() {
+            let extension = Path::new(&dir_entry.file_name)
+                .extension()
+                .map(|e| e.to_string_lossy().to_string())
+                .unwrap_or_default();
+            statistics
+                .extensions
+                .entry(extension)
+                .or_default()
+                .add(file_size, file_usage);
+            statistics
+                .sizes
+                .entry(size_bucket(file_size))
+                .or_default()
+                .add(file_size, file_usage);
+            if let Some(ref metadata) = dir_entry.metadata {
+                let age_days = metadata
+                    .modified
+                    .and_then(|mtime| self.now.duration_since(mtime).ok())
+                    .map(|age| age.as_secs() / 86400)
+                    .unwrap_or(0);
+                statistics
+                    .ages
+                    .entry(age_bucket(age_days))
+                    .or_default()
+                    .add(file_size, file_usage);
+            }
+            #[cfg(unix)]
+            if let Some(ref metadata) = dir_entry.metadata_ext {
+                statistics
+                    .uids
+                    .entry(metadata.st_uid)
+                    .or_default()
+                    .add(file_size, file_usage);
+            }
+        }
+        if let Some(ref metadata) = dir_entry.metadata_ext {
+            #[cfg(unix)]
+            {
+                if self.resolve_owners {
+                    let owner = self
+                        .name_cache
+                        .user(metadata.st_uid)
+                        .unwrap_or_else(|| metadata.st_uid.to_string());
+                    statistics
+                        .owners
+                        .entry(owner)
+                        .or_default()
+                        .add(file_size, file_usage);
+                }
+                if metadata.st_rdev > 0 {
+                    statistics.devices += 1;
+                }
+                if (metadata.st_mode & 4096) != 0 {
+                    statistics.pipes += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Add the errors of the entries of a directory which could not be read to `statistics`.
+fn add_errors(
+    statistics: &mut Statistics,
+    children: &[Result<jwalk::DirEntry<((), Option<Result<Metadata, Error>>)>, jwalk::Error>],
+    root_path_len: usize,
+) {
+    for e in children.iter().filter_map(|child| child.as_ref().err()) {
+        let (path, message) = split_error(root_path_len, e);
+        statistics.errors.push(format!("{}: {}", path, message));
+        statistics.errors_cnt += 1;
     }
 }
 
 fn count_thread(
     options: Options,
     filter: Option<Filter>,
+    name_cache: Arc<NameCache>,
+    resume: Option<Arc<Checkpoint>>,
     tx: Sender<Statistics>,
     stop: Arc<AtomicBool>,
+    pause: Arc<Pause>,
 ) {
-    let mut dirs: i32 = 0;
-    let mut files: i32 = 0;
-    let mut slinks: i32 = 0;
-    let mut hlinks: i32 = 0;
-    let mut size: u64 = 0;
-    let mut usage: u64 = 0;
-    #[cfg(unix)]
-    let mut devices: i32 = 0;
-    #[cfg(unix)]
-    let mut pipes: i32 = 0;
-    let mut cnt: i32 = 0;
-    let start_time = Instant::now();
-    let mut update_time = start_time;
-    let mut file_indexes: HashSet<u64> = HashSet::new();
-    let mut statistics = Statistics::new();
+    // Errors are reported by `check_thread_priority` in `start`
+    let _ = set_thread_priority(options.idle_io_priority, options.nice);
+    let throttle = Throttle::new(&options);
+    let read_metadata = options.return_type != ReturnType::Fast;
+    let read_metadata_ext =
+        options.return_type == ReturnType::Ext || options.resolve_owners || options.histograms;
+    let mut cnt: u32 = 0;
+    let timer = RunTimer::new(pause.clone());
+    let mut update_time = Instant::now();
+    // Errors are collected by the iterator, all other statistics by the workers.
+    // A resumed count starts with the statistics of the completed directories.
+    let mut statistics = resume
+        .as_ref()
+        .and_then(|checkpoint| checkpoint.statistics.clone())
+        .unwrap_or_else(Statistics::new);
     let root_path_len = get_root_path_len(&options.root_path);
-    let max_file_cnt = options.max_file_cnt;
-    let file_cnt = Arc::new(AtomicUsize::new(0));
-    let file_cnt_cloned = file_cnt.clone();
+    let max_depth = options.max_depth;
+    let checkpointer = options.checkpoint.as_ref().map(|path| {
+        Arc::new(Checkpointer::new(
+            path.clone(),
+            options.checkpoint_interval,
+            options.root_path.to_string_lossy().to_string(),
+            resume.as_deref(),
+            true,
+        ))
+    });
+    let checkpointer_cloned = checkpointer.clone();
+    // Directories completed before the count was resumed
+    let resume = resume.map(|checkpoint| Resume::new(&checkpoint));
+    // The root has depth 0 and is only counted if requested
+    let min_depth = match options.include_root {
+        true => options.min_depth,
+        false => options.min_depth.max(1),
+    };
+    let max_dir_entries = options.max_dir_entries;
+    let oversized_action = options.oversized_action.clone();
+    let counter = Counter {
+        resolve_owners: options.resolve_owners,
+        histograms: options.histograms,
+        detect_hardlinks: read_metadata_ext,
+        now: SystemTime::now(),
+        name_cache,
+        hardlinks: SharedHardlinkSet::new(options.hardlink_memory),
+    };
+    let partials = Arc::new(Partials::new(&options.parallelism));
+    let partials_cloned = partials.clone();
+    let budget = Arc::new(Budget::new(&options, timer.clone()));
+    let budget_cloned = budget.clone();
     let stop_cloned = stop.clone();
-    let tx_cloned = tx.clone();
+    let snapshot = |statistics: &Statistics| {
+        let mut snapshot = statistics.clone();
+        partials.merge_into(&mut snapshot);
+        snapshot.duration = timer.elapsed().as_secs_f64();
+        snapshot
+    };
     for entry in WalkDirGeneric::<((), Option<Result<Metadata, Error>>)>::new(&options.root_path)
         .skip_hidden(options.skip_hidden)
         .sort(false)
         .max_depth(options.max_depth)
-        .read_metadata(true)
-        .read_metadata_ext(options.return_type == ReturnType::Ext)
-        .process_read_dir(move |_, root_dir, _, children| {
-            if stop_cloned.load(Ordering::Relaxed) {
+        .parallelism(jwalk_parallelism(&options))
+        .read_metadata(read_metadata)
+        .read_metadata_ext(read_metadata_ext)
+        .process_read_dir(move |depth, root_dir, _, children| {
+            pause.wait();
+            throttle.wait(
+                match read_metadata {
+                    true => children.len(),
+                    false => 0,
+                },
+                &stop_cloned,
+            );
+            if stop_cloned.load(Ordering::Relaxed) || budget_cloned.exhausted() {
                 return;
             }
-            if let Some(root_dir) = root_dir.to_str() {
-                if root_dir.len() + 1 < root_path_len {
-                    return;
+            let root_dir = match root_dir.to_str() {
+                Some(root_dir) => root_dir,
+                None => return,
+            };
+            // Each directory is counted separately and merged into the partial statistics
+            // of the worker at the end, so that the slot is only locked briefly and the
+            // checkpoint only contains completed directories
+            let mut statistics = Statistics::new();
+            if root_dir.len() + 1 < root_path_len {
+                // Children only contain the root itself. A resumed count has already counted it.
+                if resume.is_none() {
+                    add_errors(&mut statistics, children, root_path_len);
+                    if min_depth == 0 {
+                        for dir_entry in children.iter().flatten() {
+                            if budget_cloned.take_entry(dir_entry) {
+                                counter.add(&mut statistics, dir_entry);
+                            }
+                        }
+                    }
+                }
+                partials_cloned.slot().merge(&statistics);
+                if let Some(ref checkpointer) = checkpointer_cloned {
+                    checkpointer.complete(None, Vec::new(), Some(&statistics));
                 }
-            } else {
                 return;
             }
+            let dir_path = root_dir.get(root_path_len..).unwrap_or("");
+            if let Some(ref resume) = resume {
+                if resume.is_completed(dir_path) {
+                    resume.retain_pending(dir_path, children);
+                    return;
+                }
+            }
+            if let Some(entries_cnt) =
+                limit_dir_entries(children, max_dir_entries, &oversized_action)
+            {
+                statistics
+                    .oversized_dirs
+                    .push((dir_path.to_string(), entries_cnt));
+            }
             filter_children(children, &filter, root_path_len);
-            if children.is_empty() {
-                return;
+            add_errors(&mut statistics, children, root_path_len);
+            let mut completed = true;
+            for dir_entry in children.iter().flatten() {
+                if dir_entry.depth >= min_depth {
+                    if !budget_cloned.take_entry(dir_entry) {
+                        completed = false;
+                        break;
+                    }
+                    counter.add(&mut statistics, dir_entry);
+                }
+            }
+            partials_cloned.slot().merge(&statistics);
+            if let Some(ref checkpointer) = checkpointer_cloned {
+                if completed {
+                    let subdirs = subdirs_to_read(dir_path, depth, max_depth, children);
+                    checkpointer.complete(Some(dir_path), subdirs, Some(&statistics));
+                }
             }
-            let file_cnt_new = file_cnt_cloned.load(Ordering::Relaxed) + children.len();
-            file_cnt_cloned.store(file_cnt_new, Ordering::Relaxed);
         })
     {
-        if stop.load(Ordering::Relaxed) {
-            break;
-        }
-        if max_file_cnt > 0 && file_cnt.load(Ordering::Relaxed) > max_file_cnt {
+        if stop.load(Ordering::Relaxed) || budget.exhausted() {
             break;
         }
-        match &entry {
-            Ok(v) => {
-                if v.depth == 0 {
-                    continue;
-                }
-                let file_type = v.file_type;
-                if file_type.is_dir() {
-                    dirs += 1;
-                } else if file_type.is_file() {
-                    files += 1;
-                } else if file_type.is_symlink() {
-                    slinks += 1;
-                }
-                if let Some(ref metadata) = v.metadata {
-                    let file_size = metadata.size;
-                    let mut blocks = file_size >> 12;
-                    if blocks << 12 < file_size {
-                        blocks += 1;
-                    }
-                    usage += blocks << 12;
-                    size += file_size;
-                }
-                if let Some(ref metadata) = v.metadata_ext {
-                    #[cfg(unix)]
-                    {
-                        if metadata.st_nlink > 1 {
-                            if file_indexes.contains(&metadata.st_ino) {
-                                hlinks += 1;
-                            } else {
-                                file_indexes.insert(metadata.st_ino);
-                            }
-                        }
-                        if metadata.st_rdev > 0 {
-                            devices += 1;
-                        }
-                        if (metadata.st_mode & 4096) != 0 {
-                            pipes += 1;
-                        }
-                    }
-                    #[cfg(windows)]
-                    {
-                        if let Some(nlink) = metadata.number_of_links {
-                            if nlink > 1 {
-                                if let Some(ino) = metadata.file_index {
-                                    if file_indexes.contains(&ino) {
-                                        hlinks += 1;
-                                    } else {
-                                        file_indexes.insert(ino);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-                cnt += 1;
-                if (cnt >= 1000) || (update_time.elapsed().as_millis() >= 10) {
-                    statistics.dirs = dirs;
-                    statistics.files = files;
-                    statistics.slinks = slinks;
-                    statistics.hlinks = hlinks;
-                    statistics.size = size;
-                    statistics.usage = usage;
-                    statistics.duration = start_time.elapsed().as_secs_f64();
-                    #[cfg(unix)]
-                    {
-                        statistics.devices = devices;
-                        statistics.pipes = pipes;
-                    }
-                    let _ = tx_cloned.send(statistics.clone());
-                    cnt = 0;
-                    update_time = Instant::now();
-                }
+        // Only directories which could not be read are reported by the iterator. These
+        // errors are also added to the checkpoint, because the directories they belong to
+        // are not read again when the count is resumed.
+        if let Some(e) = entry.ok().and_then(|v| v.read_children_error) {
+            let (path, message) = split_error(root_path_len, &e);
+            let error = format!("{}: {}", path, message);
+            if let Some(ref checkpointer) = checkpointer {
+                checkpointer.error(&path, error.clone());
             }
-            Err(e) => statistics.errors.push(e.to_string()), // TODO: Need to fetch failed path from somewhere
+            statistics.errors.push(error);
+            statistics.errors_cnt += 1;
+        }
+        cnt += 1;
+        if (cnt >= 1000) || (update_time.elapsed().as_millis() >= 10) {
+            let _ = tx.send(snapshot(&statistics));
+            cnt = 0;
+            update_time = Instant::now();
         }
     }
-    statistics.dirs = dirs;
-    statistics.files = files;
-    statistics.slinks = slinks;
-    statistics.hlinks = hlinks;
-    statistics.size = size;
-    statistics.usage = usage;
-    statistics.duration = start_time.elapsed().as_secs_f64();
-    #[cfg(unix)]
-    {
-        statistics.devices = devices;
-        statistics.pipes = pipes;
+    if let Some(checkpointer) = checkpointer {
+        if let Err(e) = checkpointer.save() {
+            let path = options.checkpoint.unwrap_or_default();
+            statistics
+                .errors
+                .push(format!("{}: {}", path.to_string_lossy(), e));
+            statistics.errors_cnt += 1;
+        }
     }
-    let _ = tx_cloned.send(statistics);
+    statistics.stop_reason = budget.stop_reason();
+    let _ = tx.send(snapshot(&statistics));
 }
 
 #[derive(Debug)]
@@ -213,35 +545,44 @@ pub struct Count {
     statistics: Statistics,
     duration: Arc<Mutex<f64>>,
     // Internal
+    resume: Option<Arc<Checkpoint>>,
     thr: Option<thread::JoinHandle<()>>,
     stop: Arc<AtomicBool>,
+    pause: Arc<Pause>,
     rx: Option<Receiver<Statistics>>,
+    name_cache: Arc<NameCache>,
+    thread_pool: ThreadPoolCache,
 }
 
 impl Count {
     pub fn new(root_path: &str) -> Result<Self, Error> {
         Ok(Count {
-            options: Options {
-                root_path: check_and_expand_path(&root_path)?,
-                sorted: false,
-                skip_hidden: true,
-                max_depth: std::usize::MAX,
-                max_file_cnt: std::usize::MAX,
-                dir_include: None,
-                dir_exclude: None,
-                file_include: None,
-                file_exclude: None,
-                case_sensitive: false,
-                return_type: ReturnType::Base,
-            },
+            options: Options::new(check_and_expand_path(&root_path)?),
             statistics: Statistics::new(),
             duration: Arc::new(Mutex::new(0.0)),
+            resume: None,
             thr: None,
             stop: Arc::new(AtomicBool::new(false)),
+            pause: Arc::new(Pause::new()),
             rx: None,
+            name_cache: Arc::new(NameCache::new()),
+            thread_pool: ThreadPoolCache::default(),
         })
     }
 
+    /// Resume an interrupted count from a checkpoint. The directories completed before
+    /// are not counted again, their statistics are taken from the checkpoint. `options`
+    /// must have the same values as for the interrupted count, e.g. those returned by
+    /// `options()`. The hardlinks found before are not stored in the checkpoint, so after
+    /// resuming `hlinks`, `unique_size` and `unique_usage` are only approximate.
+    pub fn resume_from(checkpoint: Checkpoint, options: Options) -> Result<Self, Error> {
+        checkpoint.check_root_path(&options.root_path)?;
+        let mut count = Count::new(&checkpoint.root_path)?;
+        count.options = options;
+        count.resume = Some(Arc::new(checkpoint));
+        Ok(count)
+    }
+
     /// Skip hidden entries. Enabled by default.
     pub fn skip_hidden(mut self, skip_hidden: bool) -> Self {
         self.options.skip_hidden = skip_hidden;
@@ -265,7 +606,20 @@ impl Count {
         self
     }
 
-    /// Set maximum number of files to collect
+    /// Set the minimum depth of entries to count. Entries with a smaller
+    /// depth are not counted, but their directories are still walked.
+    pub fn min_depth(mut self, depth: usize) -> Self {
+        self.options.min_depth = depth;
+        self
+    }
+
+    /// Also count the root itself, if `min_depth` is `0`.
+    pub fn include_root(mut self, include_root: bool) -> Self {
+        self.options.include_root = include_root;
+        self
+    }
+
+    /// Set maximum number of files to count
     pub fn max_file_cnt(mut self, max_file_cnt: usize) -> Self {
         self.options.max_file_cnt = match max_file_cnt {
             0 => std::usize::MAX,
@@ -274,6 +628,134 @@ impl Count {
         self
     }
 
+    /// Set maximum number of entries to count
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.options.max_entries = match max_entries {
+            0 => std::usize::MAX,
+            _ => max_entries,
+        };
+        self
+    }
+
+    /// Set maximum number of directories to count
+    pub fn max_dirs(mut self, max_dirs: usize) -> Self {
+        self.options.max_dirs = match max_dirs {
+            0 => std::usize::MAX,
+            _ => max_dirs,
+        };
+        self
+    }
+
+    /// Set maximum total size of entries to count. The size is only known if
+    /// metadata is read, so for `ReturnType::Fast` this limit has no effect.
+    pub fn max_total_bytes(mut self, max_total_bytes: u64) -> Self {
+        self.options.max_total_bytes = match max_total_bytes {
+            0 => std::u64::MAX,
+            _ => max_total_bytes,
+        };
+        self
+    }
+
+    /// Stop counting after the given time.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.options.timeout = timeout;
+        self
+    }
+
+    /// Set maximum number of entries of a directory. Directories with more entries are
+    /// truncated or skipped, depending on `oversized_action`, and reported in the statistics.
+    /// The directory is still read and its entries are still stat'ed completely.
+    pub fn max_dir_entries(mut self, max_dir_entries: usize) -> Self {
+        self.options.max_dir_entries = match max_dir_entries {
+            0 => std::usize::MAX,
+            _ => max_dir_entries,
+        };
+        self
+    }
+
+    /// Set handling of directories with more than `max_dir_entries` entries.
+    pub fn oversized_action(mut self, oversized_action: OversizedAction) -> Self {
+        self.options.oversized_action = oversized_action;
+        self
+    }
+
+    /// Set number of threads for reading directories. `0` uses the global rayon thread pool
+    /// and `1` reads all directories in the scanning thread.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.options.parallelism = match threads {
+            0 => Parallelism::Default,
+            1 => Parallelism::Serial,
+            _ => Parallelism::Threads(threads),
+        };
+        self
+    }
+
+    /// Set degree of parallelism, e.g. `Parallelism::Pool` for using a rayon thread pool
+    /// provided by the caller.
+    pub fn parallelism(mut self, parallelism: Parallelism) -> Self {
+        self.options.parallelism = parallelism;
+        self
+    }
+
+    /// Set maximum number of directories read per second. `0` disables the limit.
+    /// A worker waits after reading a directory, so the limit delays its next read.
+    pub fn max_dirs_per_sec(mut self, max_dirs_per_sec: usize) -> Self {
+        self.options.max_dirs_per_sec = match max_dirs_per_sec {
+            0 => std::usize::MAX,
+            _ => max_dirs_per_sec,
+        };
+        self
+    }
+
+    /// Set maximum number of entries whose metadata is read per second. `0` disables
+    /// the limit. Has no effect if no metadata is read. Like `max_dirs_per_sec` the
+    /// limit is applied after a directory was read and delays the next read.
+    pub fn max_stats_per_sec(mut self, max_stats_per_sec: usize) -> Self {
+        self.options.max_stats_per_sec = match max_stats_per_sec {
+            0 => std::usize::MAX,
+            _ => max_stats_per_sec,
+        };
+        self
+    }
+
+    /// Run the scanning threads with idle I/O priority. Only supported on Linux.
+    /// Has no effect on the threads of a pool set with `Parallelism::Pool`.
+    /// `start` fails if the priority cannot be set.
+    pub fn idle_io_priority(mut self, idle_io_priority: bool) -> Self {
+        self.options.idle_io_priority = idle_io_priority;
+        self
+    }
+
+    /// Set nice level of the scanning threads. `0` keeps the current level. Only supported
+    /// on Linux. Has no effect on the threads of a pool set with `Parallelism::Pool`.
+    /// `start` fails if the nice level cannot be set, e.g. a negative level without privileges.
+    pub fn nice(mut self, nice: i32) -> Self {
+        self.options.nice = nice;
+        self
+    }
+
+    /// Set memory in bytes for detecting hardlinks. If `0` all hardlinked files are
+    /// remembered exactly. Otherwise a bloom filter of this size is used, which may
+    /// count a few files wrongly as hardlinks when scanning billions of inodes.
+    pub fn hardlink_memory(mut self, hardlink_memory: usize) -> Self {
+        self.options.hardlink_memory = hardlink_memory;
+        self
+    }
+
+    /// Periodically write a checkpoint to file `checkpoint`, from which the count can be
+    /// resumed with `resume_from`. The checkpoint is also written when the count has
+    /// finished or has been stopped.
+    pub fn checkpoint(mut self, checkpoint: Option<PathBuf>) -> Self {
+        self.options.checkpoint = checkpoint;
+        self
+    }
+
+    /// Set interval for writing checkpoints. Default is 60s.
+    pub fn checkpoint_interval(mut self, checkpoint_interval: Duration) -> Self {
+        self.options.checkpoint_interval = checkpoint_interval;
+        self
+    }
+
     /// Set directory include filter
     pub fn dir_include(mut self, dir_include: Option<Vec<String>>) -> Self {
         self.options.dir_include = dir_include;
@@ -313,6 +795,25 @@ impl Count {
         self
     }
 
+    /// Set type of counting. `ReturnType::Fast` counts entries without reading any metadata,
+    /// so sizes are not calculated. `ReturnType::Ext` enables extended file type counting.
+    pub fn return_type(mut self, return_type: ReturnType) -> Self {
+        self.options.return_type = return_type;
+        self
+    }
+
+    /// Calculate file histograms per extension, owner uid, age and size.
+    pub fn histograms(mut self, histograms: bool) -> Self {
+        self.options.histograms = histograms;
+        self
+    }
+
+    /// Calculate totals per owner. Owners which cannot be resolved are reported by their uid.
+    pub fn resolve_owners(mut self, resolve_owners: bool) -> Self {
+        self.options.resolve_owners = resolve_owners;
+        self
+    }
+
     pub fn clear(&mut self) {
         self.statistics.clear();
         *self.duration.lock().unwrap() = 0.0;
@@ -323,17 +824,22 @@ impl Count {
             return Err(Error::new(ErrorKind::Other, "Busy"));
         }
         self.clear();
-        let options = self.options.clone();
+        let mut options = self.options.clone();
+        options.parallelism = self.thread_pool.parallelism(&options);
         let filter = create_filter(&options)?;
+        check_thread_priority(options.idle_io_priority, options.nice)?;
         let (tx, rx) = unbounded();
         self.rx = Some(rx);
         self.stop.store(false, Ordering::Relaxed);
+        let name_cache = self.name_cache.clone();
+        let resume = self.resume.clone();
         let stop = self.stop.clone();
+        let pause = self.pause.clone();
         let duration = self.duration.clone();
         self.thr = Some(thread::spawn(move || {
-            let start_time = Instant::now();
-            count_thread(options, filter, tx, stop);
-            *duration.lock().unwrap() = start_time.elapsed().as_secs_f64();
+            let timer = RunTimer::new(pause.clone());
+            count_thread(options, filter, name_cache, resume, tx, stop, pause);
+            *duration.lock().unwrap() = timer.elapsed().as_secs_f64();
         }));
         Ok(())
     }
@@ -351,6 +857,7 @@ impl Count {
     pub fn stop(&mut self) -> bool {
         if let Some(thr) = self.thr.take() {
             self.stop.store(true, Ordering::Relaxed);
+            self.pause.resume();
             if let Err(_e) = thr.join() {
                 return false;
             }
@@ -359,6 +866,25 @@ impl Count {
         false
     }
 
+    /// Pause the scan. Each worker still reads one more directory and then waits until
+    /// `resume` is called. The paused time does not count for the timeout and the
+    /// duration. `stop` also resumes a paused scan. With `Parallelism::Default` the waiting
+    /// workers block threads of the global rayon thread pool and so also other scans
+    /// using it. Pausing a scan while others are running needs its own thread pool.
+    pub fn pause(&self) {
+        self.pause.pause();
+    }
+
+    /// Resume a paused scan.
+    pub fn resume(&self) {
+        self.pause.resume();
+    }
+
+    /// Returns `true` if the scan is paused.
+    pub fn paused(&self) -> bool {
+        self.pause.is_paused()
+    }
+
     fn receive_all(&mut self) -> Statistics {
         if let Some(ref rx) = self.rx {
             loop {
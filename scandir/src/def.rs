@@ -1,20 +1,90 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 use glob::{MatchOptions, Pattern};
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Options {
     pub root_path: PathBuf,
-    pub sorted: bool,
+    pub sort_by: Option<SortBy>,
+    pub dirs_first: bool,
+    pub order: TraversalOrder,
     pub skip_hidden: bool,
+    pub min_depth: usize,
     pub max_depth: usize,
+    pub include_root: bool,
     pub max_file_cnt: usize,
+    pub max_entries: usize,
+    pub max_dirs: usize,
+    pub max_total_bytes: u64,
+    pub timeout: Option<Duration>,
+    pub max_dir_entries: usize,
+    pub oversized_action: OversizedAction,
+    pub parallelism: Parallelism,
+    pub max_dirs_per_sec: usize,
+    pub max_stats_per_sec: usize,
+    pub idle_io_priority: bool,
+    pub nice: i32,
+    pub hardlink_memory: usize,
+    pub checkpoint: Option<PathBuf>,
+    pub checkpoint_interval: Duration,
     pub dir_include: Option<Vec<String>>,
     pub dir_exclude: Option<Vec<String>>,
     pub file_include: Option<Vec<String>>,
     pub file_exclude: Option<Vec<String>>,
     pub case_sensitive: bool,
     pub return_type: ReturnType,
+    pub read_links: bool,
+    pub link_metadata: bool,
+    pub resolve_owners: bool,
+    pub histograms: bool,
+}
+
+impl Options {
+    /// Default options for scanning `root_path`
+    pub fn new(root_path: PathBuf) -> Self {
+        Options {
+            root_path,
+            sort_by: None,
+            dirs_first: false,
+            order: TraversalOrder::Default,
+            skip_hidden: true,
+            min_depth: 0,
+            max_depth: std::usize::MAX,
+            include_root: false,
+            max_file_cnt: std::usize::MAX,
+            max_entries: std::usize::MAX,
+            max_dirs: std::usize::MAX,
+            max_total_bytes: std::u64::MAX,
+            timeout: None,
+            max_dir_entries: std::usize::MAX,
+            oversized_action: OversizedAction::Truncate,
+            parallelism: Parallelism::Default,
+            max_dirs_per_sec: std::usize::MAX,
+            max_stats_per_sec: std::usize::MAX,
+            idle_io_priority: false,
+            nice: 0,
+            hardlink_memory: 0,
+            checkpoint: None,
+            checkpoint_interval: Duration::from_secs(60),
+            dir_include: None,
+            dir_exclude: None,
+            file_include: None,
+            file_exclude: None,
+            case_sensitive: false,
+            return_type: ReturnType::Base,
+            read_links: false,
+            link_metadata: false,
+            resolve_owners: false,
+            histograms: false,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -26,7 +96,32 @@ pub struct Filter {
     pub options: Option<MatchOptions>,
 }
 
+/// Metadata of the target of a symbolic link
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LinkMetadata {
+    pub is_dir: bool,
+    pub is_file: bool,
+    pub st_ctime: f64,
+    pub st_mtime: f64,
+    pub st_atime: f64,
+    pub st_size: u64,
+}
+
+/// Target of a symbolic link
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LinkTarget {
+    /// Raw link target as returned by `readlink`
+    pub path: String,
+    /// `true` if the link target does not exist
+    pub is_dangling: bool,
+    /// Metadata of the link target. Only available if requested and the target exists.
+    pub metadata: Option<LinkMetadata>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct DirEntry {
     pub path: String,
     pub is_symlink: bool,
@@ -36,9 +131,11 @@ pub struct DirEntry {
     pub st_mtime: f64,
     pub st_atime: f64,
     pub st_size: u64,
+    pub link_target: Option<LinkTarget>,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct DirEntryExt {
     pub path: String,
     pub is_symlink: bool,
@@ -57,9 +154,13 @@ pub struct DirEntryExt {
     pub st_uid: u32,
     pub st_gid: u32,
     pub st_rdev: u64,
+    pub owner: Option<String>,
+    pub group: Option<String>,
+    pub link_target: Option<LinkTarget>,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ScandirResult {
     DirEntry(DirEntry),
     DirEntryExt(DirEntryExt),
@@ -67,10 +168,12 @@ pub enum ScandirResult {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Toc {
     pub dirs: Vec<String>,
     pub files: Vec<String>,
     pub symlinks: Vec<String>,
+    pub broken_symlinks: Vec<String>,
     pub other: Vec<String>,
     pub errors: Vec<String>,
 }
@@ -81,6 +184,7 @@ impl Toc {
             dirs: Vec::new(),
             files: Vec::new(),
             symlinks: Vec::new(),
+            broken_symlinks: Vec::new(),
             other: Vec::new(),
             errors: Vec::new(),
         }
@@ -98,6 +202,10 @@ impl Toc {
         self.symlinks.clone()
     }
 
+    pub fn broken_symlinks(&self) -> Vec<String> {
+        self.broken_symlinks.clone()
+    }
+
     pub fn other(&self) -> Vec<String> {
         self.other.clone()
     }
@@ -110,6 +218,7 @@ impl Toc {
         return self.dirs.is_empty()
             && self.files.is_empty()
             && self.symlinks.is_empty()
+            && self.broken_symlinks.is_empty()
             && self.other.is_empty()
             && self.errors.is_empty();
     }
@@ -136,6 +245,13 @@ impl Toc {
                 .map(|x| PathBuf::from(root_dir).join(x).to_str().unwrap().to_owned())
                 .collect::<Vec<String>>(),
         );
+        self.broken_symlinks.extend_from_slice(
+            &other
+                .broken_symlinks
+                .iter()
+                .map(|x| PathBuf::from(root_dir).join(x).to_str().unwrap().to_owned())
+                .collect::<Vec<String>>(),
+        );
         self.other.extend_from_slice(
             &other
                 .other
@@ -158,31 +274,256 @@ impl Toc {
         self.dirs.clear();
         self.files.clear();
         self.symlinks.clear();
+        self.broken_symlinks.clear();
         self.other.clear();
         self.errors.clear();
     }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct WalkEntry {
     pub path: String,
     pub toc: Toc,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct WalkEntryExt {
     pub path: String,
     pub toc: Toc,
+    /// Metadata of the directory entries, indexed by file name
+    pub entries: HashMap<String, DirEntryExt>,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum WalkResult {
     Toc(Toc),
     WalkEntry(WalkEntry),
     WalkEntryExt(WalkEntryExt),
 }
 
+/// Sort order of the entries within a directory
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SortBy {
+    /// Byte-wise by file name
+    Name,
+    /// By file name, ignoring case
+    NameCaseInsensitive,
+    /// By file name, comparing embedded numbers by value (e.g. `file2` before `file10`)
+    NameNatural,
+    /// By file size, then by file name
+    Size,
+    /// By modification time, then by file name
+    Mtime,
+}
+
+impl SortBy {
+    pub fn needs_metadata(&self) -> bool {
+        matches!(self, SortBy::Size | SortBy::Mtime)
+    }
+}
+
+/// Order in which the results of a walk are returned
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TraversalOrder {
+    /// Order in which the parallel workers finish
+    Default,
+    /// All entries of a directory tree before the next sibling directory
+    DepthFirst,
+    /// All entries of one depth before the entries of the next depth
+    BreadthFirst,
+}
+
+/// Degree of parallelism of a scan
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Parallelism {
+    /// Use the global rayon thread pool
+    Default,
+    /// Use a rayon thread pool with the given number of threads. The pool is created by
+    /// the first scan and reused by the following scans of the same instance.
+    Threads(usize),
+    /// Use a rayon thread pool provided by the caller. Cannot be serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Pool(Arc<rayon::ThreadPool>),
+    /// Read all directories in the scanning thread. Useful for spinning disks and NFS.
+    Serial,
+}
+
+/// Handling of directories with more than `max_dir_entries` entries. Oversized
+/// directories are still read completely, only their results are limited.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OversizedAction {
+    /// Return only the first `max_dir_entries` entries in sort order
+    Truncate,
+    /// Return no entries and do not descend into the directory
+    Skip,
+}
+
+/// Budget which stopped a scan
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum StopReason {
+    /// Maximum number of files reached
+    MaxFileCnt,
+    /// Maximum number of entries reached
+    MaxEntries,
+    /// Maximum number of directories reached
+    MaxDirs,
+    /// Maximum total size of entries reached
+    MaxTotalBytes,
+    /// Timeout expired
+    Timeout,
+}
+
+/// Progress of a running scan
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Progress {
+    /// Number of directories read
+    pub dirs: u64,
+    /// Number of entries found
+    pub entries: u64,
+    /// Total size of the entries found
+    pub bytes: u64,
+    /// Number of errors
+    pub errors: u64,
+    /// Number of directories waiting to be read
+    pub pending_dirs: u64,
+    /// Elapsed time in seconds, without the time the scan was paused
+    pub elapsed: f64,
+}
+
+/// Key for selecting entries in `TopN`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TopNKey {
+    Size,
+    Mtime,
+    Atime,
+    Blocks,
+}
+
+/// File format of `ExportWriter`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ExportFormat {
+    /// One JSON object per line
+    Ndjson,
+    /// Comma separated values with a header line
+    Csv,
+    /// One MessagePack map per entry
+    MessagePack,
+}
+
+/// Column of `ExportWriter`. The names of the columns are the names of the fields of
+/// `DirEntryExt`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ExportColumn {
+    Path,
+    IsSymlink,
+    IsDir,
+    IsFile,
+    StCtime,
+    StMtime,
+    StAtime,
+    StMode,
+    StIno,
+    StDev,
+    StNlink,
+    StSize,
+    StBlksize,
+    StBlocks,
+    StUid,
+    StGid,
+    StRdev,
+    Owner,
+    Group,
+    /// Raw target of a symbolic link
+    LinkTarget,
+    /// Error message of entries which could not be read
+    Error,
+}
+
+impl ExportColumn {
+    /// Columns available for all return types
+    pub const BASE: [ExportColumn; 9] = [
+        ExportColumn::Path,
+        ExportColumn::IsSymlink,
+        ExportColumn::IsDir,
+        ExportColumn::IsFile,
+        ExportColumn::StCtime,
+        ExportColumn::StMtime,
+        ExportColumn::StAtime,
+        ExportColumn::StSize,
+        ExportColumn::Error,
+    ];
+
+    /// All columns. The extended columns are only available for `ReturnType::Ext`.
+    pub const ALL: [ExportColumn; 21] = [
+        ExportColumn::Path,
+        ExportColumn::IsSymlink,
+        ExportColumn::IsDir,
+        ExportColumn::IsFile,
+        ExportColumn::StCtime,
+        ExportColumn::StMtime,
+        ExportColumn::StAtime,
+        ExportColumn::StMode,
+        ExportColumn::StIno,
+        ExportColumn::StDev,
+        ExportColumn::StNlink,
+        ExportColumn::StSize,
+        ExportColumn::StBlksize,
+        ExportColumn::StBlocks,
+        ExportColumn::StUid,
+        ExportColumn::StGid,
+        ExportColumn::StRdev,
+        ExportColumn::Owner,
+        ExportColumn::Group,
+        ExportColumn::LinkTarget,
+        ExportColumn::Error,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportColumn::Path => "path",
+            ExportColumn::IsSymlink => "is_symlink",
+            ExportColumn::IsDir => "is_dir",
+            ExportColumn::IsFile => "is_file",
+            ExportColumn::StCtime => "st_ctime",
+            ExportColumn::StMtime => "st_mtime",
+            ExportColumn::StAtime => "st_atime",
+            ExportColumn::StMode => "st_mode",
+            ExportColumn::StIno => "st_ino",
+            ExportColumn::StDev => "st_dev",
+            ExportColumn::StNlink => "st_nlink",
+            ExportColumn::StSize => "st_size",
+            ExportColumn::StBlksize => "st_blksize",
+            ExportColumn::StBlocks => "st_blocks",
+            ExportColumn::StUid => "st_uid",
+            ExportColumn::StGid => "st_gid",
+            ExportColumn::StRdev => "st_rdev",
+            ExportColumn::Owner => "owner",
+            ExportColumn::Group => "group",
+            ExportColumn::LinkTarget => "link_target",
+            ExportColumn::Error => "error",
+        }
+    }
+
+    /// Column with name `name`, if any.
+    pub fn from_name(name: &str) -> Option<ExportColumn> {
+        ExportColumn::ALL.into_iter().find(|c| c.name() == name)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ReturnType {
     Fast,
     Base,
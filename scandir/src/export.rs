@@ -0,0 +1,458 @@
+use std::collections::HashMap;
+use std::io::{Error, Write};
+use std::thread;
+use std::time::Duration;
+
+use crate::common::join_path;
+use crate::def::{
+    DirEntry, DirEntryExt, ExportColumn, ExportFormat, ReturnType, ScandirResult, Toc,
+};
+use crate::scandir::Scandir;
+use crate::walk::Walk;
+
+/// Time to wait for new results of a running scan
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Debug)]
+pub(crate) enum Value<'a> {
+    Null,
+    Bool(bool),
+    U64(u64),
+    F64(f64),
+    Str(&'a str),
+}
+
+/// Entry to be written. Entries of a `Toc` only know their file type.
+#[derive(Debug)]
+pub(crate) enum Row<'a> {
+    DirEntry(&'a DirEntry),
+    DirEntryExt(&'a DirEntryExt),
+    Toc {
+        path: String,
+        is_symlink: bool,
+        is_dir: bool,
+        is_file: bool,
+    },
+    Error {
+        path: &'a str,
+        error: &'a str,
+    },
+}
+
+impl Row<'_> {
+    pub(crate) fn value(&self, column: ExportColumn) -> Value<'_> {
+        match self {
+            Row::DirEntry(entry) => match column {
+                ExportColumn::Path => Value::Str(&entry.path),
+                ExportColumn::IsSymlink => Value::Bool(entry.is_symlink),
+                ExportColumn::IsDir => Value::Bool(entry.is_dir),
+                ExportColumn::IsFile => Value::Bool(entry.is_file),
+                ExportColumn::StCtime => Value::F64(entry.st_ctime),
+                ExportColumn::StMtime => Value::F64(entry.st_mtime),
+                ExportColumn::StAtime => Value::F64(entry.st_atime),
+                ExportColumn::StSize => Value::U64(entry.st_size),
+                ExportColumn::LinkTarget => match entry.link_target {
+                    Some(ref link_target) => Value::Str(&link_target.path),
+                    None => Value::Null,
+                },
+                _ => Value::Null,
+            },
+            Row::DirEntryExt(entry) => match column {
+                ExportColumn::Path => Value::Str(&entry.path),
+                ExportColumn::IsSymlink => Value::Bool(entry.is_symlink),
+                ExportColumn::IsDir => Value::Bool(entry.is_dir),
+                ExportColumn::IsFile => Value::Bool(entry.is_file),
+                ExportColumn::StCtime => Value::F64(entry.st_ctime),
+                ExportColumn::StMtime => Value::F64(entry.st_mtime),
+                ExportColumn::StAtime => Value::F64(entry.st_atime),
+                ExportColumn::StMode => Value::U64(entry.st_mode as u64),
+                ExportColumn::StIno => Value::U64(entry.st_ino),
+                ExportColumn::StDev => Value::U64(entry.st_dev),
+                ExportColumn::StNlink => Value::U64(entry.st_nlink),
+                ExportColumn::StSize => Value::U64(entry.st_size),
+                ExportColumn::StBlksize => Value::U64(entry.st_blksize),
+                ExportColumn::StBlocks => Value::U64(entry.st_blocks),
+                ExportColumn::StUid => Value::U64(entry.st_uid as u64),
+                ExportColumn::StGid => Value::U64(entry.st_gid as u64),
+                ExportColumn::StRdev => Value::U64(entry.st_rdev),
+                ExportColumn::Owner => match entry.owner {
+                    Some(ref owner) => Value::Str(owner),
+                    None => Value::Null,
+                },
+                ExportColumn::Group => match entry.group {
+                    Some(ref group) => Value::Str(group),
+                    None => Value::Null,
+                },
+                ExportColumn::LinkTarget => match entry.link_target {
+                    Some(ref link_target) => Value::Str(&link_target.path),
+                    None => Value::Null,
+                },
+                ExportColumn::Error => Value::Null,
+            },
+            Row::Toc {
+                path,
+                is_symlink,
+                is_dir,
+                is_file,
+            } => match column {
+                ExportColumn::Path => Value::Str(path),
+                ExportColumn::IsSymlink => Value::Bool(*is_symlink),
+                ExportColumn::IsDir => Value::Bool(*is_dir),
+                ExportColumn::IsFile => Value::Bool(*is_file),
+                _ => Value::Null,
+            },
+            Row::Error { path, error } => match column {
+                ExportColumn::Path => Value::Str(path),
+                ExportColumn::Error => Value::Str(error),
+                _ => Value::Null,
+            },
+        }
+    }
+}
+
+impl<'a> From<&'a ScandirResult> for Row<'a> {
+    fn from(result: &'a ScandirResult) -> Self {
+        match result {
+            ScandirResult::DirEntry(entry) => Row::DirEntry(entry),
+            ScandirResult::DirEntryExt(entry) => Row::DirEntryExt(entry),
+            ScandirResult::Error((path, error)) => Row::Error { path, error },
+        }
+    }
+}
+
+/// Call `f` for all entries of directory `dir_path` as returned by `Walk`.
+pub(crate) fn for_each_toc_row<F: FnMut(&Row) -> Result<(), Error>>(
+    dir_path: &str,
+    toc: &Toc,
+    entries: Option<&HashMap<String, DirEntryExt>>,
+    mut f: F,
+) -> Result<(), Error> {
+    let groups = [
+        (&toc.dirs, false, true, false),
+        (&toc.files, false, false, true),
+        (&toc.symlinks, true, false, false),
+        (&toc.broken_symlinks, true, false, false),
+        (&toc.other, false, false, false),
+    ];
+    for (names, is_symlink, is_dir, is_file) in groups {
+        for name in names {
+            match entries.and_then(|entries| entries.get(name)) {
+                Some(entry) => f(&Row::DirEntryExt(entry))?,
+                None => f(&Row::Toc {
+                    path: join_path(dir_path, name),
+                    is_symlink,
+                    is_dir,
+                    is_file,
+                })?,
+            }
+        }
+    }
+    for error in &toc.errors {
+        f(&Row::Error {
+            path: dir_path,
+            error,
+        })?;
+    }
+    Ok(())
+}
+
+/// Destination of exported scan results, e.g. `ExportWriter`.
+pub trait ExportSink {
+    /// Write a result of `Scandir`.
+    fn write_result(&mut self, result: &ScandirResult) -> Result<(), Error>;
+
+    /// Write an entry which could not be read.
+    fn write_error(&mut self, path: &str, error: &str) -> Result<(), Error>;
+
+    /// Write the entries of directory `dir_path` as returned by `Walk`. If metadata of the
+    /// entries is given, indexed by file name, it is written too.
+    fn write_toc(
+        &mut self,
+        dir_path: &str,
+        toc: &Toc,
+        entries: Option<&HashMap<String, DirEntryExt>>,
+    ) -> Result<(), Error>;
+}
+
+fn write_json_str<W: Write>(writer: &mut W, s: &str) -> Result<(), Error> {
+    writer.write_all(b"\"")?;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        let escaped = match c {
+            '"' => "\\\"".to_string(),
+            '\\' => "\\\\".to_string(),
+            '\n' => "\\n".to_string(),
+            '\r' => "\\r".to_string(),
+            '\t' => "\\t".to_string(),
+            c if (c as u32) < 0x20 => format!("\\u{:04x}", c as u32),
+            _ => continue,
+        };
+        writer.write_all(&s.as_bytes()[start..i])?;
+        writer.write_all(escaped.as_bytes())?;
+        start = i + c.len_utf8();
+    }
+    writer.write_all(&s.as_bytes()[start..])?;
+    writer.write_all(b"\"")
+}
+
+fn write_json_value<W: Write>(writer: &mut W, value: &Value) -> Result<(), Error> {
+    match value {
+        Value::Null => writer.write_all(b"null"),
+        Value::Bool(v) => write!(writer, "{}", v),
+        Value::U64(v) => write!(writer, "{}", v),
+        // Debug format keeps the fraction of whole numbers, e.g. `1.0`
+        Value::F64(v) if v.is_finite() => write!(writer, "{:?}", v),
+        Value::F64(_) => writer.write_all(b"null"),
+        Value::Str(s) => write_json_str(writer, s),
+    }
+}
+
+fn write_csv_str<W: Write>(writer: &mut W, s: &str) -> Result<(), Error> {
+    if !s.contains(&[',', '"', '\n', '\r'][..]) {
+        return writer.write_all(s.as_bytes());
+    }
+    write!(writer, "\"{}\"", s.replace('"', "\"\""))
+}
+
+fn write_csv_value<W: Write>(writer: &mut W, value: &Value) -> Result<(), Error> {
+    match value {
+        Value::Null => Ok(()),
+        Value::Bool(v) => write!(writer, "{}", v),
+        Value::U64(v) => write!(writer, "{}", v),
+        Value::F64(v) => write!(writer, "{}", v),
+        Value::Str(s) => write_csv_str(writer, s),
+    }
+}
+
+fn write_msgpack_len<W: Write>(
+    writer: &mut W,
+    len: usize,
+    fix: (u8, usize),
+    markers: [u8; 3],
+) -> Result<(), Error> {
+    let (fix_marker, fix_max) = fix;
+    if len <= fix_max {
+        writer.write_all(&[fix_marker | len as u8])
+    } else if markers[0] != 0 && len <= u8::MAX as usize {
+        writer.write_all(&[markers[0], len as u8])
+    } else if len <= u16::MAX as usize {
+        writer.write_all(&[markers[1]])?;
+        writer.write_all(&(len as u16).to_be_bytes())
+    } else {
+        writer.write_all(&[markers[2]])?;
+        writer.write_all(&(len as u32).to_be_bytes())
+    }
+}
+
+fn write_msgpack_str<W: Write>(writer: &mut W, s: &str) -> Result<(), Error> {
+    write_msgpack_len(writer, s.len(), (0xa0, 31), [0xd9, 0xda, 0xdb])?;
+    writer.write_all(s.as_bytes())
+}
+
+fn write_msgpack_value<W: Write>(writer: &mut W, value: &Value) -> Result<(), Error> {
+    match value {
+        Value::Null => writer.write_all(&[0xc0]),
+        Value::Bool(false) => writer.write_all(&[0xc2]),
+        Value::Bool(true) => writer.write_all(&[0xc3]),
+        Value::U64(v) if *v < 0x80 => writer.write_all(&[*v as u8]),
+        Value::U64(v) if *v <= u8::MAX as u64 => writer.write_all(&[0xcc, *v as u8]),
+        Value::U64(v) if *v <= u16::MAX as u64 => {
+            writer.write_all(&[0xcd])?;
+            writer.write_all(&(*v as u16).to_be_bytes())
+        }
+        Value::U64(v) if *v <= u32::MAX as u64 => {
+            writer.write_all(&[0xce])?;
+            writer.write_all(&(*v as u32).to_be_bytes())
+        }
+        Value::U64(v) => {
+            writer.write_all(&[0xcf])?;
+            writer.write_all(&v.to_be_bytes())
+        }
+        Value::F64(v) => {
+            writer.write_all(&[0xcb])?;
+            writer.write_all(&v.to_be_bytes())
+        }
+        Value::Str(s) => write_msgpack_str(writer, s),
+    }
+}
+
+/// Writes scan results incrementally as NDJSON, CSV or MessagePack. Each entry is written
+/// as soon as it is passed to the writer, so no entries are kept in memory.
+#[derive(Debug)]
+pub struct ExportWriter<W: Write> {
+    writer: W,
+    format: ExportFormat,
+    columns: Vec<ExportColumn>,
+    header_written: bool,
+    rows: u64,
+}
+
+impl<W: Write> ExportWriter<W> {
+    /// Create a writer with the columns `ExportColumn::BASE`. For large exports `writer`
+    /// should be buffered.
+    pub fn new(writer: W, format: ExportFormat) -> Self {
+        ExportWriter {
+            writer,
+            format,
+            columns: ExportColumn::BASE.to_vec(),
+            header_written: false,
+            rows: 0,
+        }
+    }
+
+    /// Set columns to write. Columns without a value for an entry are written as null,
+    /// which is an empty field in CSV.
+    pub fn columns(mut self, columns: Vec<ExportColumn>) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    /// Number of entries written
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    fn write_header(&mut self) -> Result<(), Error> {
+        if self.header_written {
+            return Ok(());
+        }
+        self.header_written = true;
+        if self.format == ExportFormat::Csv {
+            for (i, column) in self.columns.iter().enumerate() {
+                if i > 0 {
+                    self.writer.write_all(b",")?;
+                }
+                self.writer.write_all(column.name().as_bytes())?;
+            }
+            self.writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &Row) -> Result<(), Error> {
+        self.write_header()?;
+        let writer = &mut self.writer;
+        match self.format {
+            ExportFormat::Ndjson => {
+                writer.write_all(b"{")?;
+                for (i, column) in self.columns.iter().enumerate() {
+                    if i > 0 {
+                        writer.write_all(b",")?;
+                    }
+                    write_json_str(writer, column.name())?;
+                    writer.write_all(b":")?;
+                    write_json_value(writer, &row.value(*column))?;
+                }
+                writer.write_all(b"}\n")?;
+            }
+            ExportFormat::Csv => {
+                for (i, column) in self.columns.iter().enumerate() {
+                    if i > 0 {
+                        writer.write_all(b",")?;
+                    }
+                    write_csv_value(writer, &row.value(*column))?;
+                }
+                writer.write_all(b"\n")?;
+            }
+            ExportFormat::MessagePack => {
+                write_msgpack_len(writer, self.columns.len(), (0x80, 15), [0, 0xde, 0xdf])?;
+                for column in self.columns.iter() {
+                    write_msgpack_str(writer, column.name())?;
+                    write_msgpack_value(writer, &row.value(*column))?;
+                }
+            }
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Flush the writer and return it. For CSV the header is written even if there were
+    /// no entries.
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.write_header()?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> ExportSink for ExportWriter<W> {
+    fn write_result(&mut self, result: &ScandirResult) -> Result<(), Error> {
+        self.write_row(&Row::from(result))
+    }
+
+    fn write_error(&mut self, path: &str, error: &str) -> Result<(), Error> {
+        self.write_row(&Row::Error { path, error })
+    }
+
+    fn write_toc(
+        &mut self,
+        dir_path: &str,
+        toc: &Toc,
+        entries: Option<&HashMap<String, DirEntryExt>>,
+    ) -> Result<(), Error> {
+        for_each_toc_row(dir_path, toc, entries, |row| self.write_row(row))
+    }
+}
+
+/// Write all results of `instance` to `writer` while the scan is running. The scan is
+/// started if it is not running yet. Create `instance` with `store(false)` to keep no
+/// results in memory.
+pub fn export_scandir<S: ExportSink>(instance: &mut Scandir, writer: &mut S) -> Result<(), Error> {
+    if !instance.busy() && !instance.finished() {
+        instance.start()?;
+    }
+    loop {
+        // All results are sent before the scanning thread finishes
+        let finished = !instance.busy();
+        let (entries, errors) = instance.results(false);
+        for entry in &entries {
+            writer.write_result(entry)?;
+        }
+        for (path, error) in &errors {
+            writer.write_error(path, error)?;
+        }
+        if finished {
+            break;
+        }
+        if entries.is_empty() && errors.is_empty() {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+    Ok(())
+}
+
+/// Write all results of `instance` to `writer` while the walk is running. The walk is
+/// started if it is not running yet. With `ReturnType::Ext` the metadata of the entries
+/// is written too. Create `instance` with `store(false)` to keep no results in memory.
+pub fn export_walk<S: ExportSink>(instance: &mut Walk, writer: &mut S) -> Result<(), Error> {
+    if !instance.busy() && !instance.finished() {
+        instance.start()?;
+    }
+    let extended = instance.options().return_type == ReturnType::Ext;
+    loop {
+        let finished = !instance.busy();
+        let cnt = match extended {
+            true => {
+                let results = instance.results_ext(false);
+                for result in &results {
+                    writer.write_toc(&result.path, &result.toc, Some(&result.entries))?;
+                }
+                results.len()
+            }
+            false => {
+                let results = instance.results(false);
+                for (dir_path, toc) in &results {
+                    writer.write_toc(dir_path, toc, None)?;
+                }
+                results.len()
+            }
+        };
+        if finished {
+            break;
+        }
+        if cnt == 0 {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+    Ok(())
+}
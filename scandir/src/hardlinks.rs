@@ -0,0 +1,105 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+/// Number of hash functions of the bloom filter
+const BLOOM_HASHES: u64 = 4;
+
+/// Number of shards of a `SharedHardlinkSet`. Must be a power of 2.
+const SHARDS: usize = 16;
+
+#[inline]
+fn mix(mut x: u64) -> u64 {
+    // splitmix64 finalizer
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+/// Bloom filter of a fixed size for (device, inode) pairs
+#[derive(Debug)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+}
+
+impl BloomFilter {
+    /// Create a bloom filter which uses `memory` bytes.
+    pub fn new(memory: usize) -> Self {
+        BloomFilter {
+            bits: vec![0; (memory / 8).max(1)],
+        }
+    }
+
+    /// Insert a pair. Returns `true` if it was not seen before. May return `false`
+    /// for a new pair with a probability depending on the size of the filter.
+    pub fn insert(&mut self, dev: u64, ino: u64) -> bool {
+        let nbits = self.bits.len() as u64 * 64;
+        let h1 = mix(ino ^ mix(dev));
+        let h2 = mix(h1) | 1;
+        let mut new = false;
+        for i in 0..BLOOM_HASHES {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % nbits;
+            let (idx, mask) = ((bit / 64) as usize, 1u64 << (bit % 64));
+            if self.bits[idx] & mask == 0 {
+                self.bits[idx] |= mask;
+                new = true;
+            }
+        }
+        new
+    }
+}
+
+/// Set of the hardlinked files seen so far, identified by device and inode.
+#[derive(Debug)]
+pub enum HardlinkSet {
+    /// Remembers all files exactly
+    Exact(HashSet<(u64, u64)>),
+    /// Uses a fixed amount of memory. Some files may wrongly be reported as seen.
+    Bounded(BloomFilter),
+}
+
+impl HardlinkSet {
+    /// Create an exact set if `memory` is `0`, else a bounded set using `memory` bytes.
+    pub fn new(memory: usize) -> Self {
+        match memory {
+            0 => HardlinkSet::Exact(HashSet::new()),
+            _ => HardlinkSet::Bounded(BloomFilter::new(memory)),
+        }
+    }
+
+    /// Insert a file. Returns `true` if it was not seen before.
+    pub fn insert(&mut self, dev: u64, ino: u64) -> bool {
+        match self {
+            HardlinkSet::Exact(set) => set.insert((dev, ino)),
+            HardlinkSet::Bounded(filter) => filter.insert(dev, ino),
+        }
+    }
+}
+
+/// Hardlink set shared by the worker threads. The files are distributed over
+/// separately locked shards, so that the workers rarely wait for each other.
+#[derive(Debug)]
+pub struct SharedHardlinkSet {
+    shards: Vec<Mutex<HardlinkSet>>,
+}
+
+impl SharedHardlinkSet {
+    /// Create a shared set. A `memory` other than `0` is split among the shards.
+    pub fn new(memory: usize) -> Self {
+        let shard_memory = match memory {
+            0 => 0,
+            _ => (memory / SHARDS).max(8),
+        };
+        SharedHardlinkSet {
+            shards: (0..SHARDS)
+                .map(|_| Mutex::new(HardlinkSet::new(shard_memory)))
+                .collect(),
+        }
+    }
+
+    /// Insert a file. Returns `true` if it was not seen before.
+    pub fn insert(&self, dev: u64, ino: u64) -> bool {
+        // Use the high bits, the bloom filter mostly depends on the low bits
+        let shard = (mix(dev ^ mix(ino)) >> 60) as usize & (SHARDS - 1);
+        self.shards[shard].lock().unwrap().insert(dev, ino)
+    }
+}
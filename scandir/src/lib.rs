@@ -4,10 +4,27 @@ extern crate glob;
 
 pub mod def;
 pub use def::*;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod budget;
+pub mod checkpoint;
+pub use checkpoint::Checkpoint;
 pub mod common;
+pub mod export;
+pub use export::*;
+pub mod users;
+pub use users::*;
 pub mod count;
 pub use count::*;
+pub mod hardlinks;
+pub mod pause;
+pub mod progress;
+pub mod throttle;
+pub mod topn;
+pub use topn::*;
 pub mod walk;
 pub use walk::*;
 pub mod scandir;
 pub use crate::scandir::*;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
@@ -0,0 +1,89 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Default)]
+struct State {
+    paused: bool,
+    paused_since: Option<Instant>,
+    paused_time: Duration,
+}
+
+/// Pauses the workers of a scan. A paused scan keeps all of its state and continues
+/// where it stopped.
+///
+/// The workers wait when they process the next directory. This happens after jwalk has
+/// read the directory and the metadata of its entries, so each worker still reads one
+/// more directory after `pause` was called.
+#[derive(Debug, Default)]
+pub struct Pause {
+    state: Mutex<State>,
+    cond: Condvar,
+}
+
+impl Pause {
+    pub fn new() -> Self {
+        Pause::default()
+    }
+
+    pub fn pause(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !state.paused {
+            state.paused = true;
+            state.paused_since = Some(Instant::now());
+        }
+    }
+
+    pub fn resume(&self) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(paused_since) = state.paused_since.take() {
+            state.paused_time += paused_since.elapsed();
+        }
+        state.paused = false;
+        self.cond.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().paused
+    }
+
+    /// Total time paused so far.
+    pub fn paused_time(&self) -> Duration {
+        let state = self.state.lock().unwrap();
+        state.paused_time
+            + state
+                .paused_since
+                .map(|paused_since| paused_since.elapsed())
+                .unwrap_or_default()
+    }
+
+    /// Block while the scan is paused.
+    pub fn wait(&self) {
+        let mut state = self.state.lock().unwrap();
+        while state.paused {
+            state = self.cond.wait(state).unwrap();
+        }
+    }
+}
+
+/// Measures the running time of a scan without the time it was paused.
+#[derive(Debug, Clone)]
+pub struct RunTimer {
+    pause: Arc<Pause>,
+    start_time: Instant,
+    paused_before: Duration,
+}
+
+impl RunTimer {
+    pub fn new(pause: Arc<Pause>) -> Self {
+        RunTimer {
+            start_time: Instant::now(),
+            paused_before: pause.paused_time(),
+            pause,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        let paused = self.pause.paused_time().saturating_sub(self.paused_before);
+        self.start_time.elapsed().saturating_sub(paused)
+    }
+}
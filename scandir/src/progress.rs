@@ -0,0 +1,132 @@
+use std::fmt::{self, Debug};
+use std::fs::Metadata;
+use std::io::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::def::Progress;
+use crate::pause::RunTimer;
+
+/// Callback for progress reports. Is called from the worker threads.
+pub type ProgressFn = dyn Fn(&Progress) + Send + Sync;
+
+#[derive(Clone)]
+pub struct ProgressCallback(pub Arc<ProgressFn>);
+
+impl Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+/// Progress of a scan, updated by all workers.
+#[derive(Debug)]
+pub struct ProgressTracker {
+    dirs: AtomicU64,
+    entries: AtomicU64,
+    bytes: AtomicU64,
+    errors: AtomicU64,
+    queued_dirs: AtomicU64,
+    // Same time as the duration of the scan, without the time it was paused
+    timer: RunTimer,
+    elapsed: Mutex<Option<f64>>,
+    callback: Option<ProgressCallback>,
+    interval: Duration,
+    last_report: Mutex<Instant>,
+}
+
+impl ProgressTracker {
+    pub fn new(callback: Option<ProgressCallback>, interval: Duration, timer: RunTimer) -> Self {
+        ProgressTracker {
+            dirs: AtomicU64::new(0),
+            entries: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            queued_dirs: AtomicU64::new(0),
+            timer,
+            elapsed: Mutex::new(None),
+            callback,
+            interval,
+            last_report: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Add the children of a directory. `read` is `false` for the parent of the root,
+    /// which only contains the root itself. Must be called after the children have been
+    /// filtered, so that only directories which will be read are queued.
+    pub fn add_children(
+        &self,
+        read: bool,
+        max_depth: usize,
+        children: &[Result<jwalk::DirEntry<((), Option<Result<Metadata, Error>>)>, jwalk::Error>],
+    ) {
+        let mut entries = 0;
+        let mut bytes = 0;
+        let mut queued_dirs = 0;
+        for dir_entry in children.iter().flatten() {
+            entries += 1;
+            bytes += dir_entry.metadata.as_ref().map(|m| m.size).unwrap_or(0);
+            if dir_entry.read_children_path.is_some() && dir_entry.depth < max_depth {
+                queued_dirs += 1;
+            }
+        }
+        self.queued_dirs.fetch_add(queued_dirs, Ordering::Relaxed);
+        if read {
+            self.dirs.fetch_add(1, Ordering::Relaxed);
+            self.entries.fetch_add(entries, Ordering::Relaxed);
+            self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        }
+        self.report();
+    }
+
+    /// Add a directory which could not be read.
+    pub fn add_failed_dir(&self) {
+        self.dirs.fetch_add(1, Ordering::Relaxed);
+        self.add_error();
+    }
+
+    pub fn add_error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn progress(&self) -> Progress {
+        let dirs = self.dirs.load(Ordering::Relaxed);
+        Progress {
+            dirs,
+            entries: self.entries.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            pending_dirs: self
+                .queued_dirs
+                .load(Ordering::Relaxed)
+                .saturating_sub(dirs),
+            elapsed: self
+                .elapsed
+                .lock()
+                .unwrap()
+                .unwrap_or_else(|| self.timer.elapsed().as_secs_f64()),
+        }
+    }
+
+    /// Call the callback if the last report is older than the interval. Only one worker
+    /// reports at a time, the others continue immediately.
+    pub fn report(&self) {
+        if let Some(ref callback) = self.callback {
+            if let Ok(mut last_report) = self.last_report.try_lock() {
+                if last_report.elapsed() >= self.interval {
+                    (callback.0)(&self.progress());
+                    *last_report = Instant::now();
+                }
+            }
+        }
+    }
+
+    /// Stop the elapsed time and send the final report.
+    pub fn finish(&self) {
+        *self.elapsed.lock().unwrap() = Some(self.timer.elapsed().as_secs_f64());
+        if let Some(ref callback) = self.callback {
+            (callback.0)(&self.progress());
+        }
+    }
+}
@@ -1,16 +1,30 @@
 use std::fs::Metadata;
 use std::io::{Error, ErrorKind};
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::{Duration, Instant, UNIX_EPOCH};
+use std::time::Duration;
 
 use flume::{unbounded, Receiver, Sender};
 
 use jwalk::WalkDirGeneric;
 
-use crate::common::{check_and_expand_path, create_filter, filter_children, get_root_path_len};
-use crate::def::{DirEntry, DirEntryExt, Filter, Options, ReturnType, ScandirResult};
+use crate::budget::Budget;
+use crate::checkpoint::{Checkpoint, Checkpointer, Resume};
+use crate::common::{
+    check_and_expand_path, create_filter, filter_children, get_root_path_len, jwalk_parallelism,
+    limit_dir_entries, read_link, sort_children, split_error, subdirs_to_read, system_time_to_f64,
+    LevelBuffer, ThreadPoolCache,
+};
+use crate::def::{
+    DirEntry, DirEntryExt, Filter, Options, OversizedAction, Parallelism, Progress, ReturnType,
+    ScandirResult, SortBy, StopReason, TraversalOrder,
+};
+use crate::pause::{Pause, RunTimer};
+use crate::progress::{ProgressCallback, ProgressTracker};
+use crate::throttle::{check_thread_priority, set_thread_priority, Throttle};
+use crate::users::NameCache;
 
 #[derive(Debug, Clone)]
 pub enum Stats {
@@ -29,9 +43,12 @@ pub struct Entry {
 }
 
 #[inline]
-fn create_entry(
+pub(crate) fn create_entry(
     root_path_len: usize,
     return_type: &ReturnType,
+    read_links: bool,
+    link_metadata: bool,
+    name_cache: Option<&NameCache>,
     dir_entry: &jwalk::DirEntry<((), Option<Result<Metadata, Error>>)>,
 ) -> (bool, Entry) {
     let file_type = dir_entry.file_type;
@@ -61,24 +78,9 @@ fn create_entry(
     #[cfg(windows)]
     let st_rdev: u64 = 0;
     if let Some(ref metadata) = dir_entry.metadata {
-        let duration = metadata
-            .created
-            .unwrap_or(UNIX_EPOCH)
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_else(|_err| Duration::new(0, 0));
-        st_ctime = duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9;
-        let duration = metadata
-            .modified
-            .unwrap_or(UNIX_EPOCH)
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_else(|_err| Duration::new(0, 0));
-        st_mtime = duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9;
-        let duration = metadata
-            .accessed
-            .unwrap_or(UNIX_EPOCH)
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_else(|_err| Duration::new(0, 0));
-        st_atime = duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9;
+        st_ctime = system_time_to_f64(metadata.created);
+        st_mtime = system_time_to_f64(metadata.modified);
+        st_atime = system_time_to_f64(metadata.accessed);
         st_size = metadata.size;
         if let Some(ref metadata) = dir_entry.metadata_ext {
             #[cfg(unix)]
@@ -131,8 +133,26 @@ fn create_entry(
     };
     key.push(&file_name);
     let key = key.to_str().unwrap().to_string();
-    let path = key.get(root_path_len..).unwrap_or(&file_name).to_string();
+    let path = match dir_entry.depth {
+        0 => String::new(), // Root
+        _ => key.get(root_path_len..).unwrap_or(&file_name).to_string(),
+    };
+    let link_target = match read_links && file_type.is_symlink() {
+        true => read_link(Path::new(&key), link_metadata),
+        false => None,
+    };
     let entry: ScandirResult = match return_type {
+        ReturnType::Fast => ScandirResult::DirEntry(DirEntry {
+            path,
+            is_symlink: file_type.is_symlink(),
+            is_dir: file_type.is_dir(),
+            is_file,
+            st_ctime: 0.0,
+            st_mtime: 0.0,
+            st_atime: 0.0,
+            st_size: 0,
+            link_target,
+        }),
         ReturnType::Base => ScandirResult::DirEntry(DirEntry {
             path,
             is_symlink: file_type.is_symlink(),
@@ -142,6 +162,7 @@ fn create_entry(
             st_mtime,
             st_atime,
             st_size,
+            link_target,
         }),
         ReturnType::Ext => ScandirResult::DirEntryExt(DirEntryExt {
             path,
@@ -161,6 +182,9 @@ fn create_entry(
             st_uid,
             st_gid,
             st_rdev,
+            owner: name_cache.and_then(|c| c.user(st_uid)),
+            group: name_cache.and_then(|c| c.group(st_gid)),
+            link_target,
         }),
         _ => ScandirResult::Error((path, "Wrong return type!".to_string())),
     };
@@ -173,67 +197,283 @@ fn create_entry(
     )
 }
 
+fn create_error_entry(root_path_len: usize, error: &jwalk::Error) -> Entry {
+    let (path, message) = split_error(root_path_len, error);
+    Entry {
+        path: error
+            .path()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.clone()), // Absolute file path
+        entry: Stats::ScandirResult(ScandirResult::Error((path, message))),
+    }
+}
+
 fn entries_thread(
     options: Options,
     filter: Option<Filter>,
+    name_cache: Arc<NameCache>,
+    budget: Arc<Budget>,
+    progress: Arc<ProgressTracker>,
+    oversized_dirs: Arc<Mutex<Vec<(String, usize)>>>,
+    resume: Option<Arc<Checkpoint>>,
     tx: Sender<Entry>,
     stop: Arc<AtomicBool>,
+    pause: Arc<Pause>,
 ) {
+    // Errors are reported by `check_thread_priority` in `start`
+    let _ = set_thread_priority(options.idle_io_priority, options.nice);
+    let throttle = Throttle::new(&options);
     let root_path_len = get_root_path_len(&options.root_path);
-    let max_file_cnt = options.max_file_cnt;
+    let checkpointer = options.checkpoint.as_ref().map(|path| {
+        Arc::new(Checkpointer::new(
+            path.clone(),
+            options.checkpoint_interval,
+            options.root_path.to_string_lossy().to_string(),
+            resume.as_deref(),
+            false,
+        ))
+    });
+    let checkpointer_cloned = checkpointer.clone();
+    // Directories completed before the scan was resumed
+    let resume = resume.map(|checkpoint| Arc::new(Resume::new(&checkpoint)));
+    let resume_cloned = resume.clone();
+    let max_dir_entries = options.max_dir_entries;
+    let oversized_action = options.oversized_action.clone();
     let return_type = options.return_type.clone();
-    let file_cnt = Arc::new(AtomicUsize::new(0));
-    let file_cnt_cloned = file_cnt.clone();
+    let read_links = options.read_links;
+    let link_metadata = options.link_metadata;
+    let resolve_owners = options.resolve_owners;
+    let sort_by = options.sort_by.clone();
+    let dirs_first = options.dirs_first;
+    // Depth first and sorted results are returned by the iterator to get a deterministic order
+    let ordered = match options.order {
+        TraversalOrder::Default => sort_by.is_some() || dirs_first,
+        TraversalOrder::DepthFirst => true,
+        TraversalOrder::BreadthFirst => false,
+    };
+    let level_buffer = match options.order {
+        TraversalOrder::BreadthFirst => Some(Arc::new(Mutex::new(LevelBuffer::new()))),
+        _ => None,
+    };
+    let level_buffer_cloned = level_buffer.clone();
+    let max_depth = options.max_depth;
+    // The root has depth 0 and is only returned if requested
+    let min_depth = match options.include_root {
+        true => options.min_depth,
+        false => options.min_depth.max(1),
+    };
+    let read_metadata = options.return_type != ReturnType::Fast
+        || sort_by
+            .as_ref()
+            .map(|s| s.needs_metadata())
+            .unwrap_or(false);
+    let budget_cloned = budget.clone();
+    let progress_cloned = progress.clone();
+    let return_type_cloned = return_type.clone();
+    let name_cache_cloned = name_cache.clone();
     let stop_cloned = stop.clone();
     let tx_cloned = tx.clone();
-    for _ in WalkDirGeneric::new(&options.root_path)
+    for result in WalkDirGeneric::new(&options.root_path)
         .skip_hidden(options.skip_hidden)
-        .sort(options.sorted)
+        .sort(false)
         .max_depth(options.max_depth)
-        .read_metadata(true)
+        .parallelism(jwalk_parallelism(&options))
+        .read_metadata(read_metadata)
         .read_metadata_ext(options.return_type == ReturnType::Ext)
-        .process_read_dir(move |_, root_dir, _, children| {
-            if stop_cloned.load(Ordering::Relaxed) {
+        .process_read_dir(move |depth, root_dir, _, children| {
+            pause.wait();
+            throttle.wait(
+                match read_metadata {
+                    true => children.len(),
+                    false => 0,
+                },
+                &stop_cloned,
+            );
+            if stop_cloned.load(Ordering::Relaxed) || budget_cloned.exhausted() {
                 return;
             }
-            if let Some(root_dir) = root_dir.to_str() {
-                if root_dir.len() + 1 < root_path_len {
-                    return;
+            let root_dir = match root_dir.to_str() {
+                Some(root_dir) => root_dir,
+                None => return,
+            };
+            let new_entry = |dir_entry| {
+                create_entry(
+                    root_path_len,
+                    &return_type_cloned,
+                    read_links,
+                    link_metadata,
+                    match resolve_owners {
+                        true => Some(&name_cache_cloned),
+                        false => None,
+                    },
+                    dir_entry,
+                )
+            };
+            if root_dir.len() + 1 < root_path_len {
+                // Children only contain the root itself
+                progress_cloned.add_children(false, max_depth, children);
+                // A resumed scan has already returned the root
+                if min_depth == 0 && !ordered && resume_cloned.is_none() {
+                    for dir_entry in children.iter().flatten() {
+                        if budget_cloned.take_entry(dir_entry) {
+                            let _ = tx_cloned.send(new_entry(dir_entry).1);
+                        }
+                    }
                 }
-            } else {
                 return;
             }
+            let dir_path = root_dir.get(root_path_len..).unwrap_or("");
+            if let Some(ref resume) = resume_cloned {
+                if resume.is_completed(dir_path) {
+                    resume.retain_pending(dir_path, children);
+                    return;
+                }
+            }
+            // Sort before truncating, so that the first entries of the sort order are kept
+            sort_children(children, &sort_by, dirs_first);
+            if let Some(entries_cnt) =
+                limit_dir_entries(children, max_dir_entries, &oversized_action)
+            {
+                oversized_dirs
+                    .lock()
+                    .unwrap()
+                    .push((dir_path.to_string(), entries_cnt));
+            }
             filter_children(children, &filter, root_path_len);
-            if children.is_empty() {
+            progress_cloned.add_children(true, max_depth, children);
+            if children.is_empty() && level_buffer_cloned.is_none() {
+                if let Some(ref checkpointer) = checkpointer_cloned {
+                    checkpointer.complete(Some(dir_path), Vec::new(), None);
+                }
                 return;
             }
-            let mut local_file_cnt: usize = 0;
-            children.iter_mut().for_each(|dir_entry_result| {
-                if let Ok(dir_entry) = dir_entry_result {
-                    let (is_file, entry) = create_entry(root_path_len, &return_type, dir_entry);
-                    if tx_cloned.send(entry).is_err() {
-                        return;
-                    }
-                    if is_file {
-                        local_file_cnt += 1;
+            if ordered {
+                if let Some(ref checkpointer) = checkpointer_cloned {
+                    // The directory is completed when the iterator has returned all entries
+                    let entries_cnt = children
+                        .iter()
+                        .flatten()
+                        .filter(|dir_entry| dir_entry.depth >= min_depth)
+                        .count();
+                    let subdirs = subdirs_to_read(dir_path, depth, max_depth, children);
+                    checkpointer.complete_after(dir_path, entries_cnt, subdirs);
+                }
+                return;
+            }
+            let mut entries = Vec::with_capacity(children.len());
+            let mut completed = true;
+            // Errors are returned by the iterator
+            for dir_entry in children.iter().flatten() {
+                if dir_entry.depth >= min_depth {
+                    if !budget_cloned.take_entry(dir_entry) {
+                        completed = false;
+                        break;
                     }
+                    entries.push(new_entry(dir_entry).1);
                 }
-            });
-            if local_file_cnt > 0 {
-                file_cnt_cloned.store(
-                    file_cnt_cloned.load(Ordering::Relaxed) + local_file_cnt,
-                    Ordering::Relaxed,
+            }
+            let subdirs = match (&checkpointer_cloned, completed) {
+                (Some(_), true) => Some(subdirs_to_read(dir_path, depth, max_depth, children)),
+                _ => None,
+            };
+            if let Some(ref level_buffer) = level_buffer_cloned {
+                let subdirs = subdirs_to_read(dir_path, depth, max_depth, children);
+                entries = level_buffer.lock().unwrap().add(
+                    depth.unwrap_or(0),
+                    dir_path.to_string(),
+                    entries,
+                    subdirs,
                 );
             }
+            for entry in entries {
+                if tx_cloned.send(entry).is_err() {
+                    break;
+                }
+            }
+            // Like in the ordered case the directory is completed when its entries have been
+            // sent, independent of whether they have already been fetched
+            if let (Some(ref checkpointer), Some(subdirs)) = (&checkpointer_cloned, subdirs) {
+                checkpointer.complete(Some(dir_path), subdirs, None);
+            }
         })
     {
-        if stop.load(Ordering::Relaxed) {
+        if stop.load(Ordering::Relaxed) || budget.exhausted() {
             break;
         }
-        if max_file_cnt > 0 && file_cnt.load(Ordering::Relaxed) > max_file_cnt {
-            break;
+        match result {
+            Ok(dir_entry) => {
+                let parent_path = dir_entry.parent_path.to_str();
+                let parent_path = parent_path.and_then(|p| p.get(root_path_len..));
+                let parent_path = parent_path.unwrap_or("");
+                let returned = match resume {
+                    // The root and the entries of completed directories have been returned
+                    Some(ref resume) => dir_entry.depth > 0 && !resume.is_completed(parent_path),
+                    None => true,
+                };
+                if ordered && returned && dir_entry.depth >= min_depth {
+                    if !budget.take_entry(&dir_entry) {
+                        break;
+                    }
+                    let (_, entry) = create_entry(
+                        root_path_len,
+                        &return_type,
+                        read_links,
+                        link_metadata,
+                        match resolve_owners {
+                            true => Some(&name_cache),
+                            false => None,
+                        },
+                        &dir_entry,
+                    );
+                    if tx.send(entry).is_err() {
+                        break;
+                    }
+                    if let Some(ref checkpointer) = checkpointer {
+                        checkpointer.returned(parent_path);
+                    }
+                }
+                if let Some(ref e) = dir_entry.read_children_error {
+                    progress.add_failed_dir();
+                    let _ = tx.send(create_error_entry(root_path_len, e));
+                    if let Some(ref level_buffer) = level_buffer {
+                        // Directory will never be read
+                        let path = dir_entry.path();
+                        let dir_path = path.to_str().and_then(|p| p.get(root_path_len..));
+                        for entry in level_buffer.lock().unwrap().add(
+                            dir_entry.depth,
+                            dir_path.unwrap_or("").to_string(),
+                            Vec::new(),
+                            Vec::new(),
+                        ) {
+                            let _ = tx.send(entry);
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                progress.add_error();
+                let _ = tx.send(create_error_entry(root_path_len, &e));
+            }
+        }
+    }
+    if let Some(level_buffer) = level_buffer {
+        for entry in level_buffer.lock().unwrap().flush() {
+            let _ = tx.send(entry);
+        }
+    }
+    if let Some(checkpointer) = checkpointer {
+        if let Err(e) = checkpointer.save() {
+            let _ = tx.send(Entry {
+                path: options
+                    .checkpoint
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+                entry: Stats::Error(e.to_string()),
+            });
         }
     }
+    progress.finish();
 }
 
 /// Class for iterating a file tree and returning `Entry` objects
@@ -241,44 +481,81 @@ fn entries_thread(
 pub struct Scandir {
     // Options
     options: Options,
+    progress_callback: Option<ProgressCallback>,
+    progress_interval: Duration,
+    store: bool,
     // Results
     entries: Vec<ScandirResult>,
     errors: Vec<(String, String)>,
     duration: Arc<Mutex<f64>>,
     // Internal
+    name_cache: Arc<NameCache>,
+    budget: Option<Arc<Budget>>,
+    progress: Option<Arc<ProgressTracker>>,
+    resume: Option<Arc<Checkpoint>>,
+    oversized_dirs: Arc<Mutex<Vec<(String, usize)>>>,
     thr: Option<thread::JoinHandle<()>>,
     stop: Arc<AtomicBool>,
+    pause: Arc<Pause>,
     rx: Option<Receiver<Entry>>,
+    thread_pool: ThreadPoolCache,
 }
 
 impl Scandir {
     pub fn new(root_path: &str) -> Result<Self, Error> {
         Ok(Scandir {
-            options: Options {
-                root_path: check_and_expand_path(&root_path)?,
-                sorted: false,
-                skip_hidden: true,
-                max_depth: std::usize::MAX,
-                max_file_cnt: std::usize::MAX,
-                dir_include: None,
-                dir_exclude: None,
-                file_include: None,
-                file_exclude: None,
-                case_sensitive: false,
-                return_type: ReturnType::Base,
-            },
+            options: Options::new(check_and_expand_path(&root_path)?),
+            progress_callback: None,
+            progress_interval: Duration::from_millis(100),
+            store: true,
             entries: Vec::new(),
             errors: Vec::new(),
             duration: Arc::new(Mutex::new(0.0)),
+            name_cache: Arc::new(NameCache::new()),
+            budget: None,
+            progress: None,
+            resume: None,
+            oversized_dirs: Arc::new(Mutex::new(Vec::new())),
             thr: None,
             stop: Arc::new(AtomicBool::new(false)),
+            pause: Arc::new(Pause::new()),
             rx: None,
+            thread_pool: ThreadPoolCache::default(),
         })
     }
 
-    /// Return results in sorted order.
-    pub fn sorted(mut self, sorted: bool) -> Self {
-        self.options.sorted = sorted;
+    /// Resume an interrupted scan from a checkpoint. The entries of the directories
+    /// completed before are not returned again. `options` must have the same values as
+    /// for the interrupted scan, e.g. those returned by `options()`.
+    pub fn resume_from(checkpoint: Checkpoint, options: Options) -> Result<Self, Error> {
+        checkpoint.check_root_path(&options.root_path)?;
+        let mut scandir = Scandir::new(&checkpoint.root_path)?;
+        scandir.options = options;
+        scandir.resume = Some(Arc::new(checkpoint));
+        Ok(scandir)
+    }
+
+    /// Return results in sorted order. Alias for `sort_by(Some(SortBy::Name))`.
+    pub fn sorted(self, sorted: bool) -> Self {
+        self.sort_by(sorted.then_some(SortBy::Name))
+    }
+
+    /// Sort the entries of each directory. Sorted results are returned in a
+    /// deterministic order.
+    pub fn sort_by(mut self, sort_by: Option<SortBy>) -> Self {
+        self.options.sort_by = sort_by;
+        self
+    }
+
+    /// Return directories before other entries of a directory.
+    pub fn dirs_first(mut self, dirs_first: bool) -> Self {
+        self.options.dirs_first = dirs_first;
+        self
+    }
+
+    /// Set order in which the results are returned.
+    pub fn order(mut self, order: TraversalOrder) -> Self {
+        self.options.order = order;
         self
     }
 
@@ -305,6 +582,19 @@ impl Scandir {
         self
     }
 
+    /// Set the minimum depth of entries yield by the iterator. Entries with
+    /// a smaller depth are not returned, but their directories are still walked.
+    pub fn min_depth(mut self, depth: usize) -> Self {
+        self.options.min_depth = depth;
+        self
+    }
+
+    /// Also return the root itself, if `min_depth` is `0`. Its path is empty.
+    pub fn include_root(mut self, include_root: bool) -> Self {
+        self.options.include_root = include_root;
+        self
+    }
+
     /// Set maximum number of files to collect
     pub fn max_file_cnt(mut self, max_file_cnt: usize) -> Self {
         self.options.max_file_cnt = match max_file_cnt {
@@ -314,6 +604,112 @@ impl Scandir {
         self
     }
 
+    /// Set maximum number of entries to collect
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.options.max_entries = match max_entries {
+            0 => std::usize::MAX,
+            _ => max_entries,
+        };
+        self
+    }
+
+    /// Set maximum number of directories to collect
+    pub fn max_dirs(mut self, max_dirs: usize) -> Self {
+        self.options.max_dirs = match max_dirs {
+            0 => std::usize::MAX,
+            _ => max_dirs,
+        };
+        self
+    }
+
+    /// Set maximum total size of entries to collect. The size is only known if
+    /// metadata is read, so for `ReturnType::Fast` this limit has no effect.
+    pub fn max_total_bytes(mut self, max_total_bytes: u64) -> Self {
+        self.options.max_total_bytes = match max_total_bytes {
+            0 => std::u64::MAX,
+            _ => max_total_bytes,
+        };
+        self
+    }
+
+    /// Stop the scan after the given time.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.options.timeout = timeout;
+        self
+    }
+
+    /// Set maximum number of entries of a directory. Directories with more entries are
+    /// truncated or skipped, depending on `oversized_action`, and reported by `oversized_dirs`.
+    /// The directory is still read and its entries are still stat'ed completely.
+    pub fn max_dir_entries(mut self, max_dir_entries: usize) -> Self {
+        self.options.max_dir_entries = match max_dir_entries {
+            0 => std::usize::MAX,
+            _ => max_dir_entries,
+        };
+        self
+    }
+
+    /// Set handling of directories with more than `max_dir_entries` entries.
+    pub fn oversized_action(mut self, oversized_action: OversizedAction) -> Self {
+        self.options.oversized_action = oversized_action;
+        self
+    }
+
+    /// Set number of threads for reading directories. `0` uses the global rayon thread pool
+    /// and `1` reads all directories in the scanning thread.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.options.parallelism = match threads {
+            0 => Parallelism::Default,
+            1 => Parallelism::Serial,
+            _ => Parallelism::Threads(threads),
+        };
+        self
+    }
+
+    /// Set degree of parallelism, e.g. `Parallelism::Pool` for using a rayon thread pool
+    /// provided by the caller.
+    pub fn parallelism(mut self, parallelism: Parallelism) -> Self {
+        self.options.parallelism = parallelism;
+        self
+    }
+
+    /// Set maximum number of directories read per second. `0` disables the limit.
+    /// A worker waits after reading a directory, so the limit delays its next read.
+    pub fn max_dirs_per_sec(mut self, max_dirs_per_sec: usize) -> Self {
+        self.options.max_dirs_per_sec = match max_dirs_per_sec {
+            0 => std::usize::MAX,
+            _ => max_dirs_per_sec,
+        };
+        self
+    }
+
+    /// Set maximum number of entries whose metadata is read per second. `0` disables
+    /// the limit. Has no effect if no metadata is read. Like `max_dirs_per_sec` the
+    /// limit is applied after a directory was read and delays the next read.
+    pub fn max_stats_per_sec(mut self, max_stats_per_sec: usize) -> Self {
+        self.options.max_stats_per_sec = match max_stats_per_sec {
+            0 => std::usize::MAX,
+            _ => max_stats_per_sec,
+        };
+        self
+    }
+
+    /// Run the scanning threads with idle I/O priority. Only supported on Linux.
+    /// Has no effect on the threads of a pool set with `Parallelism::Pool`.
+    /// `start` fails if the priority cannot be set.
+    pub fn idle_io_priority(mut self, idle_io_priority: bool) -> Self {
+        self.options.idle_io_priority = idle_io_priority;
+        self
+    }
+
+    /// Set nice level of the scanning threads. `0` keeps the current level. Only supported
+    /// on Linux. Has no effect on the threads of a pool set with `Parallelism::Pool`.
+    /// `start` fails if the nice level cannot be set, e.g. a negative level without privileges.
+    pub fn nice(mut self, nice: i32) -> Self {
+        self.options.nice = nice;
+        self
+    }
+
     /// Set directory include filter
     pub fn dir_include(mut self, dir_include: Option<Vec<String>>) -> Self {
         self.options.dir_include = dir_include;
@@ -344,15 +740,81 @@ impl Scandir {
         self
     }
 
-    /// Set extended file type counting
+    /// Set type of returned entries. `ReturnType::Fast` returns only path and file type
+    /// without reading any metadata.
     pub fn return_type(mut self, return_type: ReturnType) -> Self {
         self.options.return_type = return_type;
         self
     }
 
+    /// Read targets of symbolic links.
+    pub fn read_links(mut self, read_links: bool) -> Self {
+        self.options.read_links = read_links;
+        self
+    }
+
+    /// Also read metadata of symbolic link targets. Implies `read_links`.
+    pub fn link_metadata(mut self, link_metadata: bool) -> Self {
+        self.options.link_metadata = link_metadata;
+        if link_metadata {
+            self.options.read_links = true;
+        }
+        self
+    }
+
+    /// Resolve owner and group names. Only relevant for `ReturnType::Ext`.
+    pub fn resolve_owners(mut self, resolve_owners: bool) -> Self {
+        self.options.resolve_owners = resolve_owners;
+        self
+    }
+
+    /// Periodically write a checkpoint to file `checkpoint`, from which the scan can be
+    /// resumed with `resume_from`. The checkpoint is also written when the scan has finished
+    /// or has been stopped. Not supported for `TraversalOrder::BreadthFirst`.
+    ///
+    /// A directory is completed as soon as all of its entries have been passed to the
+    /// results of the instance, not when they have been fetched with `results`. So if the
+    /// process is killed, the entries which have not been fetched yet are lost. Entries of
+    /// directories which have not been completed may be returned again after resuming.
+    pub fn checkpoint(mut self, checkpoint: Option<PathBuf>) -> Self {
+        self.options.checkpoint = checkpoint;
+        self
+    }
+
+    /// Set interval for writing checkpoints. Default is 60s.
+    pub fn checkpoint_interval(mut self, checkpoint_interval: Duration) -> Self {
+        self.options.checkpoint_interval = checkpoint_interval;
+        self
+    }
+
+    /// Keep all results in the instance, so that they can be returned again with
+    /// `return_all`. Enabled by default. If disabled, results are only returned once,
+    /// which keeps the memory bounded when they are consumed while the scan is running.
+    pub fn store(mut self, store: bool) -> Self {
+        self.store = store;
+        self
+    }
+
+    /// Set callback for progress reports. The callback is called from the worker threads
+    /// at most once per `progress_interval` and once when the scan has finished.
+    pub fn progress_callback<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&Progress) + Send + Sync + 'static,
+    {
+        self.progress_callback = Some(ProgressCallback(Arc::new(callback)));
+        self
+    }
+
+    /// Set minimum interval between two progress reports. Default is 100ms.
+    pub fn progress_interval(mut self, progress_interval: Duration) -> Self {
+        self.progress_interval = progress_interval;
+        self
+    }
+
     pub fn clear(&mut self) {
         self.entries.clear();
         self.errors.clear();
+        self.oversized_dirs.lock().unwrap().clear();
         *self.duration.lock().unwrap() = 0.0;
     }
 
@@ -366,18 +828,51 @@ impl Scandir {
                 "Parameter return_type has invalid value",
             ));
         }
+        if (self.options.checkpoint.is_some() || self.resume.is_some())
+            && self.options.order == TraversalOrder::BreadthFirst
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Checkpoints are not supported for breadth-first order",
+            ));
+        }
         self.clear();
-        let options = self.options.clone();
+        let mut options = self.options.clone();
+        options.parallelism = self.thread_pool.parallelism(&options);
         let filter = create_filter(&options)?;
+        check_thread_priority(options.idle_io_priority, options.nice)?;
         let (tx, rx) = unbounded();
         self.rx = Some(rx);
         self.stop.store(false, Ordering::Relaxed);
+        let name_cache = self.name_cache.clone();
+        let timer = RunTimer::new(self.pause.clone());
+        let budget = Arc::new(Budget::new(&options, timer.clone()));
+        self.budget = Some(budget.clone());
+        let progress = Arc::new(ProgressTracker::new(
+            self.progress_callback.clone(),
+            self.progress_interval,
+            timer.clone(),
+        ));
+        self.progress = Some(progress.clone());
+        let oversized_dirs = self.oversized_dirs.clone();
+        let resume = self.resume.clone();
         let stop = self.stop.clone();
+        let pause = self.pause.clone();
         let duration = self.duration.clone();
         self.thr = Some(thread::spawn(move || {
-            let start_time = Instant::now();
-            entries_thread(options, filter, tx, stop);
-            *duration.lock().unwrap() = start_time.elapsed().as_secs_f64();
+            entries_thread(
+                options,
+                filter,
+                name_cache,
+                budget,
+                progress,
+                oversized_dirs,
+                resume,
+                tx,
+                stop,
+                pause,
+            );
+            *duration.lock().unwrap() = timer.elapsed().as_secs_f64();
         }));
         Ok(())
     }
@@ -395,6 +890,7 @@ impl Scandir {
     pub fn stop(&mut self) -> bool {
         if let Some(thr) = self.thr.take() {
             self.stop.store(true, Ordering::Relaxed);
+            self.pause.resume();
             if let Err(_e) = thr.join() {
                 return false;
             }
@@ -403,6 +899,25 @@ impl Scandir {
         false
     }
 
+    /// Pause the scan. Each worker still reads one more directory and then waits until
+    /// `resume` is called. The paused time does not count for the timeout and the
+    /// duration. `stop` also resumes a paused scan. With `Parallelism::Default` the waiting
+    /// workers block threads of the global rayon thread pool and so also other scans
+    /// using it. Pausing a scan while others are running needs its own thread pool.
+    pub fn pause(&self) {
+        self.pause.pause();
+    }
+
+    /// Resume a paused scan.
+    pub fn resume(&self) {
+        self.pause.resume();
+    }
+
+    /// Returns `true` if the scan is paused.
+    pub fn paused(&self) -> bool {
+        self.pause.is_paused()
+    }
+
     fn receive_all(&mut self) -> (Vec<ScandirResult>, Vec<(String, String)>) {
         let mut entries: Vec<ScandirResult> = Vec::new();
         let mut errors: Vec<(String, String)> = Vec::new();
@@ -460,9 +975,11 @@ impl Scandir {
 
     pub fn results(&mut self, return_all: bool) -> (Vec<ScandirResult>, Vec<(String, String)>) {
         let (entries, errors) = self.receive_all();
-        self.entries.extend_from_slice(&entries);
-        self.errors.extend(errors.clone());
-        if return_all {
+        if self.store {
+            self.entries.extend_from_slice(&entries);
+            self.errors.extend(errors.clone());
+        }
+        if return_all && self.store {
             return (self.entries.clone(), self.errors.clone());
         }
         (entries, errors)
@@ -506,6 +1023,25 @@ impl Scandir {
         self.results(return_all).1
     }
 
+    /// Budget which stopped the scan. `None` if the scan was complete or stopped by the user.
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.budget.as_ref().and_then(|budget| budget.stop_reason())
+    }
+
+    /// Progress of the scan. Can be called while the scan is running.
+    pub fn progress(&self) -> Progress {
+        self.progress
+            .as_ref()
+            .map(|progress| progress.progress())
+            .unwrap_or_default()
+    }
+
+    /// Relative paths and numbers of entries of the directories with more than
+    /// `max_dir_entries` entries.
+    pub fn oversized_dirs(&self) -> Vec<(String, usize)> {
+        self.oversized_dirs.lock().unwrap().clone()
+    }
+
     pub fn duration(&mut self) -> f64 {
         *self.duration.lock().unwrap()
     }
@@ -0,0 +1,444 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::def::{DirEntryExt, ExportColumn, ScandirResult, Toc};
+use crate::export::{export_scandir, for_each_toc_row, ExportSink, Row, Value};
+use crate::scandir::Scandir;
+
+/// Version of the database schema. Stored as `user_version` of the database.
+pub const SCHEMA_VERSION: i32 = 1;
+
+/// Default number of entries written in one transaction
+pub const DEFAULT_BATCH_SIZE: usize = 10000;
+
+const SCHEMA: &str = r#"
+CREATE TABLE meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+CREATE TABLE directories (
+    id INTEGER PRIMARY KEY,
+    parent_id INTEGER REFERENCES directories(id),
+    path TEXT NOT NULL UNIQUE,
+    st_ctime REAL,
+    st_mtime REAL,
+    st_atime REAL,
+    st_mode INTEGER,
+    st_uid INTEGER,
+    st_gid INTEGER,
+    owner TEXT,
+    "group" TEXT,
+    files INTEGER NOT NULL DEFAULT 0,
+    dirs INTEGER NOT NULL DEFAULT 0,
+    size INTEGER NOT NULL DEFAULT 0,
+    total_files INTEGER NOT NULL DEFAULT 0,
+    total_dirs INTEGER NOT NULL DEFAULT 0,
+    total_size INTEGER NOT NULL DEFAULT 0,
+    generation INTEGER NOT NULL
+);
+CREATE INDEX directories_parent_id ON directories(parent_id);
+CREATE TABLE files (
+    id INTEGER PRIMARY KEY,
+    parent_id INTEGER NOT NULL REFERENCES directories(id),
+    path TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    is_symlink INTEGER NOT NULL,
+    is_file INTEGER NOT NULL,
+    st_size INTEGER,
+    st_ctime REAL,
+    st_mtime REAL,
+    st_atime REAL,
+    st_mode INTEGER,
+    st_uid INTEGER,
+    st_gid INTEGER,
+    owner TEXT,
+    "group" TEXT,
+    link_target TEXT,
+    generation INTEGER NOT NULL
+);
+CREATE INDEX files_parent_id ON files(parent_id);
+CREATE TABLE errors (
+    path TEXT NOT NULL,
+    error TEXT NOT NULL,
+    generation INTEGER NOT NULL
+);
+"#;
+
+const UPSERT_DIR: &str = r#"
+INSERT INTO directories (parent_id, path, st_ctime, st_mtime, st_atime, st_mode, st_uid,
+    st_gid, owner, "group", generation)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+ON CONFLICT(path) DO UPDATE SET parent_id = excluded.parent_id,
+    st_ctime = excluded.st_ctime, st_mtime = excluded.st_mtime,
+    st_atime = excluded.st_atime, st_mode = excluded.st_mode, st_uid = excluded.st_uid,
+    st_gid = excluded.st_gid, owner = excluded.owner, "group" = excluded."group",
+    generation = excluded.generation
+RETURNING id
+"#;
+
+const TOUCH_DIR: &str = r#"
+INSERT INTO directories (parent_id, path, generation) VALUES (?1, ?2, ?3)
+ON CONFLICT(path) DO UPDATE SET generation = excluded.generation
+RETURNING id
+"#;
+
+const UPSERT_FILE: &str = r#"
+INSERT INTO files (parent_id, path, name, is_symlink, is_file, st_size, st_ctime, st_mtime,
+    st_atime, st_mode, st_uid, st_gid, owner, "group", link_target, generation)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)
+ON CONFLICT(path) DO UPDATE SET parent_id = excluded.parent_id, name = excluded.name,
+    is_symlink = excluded.is_symlink, is_file = excluded.is_file,
+    st_size = excluded.st_size, st_ctime = excluded.st_ctime,
+    st_mtime = excluded.st_mtime, st_atime = excluded.st_atime,
+    st_mode = excluded.st_mode, st_uid = excluded.st_uid, st_gid = excluded.st_gid,
+    owner = excluded.owner, "group" = excluded."group",
+    link_target = excluded.link_target, generation = excluded.generation
+"#;
+
+const UPDATE_DIR_TOTALS: &str = r#"
+UPDATE directories SET
+    files = (SELECT COUNT(*) FROM files WHERE files.parent_id = directories.id),
+    size = (SELECT COALESCE(SUM(st_size), 0) FROM files
+        WHERE files.parent_id = directories.id),
+    dirs = (SELECT COUNT(*) FROM directories AS d WHERE d.parent_id = directories.id)
+"#;
+
+fn to_sql(value: Value) -> rusqlite::types::Value {
+    match value {
+        Value::Null => rusqlite::types::Value::Null,
+        Value::Bool(v) => rusqlite::types::Value::Integer(v as i64),
+        Value::U64(v) => rusqlite::types::Value::Integer(v as i64),
+        Value::F64(v) => rusqlite::types::Value::Real(v),
+        Value::Str(v) => rusqlite::types::Value::Text(v.to_string()),
+    }
+}
+
+fn parent_path(path: &str) -> &str {
+    Path::new(path)
+        .parent()
+        .and_then(|parent| parent.to_str())
+        .unwrap_or("")
+}
+
+fn get_meta(conn: &Connection, key: &str) -> rusqlite::Result<Option<String>> {
+    conn.query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| {
+        row.get(0)
+    })
+    .optional()
+}
+
+fn set_meta(conn: &Connection, key: &str, value: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO meta (key, value) VALUES (?1, ?2) \
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        [key, value],
+    )?;
+    Ok(())
+}
+
+/// SQLite index of the entries below a root directory. Opening an existing index and
+/// writing the results of a new scan updates it. The entries are written in batched
+/// transactions while the scan is running.
+///
+/// The table `files` contains all entries which are not directories, with the id of the
+/// parent directory in `parent_id`. The table `directories` contains the directories with
+/// the totals of their direct entries (`files`, `dirs`, `size`) and of all entries below
+/// them (`total_files`, `total_dirs`, `total_size`). The directory with the empty path is
+/// the root directory. Errors are stored in table `errors`.
+#[derive(Debug)]
+pub struct SqliteIndex {
+    conn: Connection,
+    generation: i64,
+    batch_size: usize,
+    batch_rows: usize,
+    dir_ids: HashMap<String, i64>,
+    rows: u64,
+    errors: u64,
+}
+
+impl SqliteIndex {
+    /// Create or open the index in database `path` for directory `root_path`. An existing
+    /// index must have been created for the same `root_path` with the current
+    /// `SCHEMA_VERSION`.
+    pub fn open<P: AsRef<Path>>(path: P, root_path: &str) -> Result<Self, Error> {
+        let conn = Connection::open(path).map_err(Error::other)?;
+        let version: i32 = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(Error::other)?;
+        if version == 0 {
+            // A failed creation must not leave a partial schema behind
+            conn.execute_batch("BEGIN").map_err(Error::other)?;
+            conn.execute_batch(SCHEMA).map_err(Error::other)?;
+            conn.pragma_update(None, "user_version", SCHEMA_VERSION)
+                .map_err(Error::other)?;
+            set_meta(&conn, "root_path", root_path).map_err(Error::other)?;
+            conn.execute_batch("COMMIT").map_err(Error::other)?;
+        } else if version != SCHEMA_VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Unsupported schema version {}", version),
+            ));
+        } else if get_meta(&conn, "root_path")
+            .map_err(Error::other)?
+            .as_deref()
+            != Some(root_path)
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Index is for a different root path",
+            ));
+        }
+        let generation = get_meta(&conn, "generation")
+            .map_err(Error::other)?
+            .and_then(|generation| generation.parse::<i64>().ok())
+            .unwrap_or(0)
+            + 1;
+        conn.execute_batch("BEGIN").map_err(Error::other)?;
+        set_meta(&conn, "generation", &generation.to_string()).map_err(Error::other)?;
+        let mut index = SqliteIndex {
+            conn,
+            generation,
+            batch_size: DEFAULT_BATCH_SIZE,
+            batch_rows: 0,
+            dir_ids: HashMap::new(),
+            rows: 0,
+            errors: 0,
+        };
+        index.dir_id("")?;
+        Ok(index)
+    }
+
+    /// Set maximum number of entries written in one transaction.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Number of the update. Is 1 for a new index and incremented with every update.
+    pub fn generation(&self) -> i64 {
+        self.generation
+    }
+
+    /// Number of entries written
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    /// Number of errors written
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// Id of directory `path`. Directories which are not in the index yet are added
+    /// without metadata, which is set when the entry of the directory is written.
+    fn dir_id(&mut self, path: &str) -> Result<i64, Error> {
+        if let Some(id) = self.dir_ids.get(path) {
+            return Ok(*id);
+        }
+        let parent_id = match path.is_empty() {
+            true => None,
+            false => Some(self.dir_id(parent_path(path))?),
+        };
+        let id = self
+            .conn
+            .prepare_cached(TOUCH_DIR)
+            .and_then(|mut stmt| {
+                stmt.query_row(params![parent_id, path, self.generation], |row| row.get(0))
+            })
+            .map_err(Error::other)?;
+        self.dir_ids.insert(path.to_string(), id);
+        Ok(id)
+    }
+
+    fn write_row(&mut self, row: &Row) -> Result<(), Error> {
+        let path = match row.value(ExportColumn::Path) {
+            Value::Str(path) => path,
+            _ => return Ok(()),
+        };
+        if let Row::Error { error, .. } = row {
+            self.conn
+                .execute(
+                    "INSERT INTO errors (path, error, generation) VALUES (?1, ?2, ?3)",
+                    params![path, error, self.generation],
+                )
+                .map_err(Error::other)?;
+            self.errors += 1;
+        } else if matches!(row.value(ExportColumn::IsDir), Value::Bool(true)) {
+            let parent_id = match path.is_empty() {
+                true => None,
+                false => Some(self.dir_id(parent_path(path))?),
+            };
+            let mut values = vec![
+                rusqlite::types::Value::from(parent_id),
+                rusqlite::types::Value::Text(path.to_string()),
+            ];
+            for column in [
+                ExportColumn::StCtime,
+                ExportColumn::StMtime,
+                ExportColumn::StAtime,
+                ExportColumn::StMode,
+                ExportColumn::StUid,
+                ExportColumn::StGid,
+                ExportColumn::Owner,
+                ExportColumn::Group,
+            ] {
+                values.push(to_sql(row.value(column)));
+            }
+            values.push(rusqlite::types::Value::Integer(self.generation));
+            let id = self
+                .conn
+                .prepare_cached(UPSERT_DIR)
+                .and_then(|mut stmt| {
+                    stmt.query_row(rusqlite::params_from_iter(values), |row| row.get(0))
+                })
+                .map_err(Error::other)?;
+            self.dir_ids.insert(path.to_string(), id);
+        } else {
+            let parent_id = self.dir_id(parent_path(path))?;
+            let name = Path::new(path)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let mut values = vec![
+                rusqlite::types::Value::Integer(parent_id),
+                rusqlite::types::Value::Text(path.to_string()),
+                rusqlite::types::Value::Text(name),
+            ];
+            for column in [
+                ExportColumn::IsSymlink,
+                ExportColumn::IsFile,
+                ExportColumn::StSize,
+                ExportColumn::StCtime,
+                ExportColumn::StMtime,
+                ExportColumn::StAtime,
+                ExportColumn::StMode,
+                ExportColumn::StUid,
+                ExportColumn::StGid,
+                ExportColumn::Owner,
+                ExportColumn::Group,
+                ExportColumn::LinkTarget,
+            ] {
+                values.push(to_sql(row.value(column)));
+            }
+            values.push(rusqlite::types::Value::Integer(self.generation));
+            self.conn
+                .prepare_cached(UPSERT_FILE)
+                .and_then(|mut stmt| stmt.execute(rusqlite::params_from_iter(values)))
+                .map_err(Error::other)?;
+        }
+        self.rows += 1;
+        self.batch_rows += 1;
+        if self.batch_rows >= self.batch_size {
+            self.batch_rows = 0;
+            self.conn
+                .execute_batch("COMMIT; BEGIN")
+                .map_err(Error::other)?;
+        }
+        Ok(())
+    }
+
+    /// Update the totals of all directories.
+    fn update_totals(&mut self) -> rusqlite::Result<()> {
+        self.conn.execute(UPDATE_DIR_TOTALS, [])?;
+        // Subdirectories have longer paths than their parents, so they are summed up first
+        let dirs = self
+            .conn
+            .prepare("SELECT id, parent_id, files, dirs, size FROM directories ORDER BY length(path) DESC")?
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, Option<i64>>(1)?,
+                    [row.get::<_, i64>(2)?, row.get::<_, i64>(3)?, row.get::<_, i64>(4)?],
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let mut totals: HashMap<i64, [i64; 3]> = HashMap::new();
+        let mut stmt = self.conn.prepare(
+            "UPDATE directories SET total_files = ?2, total_dirs = ?3, total_size = ?4 \
+             WHERE id = ?1",
+        )?;
+        for (id, parent_id, own) in dirs {
+            let below = totals.remove(&id).unwrap_or_default();
+            let total = [own[0] + below[0], own[1] + below[1], own[2] + below[2]];
+            stmt.execute(params![id, total[0], total[1], total[2]])?;
+            if let Some(parent_id) = parent_id {
+                let parent = totals.entry(parent_id).or_default();
+                for i in 0..3 {
+                    parent[i] += total[i];
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Commit the last entries and update the totals of the directories. With `prune`
+    /// all entries which were not written since opening the index are removed. This
+    /// should only be done after a complete scan. Returns the number of removed entries.
+    pub fn finish(mut self, prune: bool) -> Result<u64, Error> {
+        let mut removed = 0;
+        if prune {
+            for table in ["files", "directories"] {
+                removed += self
+                    .conn
+                    .execute(
+                        &format!("DELETE FROM {} WHERE generation < ?1", table),
+                        [self.generation],
+                    )
+                    .map_err(Error::other)? as u64;
+            }
+            self.conn
+                .execute(
+                    "DELETE FROM errors WHERE generation < ?1",
+                    [self.generation],
+                )
+                .map_err(Error::other)?;
+        }
+        self.update_totals().map_err(Error::other)?;
+        self.conn.execute_batch("COMMIT").map_err(Error::other)?;
+        Ok(removed)
+    }
+}
+
+impl ExportSink for SqliteIndex {
+    fn write_result(&mut self, result: &ScandirResult) -> Result<(), Error> {
+        self.write_row(&Row::from(result))
+    }
+
+    fn write_error(&mut self, path: &str, error: &str) -> Result<(), Error> {
+        self.write_row(&Row::Error { path, error })
+    }
+
+    fn write_toc(
+        &mut self,
+        dir_path: &str,
+        toc: &Toc,
+        entries: Option<&HashMap<String, DirEntryExt>>,
+    ) -> Result<(), Error> {
+        for_each_toc_row(dir_path, toc, entries, |row| self.write_row(row))
+    }
+}
+
+/// Create or update the index in database `path` with the results of `instance` while
+/// the scan is running. Entries which no longer exist are removed from the index, unless
+/// the scan was stopped by a limit, directories were truncated or errors occurred. Returns the number of
+/// written entries.
+pub fn index_scandir<P: AsRef<Path>>(
+    instance: &mut Scandir,
+    path: P,
+    batch_size: usize,
+) -> Result<u64, Error> {
+    let root_path = instance.options().root_path.to_string_lossy().to_string();
+    let mut index = SqliteIndex::open(path, &root_path)?.batch_size(batch_size);
+    export_scandir(instance, &mut index)?;
+    let rows = index.rows();
+    // Entries below unreadable directories were not written and must be kept. The errors
+    // are counted by the index, because `instance` keeps none with `store(false)`.
+    let complete = instance.stop_reason().is_none()
+        && instance.oversized_dirs().is_empty()
+        && index.errors() == 0;
+    index.finish(complete)?;
+    Ok(rows)
+}
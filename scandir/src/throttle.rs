@@ -0,0 +1,144 @@
+use std::io::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::def::Options;
+
+/// Longest sleep before the stop flag is checked again
+const MAX_SLEEP: Duration = Duration::from_millis(50);
+
+/// Limits the rate of operations of all workers. Each operation reserves the next free
+/// time slot, so the rate is never exceeded, not even for a short burst.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// Create a rate limiter for `rate` operations per second.
+    pub fn new(rate: usize) -> Self {
+        RateLimiter {
+            rate: rate.max(1) as f64,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait until `cnt` operations are allowed or `stop` is set.
+    pub fn acquire(&self, cnt: usize, stop: &AtomicBool) {
+        if cnt == 0 {
+            return;
+        }
+        let start = {
+            let mut next = self.next.lock().unwrap();
+            let now = Instant::now();
+            if *next < now {
+                *next = now;
+            }
+            let start = *next;
+            *next += Duration::from_secs_f64(cnt as f64 / self.rate);
+            start
+        };
+        while !stop.load(Ordering::Relaxed) {
+            let wait = start.saturating_duration_since(Instant::now());
+            if wait.is_zero() {
+                break;
+            }
+            thread::sleep(wait.min(MAX_SLEEP));
+        }
+    }
+}
+
+/// Rate limits of a scan, shared by all workers.
+#[derive(Debug)]
+pub struct Throttle {
+    dirs: Option<RateLimiter>,
+    stats: Option<RateLimiter>,
+}
+
+impl Throttle {
+    pub fn new(options: &Options) -> Self {
+        Throttle {
+            dirs: match options.max_dirs_per_sec {
+                std::usize::MAX => None,
+                rate => Some(RateLimiter::new(rate)),
+            },
+            stats: match options.max_stats_per_sec {
+                std::usize::MAX => None,
+                rate => Some(RateLimiter::new(rate)),
+            },
+        }
+    }
+
+    /// Wait until a directory with `stats` stat calls may be processed. This is called
+    /// after the directory was read, so the wait delays the next read of the worker.
+    pub fn wait(&self, stats: usize, stop: &AtomicBool) {
+        if let Some(ref dirs) = self.dirs {
+            dirs.acquire(1, stop);
+        }
+        if let Some(ref limiter) = self.stats {
+            limiter.acquire(stats, stop);
+        }
+    }
+}
+
+/// Lower the priority of the calling thread as requested by `idle_io_priority` and `nice`.
+/// Only supported on Linux, on other platforms this does nothing. Returns the error of
+/// the failed system call.
+#[cfg(target_os = "linux")]
+pub fn set_thread_priority(idle_io_priority: bool, nice: i32) -> Result<(), Error> {
+    // Both calls only affect the calling thread, if `who` is the thread id or 0
+    if idle_io_priority {
+        const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+        const IOPRIO_CLASS_IDLE: libc::c_long = 3;
+        const IOPRIO_CLASS_SHIFT: libc::c_long = 13;
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_ioprio_set,
+                IOPRIO_WHO_PROCESS,
+                0,
+                IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+            )
+        };
+        if ret < 0 {
+            let err = Error::last_os_error();
+            return Err(Error::new(
+                err.kind(),
+                format!("Failed to set idle I/O priority: {}", err),
+            ));
+        }
+    }
+    if nice != 0 {
+        let ret = unsafe {
+            let tid = libc::syscall(libc::SYS_gettid) as libc::id_t;
+            libc::setpriority(libc::PRIO_PROCESS, tid, nice)
+        };
+        if ret < 0 {
+            let err = Error::last_os_error();
+            return Err(Error::new(
+                err.kind(),
+                format!("Failed to set nice level {}: {}", nice, err),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_thread_priority(_idle_io_priority: bool, _nice: i32) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Check that the priority of the workers can be lowered as requested, by lowering the
+/// priority of a short-lived thread. The workers lower their priority in the same way,
+/// so they can ignore the result if this check succeeded.
+pub fn check_thread_priority(idle_io_priority: bool, nice: i32) -> Result<(), Error> {
+    if !idle_io_priority && nice == 0 {
+        return Ok(());
+    }
+    thread::spawn(move || set_thread_priority(idle_io_priority, nice))
+        .join()
+        .unwrap_or_else(|_| Err(Error::other("Failed to set thread priority")))
+}
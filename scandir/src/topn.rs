@@ -0,0 +1,449 @@
+use std::cmp::{Ordering as CmpOrdering, Reverse};
+use std::collections::BinaryHeap;
+use std::fs::Metadata;
+use std::io::{Error, ErrorKind};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use flume::{unbounded, Receiver, Sender};
+use jwalk::WalkDirGeneric;
+
+use crate::common::{
+    check_and_expand_path, create_filter, filter_children, get_root_path_len, jwalk_parallelism,
+    split_error, worker_slot, worker_threads, ThreadPoolCache,
+};
+use crate::def::{DirEntryExt, Filter, Options, Parallelism, ReturnType, ScandirResult, TopNKey};
+use crate::scandir::{create_entry, Stats};
+
+#[derive(Debug)]
+struct HeapEntry {
+    key: f64,
+    entry: DirEntryExt,
+}
+
+impl PartialEq for HeapEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == CmpOrdering::Equal
+    }
+}
+
+impl Eq for HeapEntry {}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HeapEntry {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        self.key
+            .total_cmp(&other.key)
+            .then_with(|| self.entry.path.cmp(&other.entry.path))
+    }
+}
+
+type Heap = BinaryHeap<Reverse<HeapEntry>>;
+
+#[inline]
+fn get_key(entry: &DirEntryExt, key: &TopNKey) -> f64 {
+    match key {
+        TopNKey::Size => entry.st_size as f64,
+        TopNKey::Mtime => entry.st_mtime,
+        TopNKey::Atime => entry.st_atime,
+        TopNKey::Blocks => entry.st_blocks as f64,
+    }
+}
+
+/// Add `entry` to `heap` if it is one of the best `n` entries.
+fn push_bounded(heap: &mut Heap, entry: HeapEntry, n: usize) {
+    if heap.len() >= n {
+        if let Some(lowest) = heap.peek() {
+            if entry <= lowest.0 {
+                return;
+            }
+        }
+    }
+    heap.push(Reverse(entry));
+    if heap.len() > n {
+        heap.pop();
+    }
+}
+
+/// Bounded heaps of the worker threads. Each worker adds the files of the directories
+/// it reads to its own slot, so the workers do not block each other.
+struct Heaps {
+    n: usize,
+    slots: Vec<Mutex<Heap>>,
+    changed: AtomicBool,
+}
+
+impl Heaps {
+    fn new(parallelism: &Parallelism, n: usize) -> Self {
+        Heaps {
+            n,
+            slots: (0..=worker_threads(parallelism))
+                .map(|_| Mutex::new(BinaryHeap::with_capacity(n + 1)))
+                .collect(),
+            changed: AtomicBool::new(false),
+        }
+    }
+
+    fn push(&self, entries: Vec<HeapEntry>) {
+        if entries.is_empty() {
+            return;
+        }
+        let mut heap = self.slots[worker_slot(self.slots.len())].lock().unwrap();
+        for entry in entries {
+            push_bounded(&mut heap, entry, self.n);
+        }
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    /// Return the best `n` entries of all slots ordered by key, starting with the best match.
+    fn snapshot(&self) -> Vec<DirEntryExt> {
+        self.changed.store(false, Ordering::Relaxed);
+        let mut entries: Vec<HeapEntry> = Vec::new();
+        for slot in &self.slots {
+            entries.extend(slot.lock().unwrap().iter().map(|e| HeapEntry {
+                key: e.0.key,
+                entry: e.0.entry.clone(),
+            }));
+        }
+        entries.sort_by(|a, b| b.cmp(a));
+        entries.truncate(self.n);
+        entries.into_iter().map(|e| e.entry).collect()
+    }
+}
+
+fn topn_thread(
+    options: Options,
+    filter: Option<Filter>,
+    n: usize,
+    key: TopNKey,
+    reverse: bool,
+    tx: Sender<Vec<DirEntryExt>>,
+    errors: Arc<Mutex<Vec<(String, String)>>>,
+    stop: Arc<AtomicBool>,
+) {
+    let mut update_time = Instant::now();
+    let root_path_len = get_root_path_len(&options.root_path);
+    let heaps = Arc::new(Heaps::new(&options.parallelism, n));
+    let heaps_cloned = heaps.clone();
+    let stop_cloned = stop.clone();
+    for entry in WalkDirGeneric::<((), Option<Result<Metadata, Error>>)>::new(&options.root_path)
+        .skip_hidden(options.skip_hidden)
+        .sort(false)
+        .max_depth(options.max_depth)
+        .parallelism(jwalk_parallelism(&options))
+        .read_metadata(true)
+        .read_metadata_ext(true)
+        .process_read_dir(move |_, root_dir, _, children| {
+            if stop_cloned.load(Ordering::Relaxed) {
+                return;
+            }
+            if let Some(root_dir) = root_dir.to_str() {
+                if root_dir.len() + 1 < root_path_len {
+                    return;
+                }
+            } else {
+                return;
+            }
+            filter_children(children, &filter, root_path_len);
+            let mut entries = Heap::new();
+            for dir_entry in children.iter().flatten() {
+                if !dir_entry.file_type.is_file() {
+                    continue;
+                }
+                let (_, entry) = create_entry(
+                    root_path_len,
+                    &ReturnType::Ext,
+                    false,
+                    false,
+                    None,
+                    dir_entry,
+                );
+                if let Stats::ScandirResult(ScandirResult::DirEntryExt(dir_entry)) = entry.entry {
+                    let mut entry_key = get_key(&dir_entry, &key);
+                    if reverse {
+                        entry_key = -entry_key;
+                    }
+                    push_bounded(
+                        &mut entries,
+                        HeapEntry {
+                            key: entry_key,
+                            entry: dir_entry,
+                        },
+                        n,
+                    );
+                }
+            }
+            heaps_cloned.push(entries.into_vec().into_iter().map(|e| e.0).collect());
+        })
+    {
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+        // The files are added by the workers, only the errors are collected here
+        let error = match &entry {
+            Ok(v) => v.read_children_error.as_ref(),
+            Err(e) => Some(e),
+        };
+        if let Some(e) = error {
+            errors.lock().unwrap().push(split_error(root_path_len, e));
+        }
+        if heaps.changed.load(Ordering::Relaxed) && update_time.elapsed().as_millis() >= 10 {
+            let _ = tx.send(heaps.snapshot());
+            update_time = Instant::now();
+        }
+    }
+    let _ = tx.send(heaps.snapshot());
+}
+
+/// Class for finding the N entries with the largest (or smallest) size, mtime, atime or blocks
+#[derive(Debug)]
+pub struct TopN {
+    // Options
+    options: Options,
+    n: usize,
+    key: TopNKey,
+    reverse: bool,
+    // Results
+    entries: Vec<DirEntryExt>,
+    errors: Arc<Mutex<Vec<(String, String)>>>,
+    duration: Arc<Mutex<f64>>,
+    // Internal
+    thr: Option<thread::JoinHandle<()>>,
+    stop: Arc<AtomicBool>,
+    rx: Option<Receiver<Vec<DirEntryExt>>>,
+    thread_pool: ThreadPoolCache,
+}
+
+impl TopN {
+    pub fn new(root_path: &str, n: usize) -> Result<Self, Error> {
+        Ok(TopN {
+            options: Options {
+                return_type: ReturnType::Ext,
+                ..Options::new(check_and_expand_path(&root_path)?)
+            },
+            n,
+            key: TopNKey::Size,
+            reverse: false,
+            entries: Vec::new(),
+            errors: Arc::new(Mutex::new(Vec::new())),
+            duration: Arc::new(Mutex::new(0.0)),
+            thr: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            rx: None,
+            thread_pool: ThreadPoolCache::default(),
+        })
+    }
+
+    /// Set the key by which the entries are selected. Default is `TopNKey::Size`.
+    pub fn key(mut self, key: TopNKey) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// Return the entries with the smallest keys instead of the largest ones
+    /// (e.g. the oldest instead of the newest files).
+    pub fn reverse(mut self, reverse: bool) -> Self {
+        self.reverse = reverse;
+        self
+    }
+
+    /// Skip hidden entries. Enabled by default.
+    pub fn skip_hidden(mut self, skip_hidden: bool) -> Self {
+        self.options.skip_hidden = skip_hidden;
+        self
+    }
+
+    /// Set the maximum depth of entries yield by the iterator.
+    ///
+    /// The smallest depth is `0` and always corresponds to the path given
+    /// to the `new` function on this type. Its direct descendents have depth
+    /// `1`, and their descendents have depth `2`, and so on.
+    ///
+    /// Note that this will not simply filter the entries of the iterator, but
+    /// it will actually avoid descending into directories when the depth is
+    /// exceeded.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.options.max_depth = match depth {
+            0 => std::usize::MAX,
+            _ => depth,
+        };
+        self
+    }
+
+    /// Set number of threads for reading directories. `0` uses the global rayon thread pool
+    /// and `1` reads all directories in the scanning thread.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.options.parallelism = match threads {
+            0 => Parallelism::Default,
+            1 => Parallelism::Serial,
+            _ => Parallelism::Threads(threads),
+        };
+        self
+    }
+
+    /// Set degree of parallelism, e.g. `Parallelism::Pool` for using a rayon thread pool
+    /// provided by the caller.
+    pub fn parallelism(mut self, parallelism: Parallelism) -> Self {
+        self.options.parallelism = parallelism;
+        self
+    }
+
+    /// Set directory include filter
+    pub fn dir_include(mut self, dir_include: Option<Vec<String>>) -> Self {
+        self.options.dir_include = dir_include;
+        self
+    }
+
+    /// Set directory exclude filter
+    pub fn dir_exclude(mut self, dir_exclude: Option<Vec<String>>) -> Self {
+        self.options.dir_exclude = dir_exclude;
+        self
+    }
+
+    /// Set file include filter
+    pub fn file_include(mut self, file_include: Option<Vec<String>>) -> Self {
+        self.options.file_include = file_include;
+        self
+    }
+
+    /// Set file exclude filter
+    pub fn file_exclude(mut self, file_exclude: Option<Vec<String>>) -> Self {
+        self.options.file_exclude = file_exclude;
+        self
+    }
+
+    /// Set case sensitive filename filtering
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.options.case_sensitive = case_sensitive;
+        self
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.errors.lock().unwrap().clear();
+        *self.duration.lock().unwrap() = 0.0;
+    }
+
+    pub fn start(&mut self) -> Result<(), Error> {
+        if self.busy() {
+            return Err(Error::new(ErrorKind::Other, "Busy"));
+        }
+        if self.n == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Parameter n has invalid value",
+            ));
+        }
+        self.clear();
+        let mut options = self.options.clone();
+        options.parallelism = self.thread_pool.parallelism(&options);
+        let filter = create_filter(&options)?;
+        let n = self.n;
+        let key = self.key.clone();
+        let reverse = self.reverse;
+        let (tx, rx) = unbounded();
+        self.rx = Some(rx);
+        self.stop.store(false, Ordering::Relaxed);
+        let errors = self.errors.clone();
+        let stop = self.stop.clone();
+        let duration = self.duration.clone();
+        self.thr = Some(thread::spawn(move || {
+            let start_time = Instant::now();
+            topn_thread(options, filter, n, key, reverse, tx, errors, stop);
+            *duration.lock().unwrap() = start_time.elapsed().as_secs_f64();
+        }));
+        Ok(())
+    }
+
+    pub fn join(&mut self) -> bool {
+        if let Some(thr) = self.thr.take() {
+            if let Err(_e) = thr.join() {
+                return false;
+            }
+            return true;
+        }
+        false
+    }
+
+    pub fn stop(&mut self) -> bool {
+        if let Some(thr) = self.thr.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            if let Err(_e) = thr.join() {
+                return false;
+            }
+            return true;
+        }
+        false
+    }
+
+    fn receive_all(&mut self) -> Vec<DirEntryExt> {
+        if let Some(ref rx) = self.rx {
+            while let Ok(entries) = rx.try_recv() {
+                self.entries = entries;
+            }
+        }
+        self.entries.clone()
+    }
+
+    pub fn collect(&mut self) -> Result<Vec<DirEntryExt>, Error> {
+        if !self.finished() {
+            if !self.busy() {
+                self.start()?;
+            }
+            self.join();
+        }
+        Ok(self.receive_all())
+    }
+
+    pub fn has_results(&self) -> bool {
+        if let Some(ref rx) = self.rx {
+            if !rx.is_empty() {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Return the current (partial) results.
+    pub fn results(&mut self) -> Vec<DirEntryExt> {
+        self.receive_all()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        !self.errors.lock().unwrap().is_empty()
+    }
+
+    /// Relative paths and messages of the entries and directories which could not be read.
+    pub fn errors(&self) -> Vec<(String, String)> {
+        self.errors.lock().unwrap().clone()
+    }
+
+    pub fn duration(&mut self) -> f64 {
+        *self.duration.lock().unwrap()
+    }
+
+    pub fn finished(&mut self) -> bool {
+        *self.duration.lock().unwrap() > 0.0
+    }
+
+    pub fn busy(&self) -> bool {
+        if let Some(ref thr) = self.thr {
+            !thr.is_finished()
+        } else {
+            false
+        }
+    }
+
+    // For debugging
+
+    pub fn options(&self) -> Options {
+        self.options.clone()
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Thread safe cache for resolving user and group ids to names.
+///
+/// Each id is looked up only once. Ids which cannot be resolved are cached too.
+#[derive(Debug)]
+pub struct NameCache {
+    users: RwLock<HashMap<u32, Option<String>>>,
+    groups: RwLock<HashMap<u32, Option<String>>>,
+}
+
+impl NameCache {
+    pub fn new() -> Self {
+        NameCache {
+            users: RwLock::new(HashMap::new()),
+            groups: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Return name of user with id `uid`.
+    pub fn user(&self, uid: u32) -> Option<String> {
+        if let Some(name) = self.users.read().unwrap().get(&uid) {
+            return name.clone();
+        }
+        let name = lookup_user(uid);
+        self.users.write().unwrap().insert(uid, name.clone());
+        name
+    }
+
+    /// Return name of group with id `gid`.
+    pub fn group(&self, gid: u32) -> Option<String> {
+        if let Some(name) = self.groups.read().unwrap().get(&gid) {
+            return name.clone();
+        }
+        let name = lookup_group(gid);
+        self.groups.write().unwrap().insert(gid, name.clone());
+        name
+    }
+
+    pub fn clear(&self) {
+        self.users.write().unwrap().clear();
+        self.groups.write().unwrap().clear();
+    }
+}
+
+impl Default for NameCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(unix)]
+fn buffer_size(name: libc::c_int) -> usize {
+    match unsafe { libc::sysconf(name) } {
+        n if n > 0 => n as usize,
+        _ => 1024,
+    }
+}
+
+#[cfg(unix)]
+fn lookup_user(uid: u32) -> Option<String> {
+    let mut buf: Vec<libc::c_char> = vec![0; buffer_size(libc::_SC_GETPW_R_SIZE_MAX)];
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+    loop {
+        let rc =
+            unsafe { libc::getpwuid_r(uid, &mut passwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+        if rc == libc::ERANGE && buf.len() < 1 << 20 {
+            buf.resize(buf.len() * 2, 0);
+            continue;
+        }
+        if rc != 0 || result.is_null() {
+            return None;
+        }
+        let name = unsafe { std::ffi::CStr::from_ptr(passwd.pw_name) };
+        return Some(name.to_string_lossy().to_string());
+    }
+}
+
+#[cfg(unix)]
+fn lookup_group(gid: u32) -> Option<String> {
+    let mut buf: Vec<libc::c_char> = vec![0; buffer_size(libc::_SC_GETGR_R_SIZE_MAX)];
+    let mut group: libc::group = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::group = std::ptr::null_mut();
+    loop {
+        let rc =
+            unsafe { libc::getgrgid_r(gid, &mut group, buf.as_mut_ptr(), buf.len(), &mut result) };
+        if rc == libc::ERANGE && buf.len() < 1 << 20 {
+            buf.resize(buf.len() * 2, 0);
+            continue;
+        }
+        if rc != 0 || result.is_null() {
+            return None;
+        }
+        let name = unsafe { std::ffi::CStr::from_ptr(group.gr_name) };
+        return Some(name.to_string_lossy().to_string());
+    }
+}
+
+#[cfg(not(unix))]
+fn lookup_user(_uid: u32) -> Option<String> {
+    None
+}
+
+#[cfg(not(unix))]
+fn lookup_group(_gid: u32) -> Option<String> {
+    None
+}
@@ -1,26 +1,42 @@
-use std::fmt::Debug;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
 use std::fs;
 use std::io::{Error, ErrorKind};
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::path::MAIN_SEPARATOR;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Instant;
+use std::time::Duration;
 
 use flume::{unbounded, Receiver, Sender};
 use jwalk::WalkDirGeneric;
 
-use crate::common::{check_and_expand_path, create_filter, filter_children, get_root_path_len};
+use crate::budget::Budget;
+use crate::common::{
+    check_and_expand_path, create_filter, filter_children, get_root_path_len, jwalk_parallelism,
+    limit_dir_entries, sort_children, subdirs_to_read, LevelBuffer, ThreadPoolCache,
+};
 use crate::def::*;
+use crate::pause::{Pause, RunTimer};
+use crate::progress::{ProgressCallback, ProgressTracker};
+use crate::scandir::{create_entry, Stats};
+use crate::throttle::{check_thread_priority, set_thread_priority, Throttle};
 
 #[inline]
 fn update_toc(
     dir_entry: &jwalk::DirEntry<((), Option<Result<fs::Metadata, Error>>)>,
+    read_links: bool,
     toc: &mut Toc,
 ) {
     let file_type = dir_entry.file_type;
     let key = dir_entry.file_name.clone().into_string().unwrap();
     if file_type.is_symlink() {
-        toc.symlinks.push(key);
+        if read_links && fs::metadata(dir_entry.parent_path.join(&key)).is_err() {
+            toc.broken_symlinks.push(key);
+        } else {
+            toc.symlinks.push(key);
+        }
     } else if file_type.is_dir() {
         toc.dirs.push(key);
     } else if file_type.is_file() {
@@ -30,24 +46,223 @@ fn update_toc(
     }
 }
 
+#[inline]
+fn split_result(result: &WalkResult) -> Option<(String, Toc)> {
+    match result {
+        WalkResult::WalkEntry(e) => Some((e.path.clone(), e.toc.clone())),
+        WalkResult::WalkEntryExt(e) => Some((e.path.clone(), e.toc.clone())),
+        WalkResult::Toc(_) => None,
+    }
+}
+
+/// Callback for pruning subdirectories. Is called with the relative path of each directory
+/// and the names of its subdirectories. Subdirectories removed from the list are not
+/// descended into.
+pub type PruneFn = dyn Fn(&str, &mut Vec<String>) -> bool + Send + Sync;
+
+#[derive(Clone)]
+struct Prune(Arc<PruneFn>);
+
+impl Debug for Prune {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Prune")
+    }
+}
+
+/// Holds results of directories until all of their subdirectories are finished.
+#[derive(Debug, Default)]
+struct PendingDirs {
+    dirs: HashMap<String, (Option<WalkResult>, usize)>,
+}
+
+impl PendingDirs {
+    fn parent(path: &str) -> Option<&str> {
+        if path.is_empty() {
+            return None;
+        }
+        Some(path.rfind(MAIN_SEPARATOR).map(|i| &path[..i]).unwrap_or(""))
+    }
+
+    /// Add result of a directory with `subdirs` subdirectories still to be read.
+    /// Returns all results which are finished now, descendants first.
+    fn add(&mut self, path: String, result: Option<WalkResult>, subdirs: usize) -> Vec<WalkResult> {
+        let mut results = Vec::new();
+        if subdirs > 0 {
+            self.dirs.insert(path, (result, subdirs));
+            return results;
+        }
+        results.extend(result);
+        let mut path = path;
+        while let Some(parent) = Self::parent(&path) {
+            let parent = parent.to_string();
+            match self.dirs.get_mut(&parent) {
+                Some((_, cnt)) if *cnt > 1 => {
+                    *cnt -= 1;
+                    break;
+                }
+                Some(_) => {
+                    if let Some((result, _)) = self.dirs.remove(&parent) {
+                        results.extend(result);
+                    }
+                }
+                None => break,
+            }
+            path = parent;
+        }
+        results
+    }
+
+    /// Return all remaining results, deepest directories first. Remaining results exist if
+    /// subdirectories could not be read or the walk has been stopped.
+    fn flush(&mut self) -> Vec<WalkResult> {
+        let mut dirs: Vec<(String, Option<WalkResult>)> = self
+            .dirs
+            .drain()
+            .map(|(path, (result, _))| (path, result))
+            .collect();
+        dirs.sort_by_key(|(path, _)| {
+            Reverse(path.matches(MAIN_SEPARATOR).count() + !path.is_empty() as usize)
+        });
+        dirs.into_iter().filter_map(|(_, result)| result).collect()
+    }
+}
+
+/// Buffers results until they can be returned in the requested order.
+#[derive(Debug)]
+enum Buffer {
+    BottomUp(PendingDirs),
+    DepthFirst(HashMap<String, WalkResult>),
+    BreadthFirst(LevelBuffer<WalkResult>),
+}
+
+impl Buffer {
+    /// Add result of directory `path` and return all results which can be returned now.
+    fn add(
+        &mut self,
+        depth: usize,
+        path: String,
+        result: Option<WalkResult>,
+        subdirs: Vec<String>,
+    ) -> Vec<WalkResult> {
+        match self {
+            Buffer::BottomUp(pending_dirs) => pending_dirs.add(path, result, subdirs.len()),
+            Buffer::DepthFirst(results) => {
+                if let Some(result) = result {
+                    results.insert(path, result);
+                }
+                Vec::new()
+            }
+            Buffer::BreadthFirst(level_buffer) => {
+                level_buffer.add(depth, path, result.into_iter().collect(), subdirs)
+            }
+        }
+    }
+
+    /// Return all results which can be returned after `dir_entry` has been returned by the
+    /// iterator of jwalk.
+    fn iterated(
+        &mut self,
+        root_path_len: usize,
+        dir_entry: &jwalk::DirEntry<((), Option<Result<fs::Metadata, Error>>)>,
+    ) -> Vec<WalkResult> {
+        if let Buffer::DepthFirst(results) = self {
+            // The iterator returns entries depth first, so the result of a directory
+            // can be returned as soon as its first entry is returned
+            if dir_entry.depth == 0 {
+                return Vec::new();
+            }
+            let parent_path = dir_entry.parent_path.to_str();
+            let parent_path = parent_path.and_then(|p| p.get(root_path_len..));
+            return results
+                .remove(parent_path.unwrap_or(""))
+                .into_iter()
+                .collect();
+        }
+        if dir_entry.read_children_error.is_none() {
+            return Vec::new();
+        }
+        // Directory will never be read
+        let path = dir_entry.path();
+        let path = path.to_str().and_then(|p| p.get(root_path_len..));
+        self.add(
+            dir_entry.depth,
+            path.unwrap_or("").to_string(),
+            None,
+            Vec::new(),
+        )
+    }
+
+    fn flush(&mut self) -> Vec<WalkResult> {
+        match self {
+            Buffer::BottomUp(pending_dirs) => pending_dirs.flush(),
+            Buffer::DepthFirst(results) => {
+                let mut results: Vec<(String, WalkResult)> = results.drain().collect();
+                results.sort_by(|a, b| a.0.cmp(&b.0));
+                results.into_iter().map(|(_, result)| result).collect()
+            }
+            Buffer::BreadthFirst(level_buffer) => level_buffer.flush(),
+        }
+    }
+}
+
 pub fn toc_thread(
     options: Options,
     filter: Option<Filter>,
-    tx: Sender<(String, Toc)>,
+    topdown: bool,
+    prune: Option<Arc<PruneFn>>,
+    budget: Arc<Budget>,
+    progress: Arc<ProgressTracker>,
+    oversized_dirs: Arc<Mutex<Vec<(String, usize)>>>,
+    tx: Sender<WalkResult>,
     stop: Arc<AtomicBool>,
+    pause: Arc<Pause>,
 ) {
+    // Errors are reported by `check_thread_priority` in `start`
+    let _ = set_thread_priority(options.idle_io_priority, options.nice);
+    let throttle = Throttle::new(&options);
     let root_path_len = get_root_path_len(&options.root_path);
-    let max_file_cnt = options.max_file_cnt;
-    let file_cnt = Arc::new(AtomicUsize::new(0));
-    let file_cnt_cloned = file_cnt.clone();
+    let max_dir_entries = options.max_dir_entries;
+    let oversized_action = options.oversized_action.clone();
+    let min_depth = options.min_depth;
+    let max_depth = options.max_depth;
+    let read_links = options.read_links;
+    let extended = options.return_type == ReturnType::Ext;
+    let sort_by = options.sort_by.clone();
+    let dirs_first = options.dirs_first;
+    let read_metadata = extended
+        || sort_by
+            .as_ref()
+            .map(|s| s.needs_metadata())
+            .unwrap_or(false);
+    let budget_cloned = budget.clone();
+    let progress_cloned = progress.clone();
+    let buffer = match (topdown, &options.order) {
+        (false, _) => Some(Buffer::BottomUp(PendingDirs::default())),
+        (true, TraversalOrder::Default) => None,
+        (true, TraversalOrder::DepthFirst) => Some(Buffer::DepthFirst(HashMap::new())),
+        (true, TraversalOrder::BreadthFirst) => Some(Buffer::BreadthFirst(LevelBuffer::new())),
+    };
+    let buffer = buffer.map(|b| Arc::new(Mutex::new(b)));
+    let buffer_cloned = buffer.clone();
     let stop_cloned = stop.clone();
     let tx_cloned = tx.clone();
-    for _ in WalkDirGeneric::new(&options.root_path)
+    for result in WalkDirGeneric::new(&options.root_path)
         .skip_hidden(options.skip_hidden)
-        .sort(options.sorted)
+        .sort(false)
         .max_depth(options.max_depth)
-        .process_read_dir(move |_, root_dir, _, children| {
-            if stop_cloned.load(Ordering::Relaxed) {
+        .parallelism(jwalk_parallelism(&options))
+        .read_metadata(read_metadata)
+        .read_metadata_ext(extended)
+        .process_read_dir(move |depth, root_dir, _, children| {
+            pause.wait();
+            throttle.wait(
+                match read_metadata {
+                    true => children.len(),
+                    false => 0,
+                },
+                &stop_cloned,
+            );
+            if stop_cloned.load(Ordering::Relaxed) || budget_cloned.exhausted() {
                 return;
             }
             let root_dir = root_dir.to_str();
@@ -56,80 +271,201 @@ pub fn toc_thread(
             }
             let root_dir = root_dir.unwrap();
             if root_dir.len() + 1 < root_path_len {
+                progress_cloned.add_children(false, max_depth, children);
                 return;
             }
+            let path = match root_dir.len() > root_path_len {
+                true => root_dir[root_path_len..].to_owned(),
+                false => "".to_owned(),
+            };
+            // Sort before truncating, so that the first entries of the sort order are kept
+            sort_children(children, &sort_by, dirs_first);
+            if let Some(entries_cnt) =
+                limit_dir_entries(children, max_dir_entries, &oversized_action)
+            {
+                oversized_dirs
+                    .lock()
+                    .unwrap()
+                    .push((path.clone(), entries_cnt));
+            }
             filter_children(children, &filter, root_path_len);
-            if children.is_empty() {
+            if children.is_empty() && buffer_cloned.is_none() {
+                progress_cloned.add_children(true, max_depth, children);
                 return;
             }
             let mut toc = Toc::new();
-            children.iter_mut().for_each(|dir_entry_result| {
+            let mut entries = HashMap::new();
+            // Entries of directories above min_depth are not returned
+            let returned = depth.unwrap_or(0) + 1 >= min_depth;
+            for dir_entry_result in children.iter() {
                 if let Ok(dir_entry) = dir_entry_result {
-                    update_toc(&dir_entry, &mut toc);
+                    if returned && !budget_cloned.take_entry(dir_entry) {
+                        break;
+                    }
+                    update_toc(dir_entry, read_links, &mut toc);
+                    if extended {
+                        let (_, entry) = create_entry(
+                            root_path_len,
+                            &ReturnType::Ext,
+                            read_links,
+                            false,
+                            None,
+                            dir_entry,
+                        );
+                        if let Stats::ScandirResult(ScandirResult::DirEntryExt(entry)) = entry.entry
+                        {
+                            entries
+                                .insert(dir_entry.file_name.to_string_lossy().to_string(), entry);
+                        }
+                    }
+                }
+            }
+            if let Some(ref prune) = prune {
+                if !toc.dirs.is_empty() {
+                    if !prune(&path, &mut toc.dirs) {
+                        stop_cloned.store(true, Ordering::Relaxed);
+                        toc.dirs.clear();
+                    }
+                    children.iter_mut().for_each(|dir_entry_result| {
+                        if let Ok(dir_entry) = dir_entry_result {
+                            if dir_entry.file_type.is_dir()
+                                && !toc
+                                    .dirs
+                                    .iter()
+                                    .any(|d| dir_entry.file_name.to_str() == Some(d))
+                            {
+                                dir_entry.read_children_path = None;
+                            }
+                        }
+                    });
+                }
+            }
+            progress_cloned.add_children(true, max_depth, children);
+            let result = match toc.is_empty() || !returned {
+                true => None,
+                false => Some(match extended {
+                    true => WalkResult::WalkEntryExt(WalkEntryExt {
+                        path: path.clone(),
+                        toc,
+                        entries,
+                    }),
+                    false => WalkResult::WalkEntry(WalkEntry {
+                        path: path.clone(),
+                        toc,
+                    }),
+                }),
+            };
+            match buffer_cloned {
+                Some(ref buffer) => {
+                    let subdirs = subdirs_to_read(&path, depth, max_depth, children);
+                    let results =
+                        buffer
+                            .lock()
+                            .unwrap()
+                            .add(depth.unwrap_or(0), path, result, subdirs);
+                    for result in results {
+                        let _ = tx_cloned.send(result);
+                    }
                 }
-            });
-            if !toc.is_empty() {
-                if root_dir.len() > root_path_len {
-                    let _ = tx_cloned.send((root_dir[root_path_len..].to_owned(), toc));
-                } else {
-                    let _ = tx_cloned.send(("".to_owned(), toc));
+                None => {
+                    if let Some(result) = result {
+                        let _ = tx_cloned.send(result);
+                    }
                 }
             }
-            let file_cnt_new = file_cnt_cloned.load(Ordering::Relaxed) + children.len();
-            file_cnt_cloned.store(file_cnt_new, Ordering::Relaxed);
         })
     {
-        if stop.load(Ordering::Relaxed) {
+        if stop.load(Ordering::Relaxed) || budget.exhausted() {
             break;
         }
-        if max_file_cnt > 0 && file_cnt.load(Ordering::Relaxed) > max_file_cnt {
-            break;
+        match result {
+            Ok(ref dir_entry) if dir_entry.read_children_error.is_some() => {
+                progress.add_failed_dir()
+            }
+            Err(_) => progress.add_error(),
+            _ => {}
+        }
+        if let (Some(ref buffer), Ok(dir_entry)) = (&buffer, &result) {
+            for result in buffer.lock().unwrap().iterated(root_path_len, dir_entry) {
+                let _ = tx.send(result);
+            }
+        }
+    }
+    if let Some(buffer) = buffer {
+        for result in buffer.lock().unwrap().flush() {
+            let _ = tx.send(result);
         }
     }
+    progress.finish();
 }
 
 #[derive(Debug)]
 pub struct Walk {
     // Options
     options: Options,
+    topdown: bool,
+    prune: Option<Prune>,
+    progress_callback: Option<ProgressCallback>,
+    progress_interval: Duration,
+    store: bool,
     // Results
-    entries: Vec<(String, Toc)>,
+    entries: Vec<WalkResult>,
     duration: Arc<Mutex<f64>>,
     has_errors: bool,
     // Internal
+    budget: Option<Arc<Budget>>,
+    progress: Option<Arc<ProgressTracker>>,
+    oversized_dirs: Arc<Mutex<Vec<(String, usize)>>>,
     thr: Option<thread::JoinHandle<()>>,
     stop: Arc<AtomicBool>,
-    rx: Option<Receiver<(String, Toc)>>,
+    pause: Arc<Pause>,
+    rx: Option<Receiver<WalkResult>>,
+    thread_pool: ThreadPoolCache,
 }
 
 impl Walk {
     pub fn new(root_path: &str) -> Result<Self, Error> {
         Ok(Walk {
-            options: Options {
-                root_path: check_and_expand_path(&root_path)?,
-                sorted: false,
-                skip_hidden: true,
-                max_depth: std::usize::MAX,
-                max_file_cnt: std::usize::MAX,
-                dir_include: None,
-                dir_exclude: None,
-                file_include: None,
-                file_exclude: None,
-                case_sensitive: false,
-                return_type: ReturnType::Base,
-            },
+            options: Options::new(check_and_expand_path(&root_path)?),
+            topdown: true,
+            prune: None,
+            progress_callback: None,
+            progress_interval: Duration::from_millis(100),
+            store: true,
             entries: Vec::new(),
             duration: Arc::new(Mutex::new(0.0)),
             has_errors: false,
+            budget: None,
+            progress: None,
+            oversized_dirs: Arc::new(Mutex::new(Vec::new())),
             thr: None,
             stop: Arc::new(AtomicBool::new(false)),
+            pause: Arc::new(Pause::new()),
             rx: None,
+            thread_pool: ThreadPoolCache::default(),
         })
     }
 
-    /// Return results in sorted order.
-    pub fn sorted(mut self, sorted: bool) -> Self {
-        self.options.sorted = sorted;
+    /// Return results in sorted order. Alias for `sort_by(Some(SortBy::Name))`.
+    pub fn sorted(self, sorted: bool) -> Self {
+        self.sort_by(sorted.then_some(SortBy::Name))
+    }
+
+    /// Sort the entries of each directory.
+    pub fn sort_by(mut self, sort_by: Option<SortBy>) -> Self {
+        self.options.sort_by = sort_by;
+        self
+    }
+
+    /// Return directories before other entries of a directory.
+    pub fn dirs_first(mut self, dirs_first: bool) -> Self {
+        self.options.dirs_first = dirs_first;
+        self
+    }
+
+    /// Set order in which the directories are returned. Only relevant for top-down walks.
+    pub fn order(mut self, order: TraversalOrder) -> Self {
+        self.options.order = order;
         self
     }
 
@@ -156,6 +492,13 @@ impl Walk {
         self
     }
 
+    /// Set the minimum depth of entries to return. Directories whose entries
+    /// have a smaller depth are not returned, but are still walked.
+    pub fn min_depth(mut self, depth: usize) -> Self {
+        self.options.min_depth = depth;
+        self
+    }
+
     /// Set maximum number of files to collect
     pub fn max_file_cnt(mut self, max_file_cnt: usize) -> Self {
         self.options.max_file_cnt = match max_file_cnt {
@@ -165,6 +508,112 @@ impl Walk {
         self
     }
 
+    /// Set maximum number of entries to collect
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.options.max_entries = match max_entries {
+            0 => std::usize::MAX,
+            _ => max_entries,
+        };
+        self
+    }
+
+    /// Set maximum number of directories to collect
+    pub fn max_dirs(mut self, max_dirs: usize) -> Self {
+        self.options.max_dirs = match max_dirs {
+            0 => std::usize::MAX,
+            _ => max_dirs,
+        };
+        self
+    }
+
+    /// Set maximum total size of entries to collect. The size is only known if
+    /// metadata is read, so this limit requires `ReturnType::Ext` or sorting by size.
+    pub fn max_total_bytes(mut self, max_total_bytes: u64) -> Self {
+        self.options.max_total_bytes = match max_total_bytes {
+            0 => std::u64::MAX,
+            _ => max_total_bytes,
+        };
+        self
+    }
+
+    /// Stop the walk after the given time.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.options.timeout = timeout;
+        self
+    }
+
+    /// Set maximum number of entries of a directory. Directories with more entries are
+    /// truncated or skipped, depending on `oversized_action`, and reported by `oversized_dirs`.
+    /// The directory is still read and its entries are still stat'ed completely.
+    pub fn max_dir_entries(mut self, max_dir_entries: usize) -> Self {
+        self.options.max_dir_entries = match max_dir_entries {
+            0 => std::usize::MAX,
+            _ => max_dir_entries,
+        };
+        self
+    }
+
+    /// Set handling of directories with more than `max_dir_entries` entries.
+    pub fn oversized_action(mut self, oversized_action: OversizedAction) -> Self {
+        self.options.oversized_action = oversized_action;
+        self
+    }
+
+    /// Set number of threads for reading directories. `0` uses the global rayon thread pool
+    /// and `1` reads all directories in the scanning thread.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.options.parallelism = match threads {
+            0 => Parallelism::Default,
+            1 => Parallelism::Serial,
+            _ => Parallelism::Threads(threads),
+        };
+        self
+    }
+
+    /// Set degree of parallelism, e.g. `Parallelism::Pool` for using a rayon thread pool
+    /// provided by the caller.
+    pub fn parallelism(mut self, parallelism: Parallelism) -> Self {
+        self.options.parallelism = parallelism;
+        self
+    }
+
+    /// Set maximum number of directories read per second. `0` disables the limit.
+    /// A worker waits after reading a directory, so the limit delays its next read.
+    pub fn max_dirs_per_sec(mut self, max_dirs_per_sec: usize) -> Self {
+        self.options.max_dirs_per_sec = match max_dirs_per_sec {
+            0 => std::usize::MAX,
+            _ => max_dirs_per_sec,
+        };
+        self
+    }
+
+    /// Set maximum number of entries whose metadata is read per second. `0` disables
+    /// the limit. Has no effect if no metadata is read. Like `max_dirs_per_sec` the
+    /// limit is applied after a directory was read and delays the next read.
+    pub fn max_stats_per_sec(mut self, max_stats_per_sec: usize) -> Self {
+        self.options.max_stats_per_sec = match max_stats_per_sec {
+            0 => std::usize::MAX,
+            _ => max_stats_per_sec,
+        };
+        self
+    }
+
+    /// Run the scanning threads with idle I/O priority. Only supported on Linux.
+    /// Has no effect on the threads of a pool set with `Parallelism::Pool`.
+    /// `start` fails if the priority cannot be set.
+    pub fn idle_io_priority(mut self, idle_io_priority: bool) -> Self {
+        self.options.idle_io_priority = idle_io_priority;
+        self
+    }
+
+    /// Set nice level of the scanning threads. `0` keeps the current level. Only supported
+    /// on Linux. Has no effect on the threads of a pool set with `Parallelism::Pool`.
+    /// `start` fails if the nice level cannot be set, e.g. a negative level without privileges.
+    pub fn nice(mut self, nice: i32) -> Self {
+        self.options.nice = nice;
+        self
+    }
+
     /// Set directory include filter
     pub fn dir_include(mut self, dir_include: Option<Vec<String>>) -> Self {
         self.options.dir_include = dir_include;
@@ -195,15 +644,67 @@ impl Walk {
         self
     }
 
-    /// Set extended file type counting
+    /// Set type of returned results. With `ReturnType::Ext` the metadata of all entries
+    /// is read and available with `results_ext`.
     pub fn return_type(mut self, return_type: ReturnType) -> Self {
         self.options.return_type = return_type;
         self
     }
 
+    /// Resolve symbolic links and list broken links separately in `Toc::broken_symlinks`.
+    pub fn read_links(mut self, read_links: bool) -> Self {
+        self.options.read_links = read_links;
+        self
+    }
+
+    /// Return directories before their subdirectories (default). If disabled, a directory is
+    /// returned after all of its subdirectories, like `os.walk` with `topdown=False`.
+    pub fn topdown(mut self, topdown: bool) -> Self {
+        self.topdown = topdown;
+        self
+    }
+
+    /// Set callback for pruning subdirectories. The callback is called for each directory
+    /// with its relative path and the names of its subdirectories. Subdirectories removed
+    /// from the list are neither returned nor descended into. If the callback returns
+    /// `false`, the walk is stopped. Like `os.walk`, pruning requires `topdown`, otherwise
+    /// `start` fails.
+    pub fn prune<F>(mut self, prune: F) -> Self
+    where
+        F: Fn(&str, &mut Vec<String>) -> bool + Send + Sync + 'static,
+    {
+        self.prune = Some(Prune(Arc::new(prune)));
+        self
+    }
+
+    /// Keep all results in the instance, so that they can be returned again with
+    /// `return_all`. Enabled by default. If disabled, results are only returned once,
+    /// which keeps the memory bounded when they are consumed while the walk is running.
+    pub fn store(mut self, store: bool) -> Self {
+        self.store = store;
+        self
+    }
+
+    /// Set callback for progress reports. The callback is called from the worker threads
+    /// at most once per `progress_interval` and once when the walk has finished.
+    pub fn progress_callback<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&Progress) + Send + Sync + 'static,
+    {
+        self.progress_callback = Some(ProgressCallback(Arc::new(callback)));
+        self
+    }
+
+    /// Set minimum interval between two progress reports. Default is 100ms.
+    pub fn progress_interval(mut self, progress_interval: Duration) -> Self {
+        self.progress_interval = progress_interval;
+        self
+    }
+
     pub fn clear(&mut self) {
         self.entries.clear();
         self.has_errors = false;
+        self.oversized_dirs.lock().unwrap().clear();
         *self.duration.lock().unwrap() = 0.0;
     }
 
@@ -211,18 +712,49 @@ impl Walk {
         if self.busy() {
             return Err(Error::new(ErrorKind::Other, "Busy"));
         }
+        if self.prune.is_some() && !self.topdown {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Pruning requires topdown",
+            ));
+        }
         self.clear();
-        let options = self.options.clone();
+        let mut options = self.options.clone();
+        options.parallelism = self.thread_pool.parallelism(&options);
         let filter = create_filter(&options)?;
+        check_thread_priority(options.idle_io_priority, options.nice)?;
+        let topdown = self.topdown;
+        let prune = self.prune.as_ref().map(|p| p.0.clone());
         let (tx, rx) = unbounded();
         self.rx = Some(rx);
         self.stop.store(false, Ordering::Relaxed);
+        let timer = RunTimer::new(self.pause.clone());
+        let budget = Arc::new(Budget::new(&options, timer.clone()));
+        self.budget = Some(budget.clone());
+        let progress = Arc::new(ProgressTracker::new(
+            self.progress_callback.clone(),
+            self.progress_interval,
+            timer.clone(),
+        ));
+        self.progress = Some(progress.clone());
+        let oversized_dirs = self.oversized_dirs.clone();
         let stop = self.stop.clone();
+        let pause = self.pause.clone();
         let duration = self.duration.clone();
         self.thr = Some(thread::spawn(move || {
-            let start_time = Instant::now();
-            toc_thread(options, filter, tx, stop);
-            *duration.lock().unwrap() = start_time.elapsed().as_secs_f64();
+            toc_thread(
+                options,
+                filter,
+                topdown,
+                prune,
+                budget,
+                progress,
+                oversized_dirs,
+                tx,
+                stop,
+                pause,
+            );
+            *duration.lock().unwrap() = timer.elapsed().as_secs_f64();
         }));
         Ok(())
     }
@@ -240,6 +772,7 @@ impl Walk {
     pub fn stop(&mut self) -> bool {
         if let Some(thr) = self.thr.take() {
             self.stop.store(true, Ordering::Relaxed);
+            self.pause.resume();
             if let Err(_e) = thr.join() {
                 return false;
             }
@@ -248,14 +781,35 @@ impl Walk {
         false
     }
 
-    fn receive_all(&mut self) -> Vec<(String, Toc)> {
+    /// Pause the walk. Each worker still reads one more directory and then waits until
+    /// `resume` is called. The paused time does not count for the timeout and the
+    /// duration. `stop` also resumes a paused walk. With `Parallelism::Default` the waiting
+    /// workers block threads of the global rayon thread pool and so also other scans
+    /// using it. Pausing a walk while others are running needs its own thread pool.
+    pub fn pause(&self) {
+        self.pause.pause();
+    }
+
+    /// Resume a paused walk.
+    pub fn resume(&self) {
+        self.pause.resume();
+    }
+
+    /// Returns `true` if the walk is paused.
+    pub fn paused(&self) -> bool {
+        self.pause.is_paused()
+    }
+
+    fn receive_all(&mut self) -> Vec<WalkResult> {
         let mut entries = Vec::new();
         if let Some(ref rx) = self.rx {
             loop {
                 match rx.try_recv() {
                     Ok(entry) => {
-                        if !entry.1.errors.is_empty() {
-                            self.has_errors = true;
+                        if let Some((_, toc)) = split_result(&entry) {
+                            if !toc.errors.is_empty() {
+                                self.has_errors = true;
+                            }
                         }
                         entries.push(entry);
                     }
@@ -301,17 +855,58 @@ impl Walk {
 
     pub fn results(&mut self, return_all: bool) -> Vec<(String, Toc)> {
         let entries = self.receive_all();
-        self.entries.extend_from_slice(&entries);
-        if return_all {
-            return self.entries.clone();
+        if self.store {
+            self.entries.extend_from_slice(&entries);
+        }
+        let entries = match return_all && self.store {
+            true => &self.entries,
+            false => &entries,
+        };
+        entries.iter().filter_map(split_result).collect()
+    }
+
+    /// Return results including metadata of the entries. Only available for `ReturnType::Ext`.
+    pub fn results_ext(&mut self, return_all: bool) -> Vec<WalkEntryExt> {
+        let entries = self.receive_all();
+        if self.store {
+            self.entries.extend_from_slice(&entries);
         }
+        let entries = match return_all && self.store {
+            true => &self.entries,
+            false => &entries,
+        };
         entries
+            .iter()
+            .filter_map(|result| match result {
+                WalkResult::WalkEntryExt(e) => Some(e.clone()),
+                _ => None,
+            })
+            .collect()
     }
 
     pub fn has_errors(&mut self) -> bool {
         !self.has_errors
     }
 
+    /// Budget which stopped the walk. `None` if the walk was complete or stopped by the user.
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.budget.as_ref().and_then(|budget| budget.stop_reason())
+    }
+
+    /// Progress of the walk. Can be called while the walk is running.
+    pub fn progress(&self) -> Progress {
+        self.progress
+            .as_ref()
+            .map(|progress| progress.progress())
+            .unwrap_or_default()
+    }
+
+    /// Relative paths and numbers of entries of the directories with more than
+    /// `max_dir_entries` entries.
+    pub fn oversized_dirs(&self) -> Vec<(String, usize)> {
+        self.oversized_dirs.lock().unwrap().clone()
+    }
+
     pub fn duration(&mut self) -> f64 {
         *self.duration.lock().unwrap()
     }
@@ -0,0 +1,125 @@
+#![cfg(feature = "arrow")]
+
+use std::fs::File;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float64Type, UInt32Type, UInt64Type};
+use arrow_array::{Array, RecordBatch};
+use arrow_ipc::reader::StreamReader;
+use arrow_schema::DataType;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+use scandir::arrow::RecordBatchWriter;
+use scandir::{
+    export_scandir, export_walk, ExportColumn, ExportSink, ReturnType, Scandir, ScandirResult, Walk,
+};
+
+mod common;
+use common::{dir_entry, src_dir};
+
+fn row_count(batches: &[RecordBatch]) -> usize {
+    batches.iter().map(|batch| batch.num_rows()).sum()
+}
+
+#[test]
+fn record_batches() {
+    let mut writer = RecordBatchWriter::memory(ExportColumn::ALL.to_vec()).batch_size(2);
+    for i in 0..3 {
+        writer
+            .write_result(&ScandirResult::DirEntry(dir_entry(&format!("f{}", i), i)))
+            .unwrap();
+    }
+    writer.write_error("denied", "Permission denied").unwrap();
+    let schema = writer.schema();
+    assert_eq!(
+        schema.field_with_name("st_size").unwrap().data_type(),
+        &DataType::UInt64
+    );
+    assert_eq!(
+        schema.field_with_name("st_mode").unwrap().data_type(),
+        &DataType::UInt32
+    );
+    assert_eq!(writer.rows(), 4);
+    let batches = writer.finish().unwrap();
+    assert_eq!(batches.len(), 2);
+    assert_eq!(batches[0].num_rows(), 2);
+    assert_eq!(batches[1].num_rows(), 2);
+    let batch = &batches[1];
+    let paths = batch.column_by_name("path").unwrap().as_string::<i32>();
+    assert_eq!(paths.value(0), "f2");
+    assert_eq!(paths.value(1), "denied");
+    let sizes = batch
+        .column_by_name("st_size")
+        .unwrap()
+        .as_primitive::<UInt64Type>();
+    assert_eq!(sizes.value(0), 2);
+    assert!(sizes.is_null(1));
+    let mtimes = batch
+        .column_by_name("st_mtime")
+        .unwrap()
+        .as_primitive::<Float64Type>();
+    assert_eq!(mtimes.value(0), 2.5);
+    // Base entries have no extended metadata
+    assert!(batch.column_by_name("st_mode").unwrap().is_null(0));
+    let errors = batch.column_by_name("error").unwrap().as_string::<i32>();
+    assert!(errors.is_null(0));
+    assert_eq!(errors.value(1), "Permission denied");
+}
+
+#[test]
+fn scan_to_ipc() {
+    let (entries, _) = Scandir::new(&src_dir()).unwrap().collect().unwrap();
+    let mut instance = Scandir::new(&src_dir())
+        .unwrap()
+        .return_type(ReturnType::Ext)
+        .store(false);
+    let mut writer = RecordBatchWriter::ipc(Vec::new(), ExportColumn::ALL.to_vec())
+        .unwrap()
+        .batch_size(4);
+    export_scandir(&mut instance, &mut writer).unwrap();
+    assert_eq!(writer.rows() as usize, entries.len());
+    let data = writer.finish().unwrap().into_inner().unwrap();
+    let batches = StreamReader::try_new(&data[..], None)
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(row_count(&batches), entries.len());
+    assert!(batches.iter().all(|batch| batch.num_rows() <= 4));
+    let found = batches.iter().any(|batch| {
+        let paths = batch.column_by_name("path").unwrap().as_string::<i32>();
+        let modes = batch
+            .column_by_name("st_mode")
+            .unwrap()
+            .as_primitive::<UInt32Type>();
+        (0..batch.num_rows()).any(|i| paths.value(i) == "lib.rs" && !modes.is_null(i))
+    });
+    assert!(found);
+}
+
+#[test]
+fn walk_to_parquet() {
+    let toc = Walk::new(&src_dir()).unwrap().collect().unwrap();
+    let file_path = std::env::temp_dir().join(format!("scandir_{}.parquet", std::process::id()));
+    let mut instance = Walk::new(&src_dir()).unwrap().store(false);
+    let mut writer = RecordBatchWriter::parquet(
+        File::create(&file_path).unwrap(),
+        ExportColumn::BASE.to_vec(),
+    )
+    .unwrap()
+    .batch_size(3);
+    export_walk(&mut instance, &mut writer).unwrap();
+    let rows = writer.rows() as usize;
+    assert_eq!(
+        rows,
+        toc.dirs.len() + toc.files.len() + toc.symlinks.len() + toc.other.len()
+    );
+    writer.finish().unwrap();
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&file_path).unwrap())
+        .unwrap()
+        .build()
+        .unwrap();
+    let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+    std::fs::remove_file(&file_path).unwrap();
+    assert_eq!(row_count(&batches), rows);
+    assert_eq!(batches[0].schema().fields().len(), ExportColumn::BASE.len());
+}
@@ -0,0 +1,221 @@
+use std::fs::{self, Metadata};
+use std::io::{Error, ErrorKind};
+use std::path::MAIN_SEPARATOR_STR;
+use std::sync::Arc;
+
+use jwalk::WalkDirGeneric;
+
+use scandir::checkpoint::Resume;
+use scandir::{Checkpoint, Count, ReturnType, Scandir, Statistics, Totals};
+
+mod common;
+use common::TempDir;
+
+fn path(path: &str) -> String {
+    path.replace('/', MAIN_SEPARATOR_STR)
+}
+
+#[test]
+fn save_and_load() {
+    let tmp = TempDir::new("checkpoint_save");
+    let file_path = tmp.0.join("scan.checkpoint");
+    let mut statistics = Statistics::new();
+    statistics.files = 12;
+    statistics.size = 1234;
+    statistics.unique_size = 1000;
+    statistics.errors_cnt = 1;
+    statistics.errors.push("dir\\1: line\nbreak".to_string());
+    statistics.owners.insert(
+        "first last".to_string(),
+        Totals {
+            count: 2,
+            size: 30,
+            usage: 8192,
+        },
+    );
+    statistics.sizes.insert(
+        5,
+        Totals {
+            count: 1,
+            size: 20,
+            usage: 4096,
+        },
+    );
+    statistics
+        .oversized_dirs
+        .push(("big dir".to_string(), 5000));
+    let checkpoint = Checkpoint {
+        root_path: "C:\\root\npath".to_string(),
+        completed_dirs: 3,
+        pending_dirs: vec![
+            "a".to_string(),
+            "back\\slash".to_string(),
+            "new\nline".to_string(),
+            "trailing\\".to_string(),
+        ],
+        statistics: Some(statistics),
+    };
+    checkpoint.save(&file_path).unwrap();
+    // Special characters are escaped, so that each value is on one line
+    let content = fs::read_to_string(&file_path).unwrap();
+    assert!(content.contains("pending back\\\\slash\n"));
+    assert!(content.contains("pending new\\nline\n"));
+    assert!(!tmp.0.join("scan.checkpoint.tmp").exists());
+
+    let loaded = Checkpoint::load(&file_path).unwrap();
+    assert_eq!(loaded.root_path, checkpoint.root_path);
+    assert_eq!(loaded.completed_dirs, 3);
+    assert_eq!(loaded.pending_dirs, checkpoint.pending_dirs);
+    let loaded = loaded.statistics.unwrap();
+    assert_eq!(
+        (
+            loaded.files,
+            loaded.size,
+            loaded.unique_size,
+            loaded.errors_cnt
+        ),
+        (12, 1234, 1000, 1)
+    );
+    assert_eq!(loaded.errors, vec!["dir\\1: line\nbreak".to_string()]);
+    let owner = &loaded.owners["first last"];
+    assert_eq!((owner.count, owner.size, owner.usage), (2, 30, 8192));
+    assert_eq!(loaded.sizes[&5].size, 20);
+    assert_eq!(loaded.oversized_dirs, vec![("big dir".to_string(), 5000)]);
+
+    // A checkpoint of a scan without statistics
+    let checkpoint = Checkpoint {
+        root_path: "/root".to_string(),
+        ..Default::default()
+    };
+    checkpoint.save(&file_path).unwrap();
+    let loaded = Checkpoint::load(&file_path).unwrap();
+    assert!(loaded.pending_dirs.is_empty());
+    assert!(loaded.statistics.is_none());
+}
+
+#[test]
+fn carriage_return() {
+    let tmp = TempDir::new("checkpoint_cr");
+    let file_path = tmp.0.join("scan.checkpoint");
+    let checkpoint = Checkpoint {
+        root_path: "root\r".to_string(),
+        completed_dirs: 1,
+        pending_dirs: vec!["carriage\r".to_string(), "crlf\r\nline".to_string()],
+        statistics: None,
+    };
+    checkpoint.save(&file_path).unwrap();
+    let content = fs::read_to_string(&file_path).unwrap();
+    assert!(content.contains("pending carriage\\r\n"));
+    assert!(!content.contains('\r'));
+
+    let loaded = Checkpoint::load(&file_path).unwrap();
+    assert_eq!(loaded.root_path, checkpoint.root_path);
+    assert_eq!(loaded.pending_dirs, checkpoint.pending_dirs);
+}
+
+#[test]
+fn load_invalid() {
+    let tmp = TempDir::new("checkpoint_invalid");
+    let file_path = tmp.0.join("scan.checkpoint");
+    fs::write(&file_path, "no checkpoint\n").unwrap();
+    let err = Checkpoint::load(&file_path).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    fs::write(&file_path, "scandir-checkpoint 1\ncompleted x\n").unwrap();
+    let err = Checkpoint::load(&file_path).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    // Statistics lines are only valid after the statistics header
+    fs::write(&file_path, "scandir-checkpoint 1\nfiles 3\n").unwrap();
+    let err = Checkpoint::load(&file_path).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    let err = Checkpoint::load(tmp.0.join("missing")).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+}
+
+#[test]
+fn resume_retain_pending() {
+    let tmp = TempDir::new("checkpoint_resume");
+    let root = tmp.0.join("root");
+    for dir in ["a/x", "a/y", "b", "c/z"] {
+        fs::create_dir_all(root.join(dir)).unwrap();
+    }
+    fs::write(root.join("file.txt"), b"0123456789").unwrap();
+    fs::write(root.join("a/file.txt"), b"0123456789").unwrap();
+    let checkpoint = Checkpoint {
+        root_path: root.to_string_lossy().to_string(),
+        completed_dirs: 4,
+        pending_dirs: vec![path("a/x"), "c".to_string()],
+        statistics: None,
+    };
+    let resume = Arc::new(Resume::new(&checkpoint));
+    // Ancestors of pending directories have been completed
+    assert!(resume.is_completed(""));
+    assert!(resume.is_completed("a"));
+    assert!(!resume.is_completed(&path("a/x")));
+    assert!(!resume.is_completed("b"));
+    assert!(!resume.is_completed("c"));
+
+    let root_path_len = root.to_string_lossy().len() + 1;
+    let resume_cloned = resume.clone();
+    let mut paths: Vec<String> =
+        WalkDirGeneric::<((), Option<Result<Metadata, Error>>)>::new(&root)
+            .process_read_dir(move |_, root_dir, _, children| {
+                let root_dir = root_dir.to_string_lossy();
+                if root_dir.len() + 1 < root_path_len {
+                    // Children only contain the root itself
+                    return;
+                }
+                let dir_path = root_dir.get(root_path_len..).unwrap_or("");
+                if resume_cloned.is_completed(dir_path) {
+                    resume_cloned.retain_pending(dir_path, children);
+                }
+            })
+            .into_iter()
+            .flatten()
+            .filter(|dir_entry| dir_entry.depth > 0)
+            .map(|dir_entry| {
+                let path = dir_entry.path().to_string_lossy().to_string();
+                path[root_path_len..].to_string()
+            })
+            .collect();
+    paths.sort();
+    // Files of completed directories and completed subdirectories are dropped
+    assert_eq!(paths, vec![path("a"), path("a/x"), path("c"), path("c/z")]);
+
+    // A checkpoint without pending directories is of a complete scan
+    let resume = Resume::new(&Checkpoint::default());
+    assert!(resume.is_completed(""));
+    let mut children = Vec::new();
+    resume.retain_pending("", &mut children);
+    assert!(children.is_empty());
+}
+
+#[test]
+fn resume_from_options() {
+    let tmp = TempDir::new("checkpoint_options");
+    let root = tmp.0.join("root");
+    fs::create_dir_all(root.join("a")).unwrap();
+    let options = Scandir::new(&root.to_string_lossy())
+        .unwrap()
+        .max_depth(1)
+        .return_type(ReturnType::Ext)
+        .options();
+    let checkpoint = Checkpoint {
+        root_path: options.root_path.to_string_lossy().to_string(),
+        completed_dirs: 1,
+        pending_dirs: vec!["a".to_string()],
+        statistics: Some(Statistics::new()),
+    };
+    // The options of the interrupted scan are kept
+    let scandir = Scandir::resume_from(checkpoint.clone(), options.clone()).unwrap();
+    assert_eq!(scandir.options().max_depth, 1);
+    assert!(matches!(scandir.options().return_type, ReturnType::Ext));
+    let count = Count::resume_from(checkpoint.clone(), options).unwrap();
+    assert_eq!(count.options().max_depth, 1);
+
+    // The checkpoint must be of the same directory
+    let options = Scandir::new(&tmp.0.to_string_lossy()).unwrap().options();
+    let err = Scandir::resume_from(checkpoint.clone(), options.clone()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    let err = Count::resume_from(checkpoint, options).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
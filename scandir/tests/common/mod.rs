@@ -0,0 +1,46 @@
+// Helpers shared by the integration tests. Not every test uses all of them.
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::PathBuf;
+
+use scandir::DirEntry;
+
+pub fn src_dir() -> String {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("src")
+        .to_string_lossy()
+        .to_string()
+}
+
+pub fn dir_entry(path: &str, st_size: u64) -> DirEntry {
+    DirEntry {
+        path: path.to_string(),
+        is_symlink: false,
+        is_dir: false,
+        is_file: true,
+        st_ctime: 1.0,
+        st_mtime: 2.5,
+        st_atime: 3.0,
+        st_size,
+        link_target: None,
+    }
+}
+
+/// Temporary directory which is removed when dropped
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("scandir_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+
+use rmpv::Value as MsgValue;
+use serde_json::{json, Value};
+
+use scandir::{
+    export_scandir, export_walk, DirEntryExt, ExportColumn, ExportFormat, ExportSink, ExportWriter,
+    ReturnType, Scandir, ScandirResult, Toc, Walk,
+};
+
+mod common;
+use common::{dir_entry, src_dir};
+
+fn read_msgpack(mut data: &[u8]) -> Vec<MsgValue> {
+    let mut values = Vec::new();
+    while !data.is_empty() {
+        values.push(rmpv::decode::read_value(&mut data).unwrap());
+    }
+    values
+}
+
+fn msgpack_get<'a>(value: &'a MsgValue, key: &str) -> &'a MsgValue {
+    value
+        .as_map()
+        .unwrap()
+        .iter()
+        .find(|(k, _)| k.as_str() == Some(key))
+        .map(|(_, v)| v)
+        .unwrap()
+}
+
+#[test]
+fn ndjson() {
+    let mut writer = ExportWriter::new(Vec::new(), ExportFormat::Ndjson);
+    writer
+        .write_result(&ScandirResult::DirEntry(dir_entry("dir/a \"b\"\n.txt", 10)))
+        .unwrap();
+    writer.write_error("denied", "Permission denied").unwrap();
+    assert_eq!(writer.rows(), 2);
+    let output = String::from_utf8(writer.finish().unwrap()).unwrap();
+    let lines: Vec<Value> = output
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(
+        lines[0],
+        json!({
+            "path": "dir/a \"b\"\n.txt",
+            "is_symlink": false,
+            "is_dir": false,
+            "is_file": true,
+            "st_ctime": 1.0,
+            "st_mtime": 2.5,
+            "st_atime": 3.0,
+            "st_size": 10,
+            "error": null,
+        })
+    );
+    assert_eq!(lines[1]["path"], json!("denied"));
+    assert_eq!(lines[1]["error"], json!("Permission denied"));
+    assert_eq!(lines[1]["st_size"], Value::Null);
+}
+
+#[test]
+fn csv_columns() {
+    let mut writer = ExportWriter::new(Vec::new(), ExportFormat::Csv).columns(vec![
+        ExportColumn::Path,
+        ExportColumn::StSize,
+        ExportColumn::StIno,
+    ]);
+    writer
+        .write_result(&ScandirResult::DirEntry(dir_entry("a,\"b\".txt", 10)))
+        .unwrap();
+    writer
+        .write_result(&ScandirResult::DirEntry(dir_entry("c.txt", 0)))
+        .unwrap();
+    let output = String::from_utf8(writer.finish().unwrap()).unwrap();
+    assert_eq!(
+        output,
+        "path,st_size,st_ino\n\"a,\"\"b\"\".txt\",10,\nc.txt,0,\n"
+    );
+    // The header is written even without entries
+    let writer = ExportWriter::new(Vec::new(), ExportFormat::Csv)
+        .columns(vec![ExportColumn::Path, ExportColumn::Owner]);
+    assert_eq!(writer.finish().unwrap(), b"path,owner\n");
+    assert_eq!(
+        ExportColumn::from_name("st_blocks"),
+        Some(ExportColumn::StBlocks)
+    );
+    assert_eq!(ExportColumn::from_name("unknown"), None);
+}
+
+#[test]
+fn message_pack() {
+    let entry = DirEntryExt {
+        path: "x".repeat(300),
+        is_symlink: false,
+        is_dir: false,
+        is_file: true,
+        st_ctime: 1.0,
+        st_mtime: 2.0,
+        st_atime: 3.0,
+        st_mode: 0o100644,
+        st_ino: u64::MAX,
+        st_dev: 70000,
+        st_nlink: 1,
+        st_size: 200,
+        st_blksize: 4096,
+        st_blocks: 8,
+        st_uid: 1000,
+        st_gid: 100,
+        st_rdev: 0,
+        owner: Some("a".repeat(40)),
+        group: None,
+        link_target: None,
+    };
+    let mut writer = ExportWriter::new(Vec::new(), ExportFormat::MessagePack)
+        .columns(ExportColumn::ALL.to_vec());
+    writer
+        .write_result(&ScandirResult::DirEntryExt(entry.clone()))
+        .unwrap();
+    writer
+        .write_result(&ScandirResult::DirEntry(dir_entry("b", 1)))
+        .unwrap();
+    let values = read_msgpack(&writer.finish().unwrap());
+    assert_eq!(values.len(), 2);
+    assert_eq!(values[0].as_map().unwrap().len(), ExportColumn::ALL.len());
+    assert_eq!(
+        msgpack_get(&values[0], "path").as_str(),
+        Some(&entry.path[..])
+    );
+    assert_eq!(msgpack_get(&values[0], "st_ino").as_u64(), Some(u64::MAX));
+    assert_eq!(msgpack_get(&values[0], "st_dev").as_u64(), Some(70000));
+    assert_eq!(msgpack_get(&values[0], "st_mode").as_u64(), Some(0o100644));
+    assert_eq!(msgpack_get(&values[0], "st_size").as_u64(), Some(200));
+    assert_eq!(msgpack_get(&values[0], "st_mtime").as_f64(), Some(2.0));
+    assert_eq!(msgpack_get(&values[0], "is_file").as_bool(), Some(true));
+    assert_eq!(
+        msgpack_get(&values[0], "owner").as_str(),
+        Some(&"a".repeat(40)[..])
+    );
+    assert!(msgpack_get(&values[0], "group").is_nil());
+    assert!(msgpack_get(&values[1], "st_ino").is_nil());
+}
+
+#[test]
+fn toc() {
+    let mut toc = Toc::new();
+    toc.dirs.push("sub".to_string());
+    toc.files.push("file.txt".to_string());
+    toc.symlinks.push("link".to_string());
+    let mut writer = ExportWriter::new(Vec::new(), ExportFormat::Ndjson);
+    writer.write_toc("dir", &toc, None).unwrap();
+    let output = String::from_utf8(writer.finish().unwrap()).unwrap();
+    let lines: Vec<Value> = output
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(
+        lines[0]["path"],
+        json!(format!("dir{}sub", std::path::MAIN_SEPARATOR))
+    );
+    assert_eq!(lines[0]["is_dir"], json!(true));
+    assert_eq!(lines[1]["is_file"], json!(true));
+    assert_eq!(lines[1]["st_size"], Value::Null);
+    assert_eq!(lines[2]["is_symlink"], json!(true));
+    let mut instance = Scandir::new(&src_dir())
+        .unwrap()
+        .return_type(ReturnType::Ext);
+    let (results, _) = instance.collect().unwrap();
+    let mut entries = HashMap::new();
+    for result in results {
+        if let ScandirResult::DirEntryExt(e) = result {
+            if e.path == "lib.rs" {
+                entries.insert("file.txt".to_string(), e);
+            }
+        }
+    }
+    let mut writer = ExportWriter::new(Vec::new(), ExportFormat::Ndjson);
+    writer.write_toc("", &toc, Some(&entries)).unwrap();
+    let output = String::from_utf8(writer.finish().unwrap()).unwrap();
+    let line: Value = serde_json::from_str(output.lines().nth(1).unwrap()).unwrap();
+    assert_eq!(line["path"], json!("lib.rs"));
+    assert!(line["st_size"].as_u64().unwrap() > 0);
+}
+
+#[test]
+fn export_scan() {
+    let (entries, errors) = Scandir::new(&src_dir()).unwrap().collect().unwrap();
+    let mut instance = Scandir::new(&src_dir()).unwrap().store(false);
+    let mut writer = ExportWriter::new(Vec::new(), ExportFormat::Ndjson);
+    export_scandir(&mut instance, &mut writer).unwrap();
+    assert_eq!(writer.rows() as usize, entries.len() + errors.len());
+    let output = String::from_utf8(writer.finish().unwrap()).unwrap();
+    assert_eq!(output.lines().count(), entries.len());
+    assert!(output.contains("\"path\":\"lib.rs\""));
+    // Nothing is kept in the instance
+    assert_eq!(instance.results(true).0.len(), 0);
+    assert_eq!(instance.results_cnt(false), 0);
+}
+
+#[test]
+fn export_walk_ext() {
+    let toc = Walk::new(&src_dir()).unwrap().collect().unwrap();
+    for return_type in [ReturnType::Base, ReturnType::Ext] {
+        let mut instance = Walk::new(&src_dir())
+            .unwrap()
+            .return_type(return_type.clone())
+            .store(false);
+        let mut writer = ExportWriter::new(Vec::new(), ExportFormat::Csv).columns(vec![
+            ExportColumn::Path,
+            ExportColumn::IsFile,
+            ExportColumn::StSize,
+        ]);
+        export_walk(&mut instance, &mut writer).unwrap();
+        assert_eq!(
+            writer.rows() as usize,
+            toc.dirs.len() + toc.files.len() + toc.symlinks.len() + toc.other.len()
+        );
+        let output = String::from_utf8(writer.finish().unwrap()).unwrap();
+        let line = output
+            .lines()
+            .find(|line| line.starts_with("lib.rs,"))
+            .unwrap();
+        match return_type {
+            ReturnType::Ext => assert!(line.len() > "lib.rs,true,".len()),
+            _ => assert_eq!(line, "lib.rs,true,"),
+        }
+        assert_eq!(instance.results_cnt(false), 0);
+    }
+}
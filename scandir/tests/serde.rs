@@ -0,0 +1,183 @@
+#![cfg(feature = "serde")]
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use scandir::{
+    Count, DirEntry, DirEntryExt, LinkMetadata, LinkTarget, Options, Parallelism, ReturnType,
+    Scandir, ScandirResult, Statistics, Toc, Walk, WalkEntryExt, WalkResult,
+};
+
+mod common;
+use common::src_dir;
+
+/// Serialize `value`, deserialize it again and check that nothing was lost.
+fn round_trip<T: Serialize + DeserializeOwned>(value: &T) -> Value {
+    let serialized = serde_json::to_value(value).unwrap();
+    let deserialized: T = serde_json::from_value(serialized.clone()).unwrap();
+    assert_eq!(serde_json::to_value(&deserialized).unwrap(), serialized);
+    serialized
+}
+
+fn dir_entry_ext() -> DirEntryExt {
+    DirEntryExt {
+        path: "dir/file.txt".to_string(),
+        is_symlink: true,
+        is_dir: false,
+        is_file: false,
+        st_ctime: 1.5,
+        st_mtime: 2.5,
+        st_atime: 3.5,
+        st_mode: 0o120777,
+        st_ino: 42,
+        st_dev: 2049,
+        st_nlink: 1,
+        st_size: 8,
+        st_blksize: 4096,
+        st_blocks: 0,
+        st_uid: 1000,
+        st_gid: 100,
+        st_rdev: 0,
+        owner: Some("user".to_string()),
+        group: None,
+        link_target: Some(LinkTarget {
+            path: "../target.txt".to_string(),
+            is_dangling: false,
+            metadata: Some(LinkMetadata {
+                is_dir: false,
+                is_file: true,
+                st_ctime: 1.0,
+                st_mtime: 2.0,
+                st_atime: 3.0,
+                st_size: 100,
+            }),
+        }),
+    }
+}
+
+#[test]
+fn dir_entry_field_names() {
+    let entry = DirEntry {
+        path: "file.txt".to_string(),
+        is_symlink: false,
+        is_dir: false,
+        is_file: true,
+        st_ctime: 1.0,
+        st_mtime: 2.0,
+        st_atime: 3.0,
+        st_size: 4,
+        link_target: None,
+    };
+    assert_eq!(
+        round_trip(&entry),
+        json!({
+            "path": "file.txt",
+            "is_symlink": false,
+            "is_dir": false,
+            "is_file": true,
+            "st_ctime": 1.0,
+            "st_mtime": 2.0,
+            "st_atime": 3.0,
+            "st_size": 4,
+            "link_target": null,
+        })
+    );
+}
+
+#[test]
+fn dir_entry_ext_round_trip() {
+    let value = round_trip(&dir_entry_ext());
+    assert_eq!(value["st_ino"], json!(42));
+    assert_eq!(value["link_target"]["metadata"]["st_size"], json!(100));
+    let value = round_trip(&ScandirResult::DirEntryExt(dir_entry_ext()));
+    assert_eq!(value["DirEntryExt"]["owner"], json!("user"));
+    let value = round_trip(&ScandirResult::Error((
+        "denied".to_string(),
+        "Permission denied".to_string(),
+    )));
+    assert_eq!(value, json!({"Error": ["denied", "Permission denied"]}));
+}
+
+#[test]
+fn scandir_results_round_trip() {
+    for return_type in [ReturnType::Fast, ReturnType::Base, ReturnType::Ext] {
+        let mut instance = Scandir::new(&src_dir()).unwrap().return_type(return_type);
+        let (entries, errors) = instance.collect().unwrap();
+        assert!(!entries.is_empty());
+        assert!(errors.is_empty());
+        let value = round_trip(&entries);
+        assert_eq!(value.as_array().unwrap().len(), entries.len());
+    }
+}
+
+#[test]
+fn toc_round_trip() {
+    let toc = Walk::new(&src_dir()).unwrap().collect().unwrap();
+    assert!(toc.files.contains(&"lib.rs".to_string()));
+    let value = round_trip(&toc);
+    for key in [
+        "dirs",
+        "files",
+        "symlinks",
+        "broken_symlinks",
+        "other",
+        "errors",
+    ] {
+        assert!(value[key].is_array(), "{}", key);
+    }
+    let mut entries = HashMap::new();
+    entries.insert("file.txt".to_string(), dir_entry_ext());
+    let mut toc = Toc::new();
+    toc.files.push("file.txt".to_string());
+    let value = round_trip(&WalkResult::WalkEntryExt(WalkEntryExt {
+        path: "dir".to_string(),
+        toc,
+        entries,
+    }));
+    assert_eq!(value["WalkEntryExt"]["toc"]["files"], json!(["file.txt"]));
+}
+
+#[test]
+fn statistics_round_trip() {
+    let statistics = Count::new(&src_dir())
+        .unwrap()
+        .histograms(true)
+        .collect()
+        .unwrap();
+    assert!(statistics.files > 0);
+    let value = round_trip(&statistics);
+    assert_eq!(value["files"], json!(statistics.files));
+    assert_eq!(value["stop_reason"], Value::Null);
+    let deserialized: Statistics = serde_json::from_value(value).unwrap();
+    assert_eq!(deserialized.sizes.len(), statistics.sizes.len());
+    assert_eq!(deserialized.uids.len(), statistics.uids.len());
+}
+
+#[test]
+fn options_round_trip() {
+    let options = Scandir::new(&src_dir())
+        .unwrap()
+        .max_depth(3)
+        .threads(4)
+        .timeout(Some(Duration::from_millis(1500)))
+        .file_include(Some(vec!["*.rs".to_string()]))
+        .options();
+    let value = round_trip(&options);
+    assert_eq!(value["max_depth"], json!(3));
+    assert_eq!(value["parallelism"], json!({"Threads": 4}));
+    assert_eq!(value["return_type"], json!("Base"));
+    let deserialized: Options = serde_json::from_value(value).unwrap();
+    assert_eq!(deserialized.root_path, options.root_path);
+    assert_eq!(deserialized.timeout, Some(Duration::from_millis(1500)));
+    // A thread pool of the caller cannot be serialized
+    let pool = rayon::ThreadPoolBuilder::new().build().unwrap();
+    let options = Scandir::new(&src_dir())
+        .unwrap()
+        .parallelism(Parallelism::Pool(pool.into()))
+        .options();
+    assert!(serde_json::to_value(&options).is_err());
+}
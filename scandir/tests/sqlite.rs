@@ -0,0 +1,148 @@
+#![cfg(feature = "sqlite")]
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, MAIN_SEPARATOR_STR};
+
+use rusqlite::Connection;
+
+use scandir::sqlite::{index_scandir, SqliteIndex, SCHEMA_VERSION};
+use scandir::{ReturnType, Scandir};
+
+mod common;
+use common::TempDir;
+
+/// Tree with 2 files of 10 bytes in each of `root`, `root/a`, `root/a/b` and `root/c`
+fn create_tree(root: &Path) {
+    for dir in ["", "a", "a/b", "c"] {
+        let dir = root.join(dir);
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["1.txt", "2.txt"] {
+            fs::write(dir.join(name), b"0123456789").unwrap();
+        }
+    }
+}
+
+fn query(conn: &Connection, sql: &str) -> i64 {
+    conn.query_row(sql, [], |row| row.get(0)).unwrap()
+}
+
+fn path(path: &str) -> String {
+    path.replace('/', MAIN_SEPARATOR_STR)
+}
+
+#[test]
+fn index_and_update() {
+    let tmp = TempDir::new("sqlite_update");
+    let root = tmp.0.join("root");
+    create_tree(&root);
+    let db_path = tmp.0.join("index.db");
+    let root_path = root.to_string_lossy().to_string();
+    let mut instance = Scandir::new(&root_path)
+        .unwrap()
+        .return_type(ReturnType::Ext)
+        .store(false);
+    assert_eq!(index_scandir(&mut instance, &db_path, 3).unwrap(), 11);
+    let conn = Connection::open(&db_path).unwrap();
+    assert_eq!(query(&conn, "PRAGMA user_version"), SCHEMA_VERSION as i64);
+    assert_eq!(query(&conn, "SELECT COUNT(*) FROM files"), 8);
+    assert_eq!(query(&conn, "SELECT COUNT(*) FROM directories"), 4);
+    assert_eq!(
+        query(
+            &conn,
+            "SELECT total_files * 10000 + total_dirs * 1000 + total_size FROM directories \
+             WHERE path = ''"
+        ),
+        8 * 10000 + 3 * 1000 + 80
+    );
+    let (files, dirs, size, total_size): (i64, i64, i64, i64) = conn
+        .query_row(
+            "SELECT files, dirs, size, total_size FROM directories WHERE path = ?1",
+            [path("a")],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .unwrap();
+    assert_eq!((files, dirs, size, total_size), (2, 1, 20, 40));
+    let (name, parent): (String, String) = conn
+        .query_row(
+            "SELECT f.name, d.path FROM files f JOIN directories d ON d.id = f.parent_id \
+             WHERE f.path = ?1",
+            [path("a/b/1.txt")],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert_eq!((name.as_str(), parent), ("1.txt", path("a/b")));
+    assert!(query(&conn, "SELECT MIN(st_mode) FROM files") > 0);
+    drop(conn);
+
+    // Update the index after changing the tree
+    fs::remove_dir_all(root.join("a/b")).unwrap();
+    fs::write(root.join("c/3.txt"), b"01234").unwrap();
+    let mut instance = Scandir::new(&root_path).unwrap().store(false);
+    assert_eq!(index_scandir(&mut instance, &db_path, 100).unwrap(), 9);
+    let conn = Connection::open(&db_path).unwrap();
+    assert_eq!(
+        query(
+            &conn,
+            "SELECT CAST(value AS INTEGER) FROM meta WHERE key = 'generation'"
+        ),
+        2
+    );
+    assert_eq!(query(&conn, "SELECT COUNT(*) FROM files"), 7);
+    assert_eq!(query(&conn, "SELECT COUNT(*) FROM directories"), 3);
+    assert_eq!(
+        query(&conn, "SELECT total_size FROM directories WHERE path = ''"),
+        65
+    );
+}
+
+#[test]
+fn open_errors() {
+    let tmp = TempDir::new("sqlite_errors");
+    create_tree(&tmp.0.join("root"));
+    let db_path = tmp.0.join("index.db");
+    let index = SqliteIndex::open(&db_path, "root").unwrap();
+    assert_eq!(index.generation(), 1);
+    assert_eq!(index.finish(true).unwrap(), 0);
+    let err = SqliteIndex::open(&db_path, "other").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    let index = SqliteIndex::open(&db_path, "root").unwrap();
+    assert_eq!(index.generation(), 2);
+    index.finish(false).unwrap();
+    Connection::open(&db_path)
+        .unwrap()
+        .pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+        .unwrap();
+    let err = SqliteIndex::open(&db_path, "root").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[cfg(unix)]
+#[test]
+fn keep_entries_after_errors() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = TempDir::new("sqlite_keep");
+    let root = tmp.0.join("root");
+    create_tree(&root);
+    let db_path = tmp.0.join("index.db");
+    let root_path = root.to_string_lossy().to_string();
+    let mut instance = Scandir::new(&root_path).unwrap().store(false);
+    index_scandir(&mut instance, &db_path, 100).unwrap();
+    let locked = root.join("a");
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+    if fs::read_dir(&locked).is_ok() {
+        // Running as root
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        return;
+    }
+    let mut instance = Scandir::new(&root_path).unwrap().store(false);
+    let result = index_scandir(&mut instance, &db_path, 100);
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+    result.unwrap();
+    // Entries below the unreadable directory are not removed
+    let conn = Connection::open(&db_path).unwrap();
+    assert_eq!(query(&conn, "SELECT COUNT(*) FROM files"), 8);
+    assert_eq!(query(&conn, "SELECT COUNT(*) FROM directories"), 4);
+    assert!(query(&conn, "SELECT COUNT(*) FROM errors") > 0);
+}